@@ -0,0 +1,39 @@
+//! Times how long a backed-up belt takes to close the gaps between many small groups, which merges
+//! each following group into the head group one removal at a time.
+//!
+//! Run with `cargo bench --bench belt_merge`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use factory_lib::logistics::{Belt, Stack};
+use factory_lib::types::ITEM_WIDTH;
+
+const GROUPS: u32 = 4096;
+const ROUNDS: u32 = 20;
+
+/// Builds a belt holding `GROUPS` single stacks of alternating types, one slot apart.
+fn gapped_belt() -> Belt {
+    let layout: Vec<(Stack, u32)> = (0..GROUPS)
+        .map(|idx| (Stack::new((idx % 2) as u16, 1), ITEM_WIDTH))
+        .collect();
+    Belt::from_layout(GROUPS * ITEM_WIDTH * 2, ITEM_WIDTH, &layout).expect("layout fits")
+}
+
+fn time_rounds(mut round: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        round();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let belt_merge = time_rounds(|| {
+        let mut belt = gapped_belt();
+        // Without an output the head stays put and every group behind it closes up.
+        belt.run(GROUPS * ITEM_WIDTH);
+        black_box(belt.item_count());
+    });
+    println!("belt: closing {GROUPS} gaps took {belt_merge:?}");
+}
@@ -0,0 +1,190 @@
+use crate::logistics::Belt;
+
+/**
+ * A load balancer that spreads the items of several input belts evenly across several output belts.
+ * Inputs are drained in round-robin order like a `Merger`. Each stack goes to the output that has
+ * received the fewest stacks so far among those that can take it, with ties broken in round-robin
+ * order. An output that was blocked is therefore favoured once it frees up, until it has caught up
+ * with the others, so over many ticks every output receives its fair share whenever capacity allows.
+ * Both round-robin positions and the per-output tallies carry over between ticks.
+ */
+#[derive(Debug, Default)]
+pub struct Balancer {
+    input_rr_index: usize,
+    output_rr_index: usize,
+    // Stacks handed to each output, relative to the least served output.
+    delivered: Vec<u64>,
+}
+
+impl Balancer {
+    pub fn new() -> Self {
+        Self {
+            input_rr_index: 0,
+            output_rr_index: 0,
+            delivered: Vec::new(),
+        }
+    }
+
+    /// Runs a single tick of the balancer, moving stacks from the heads of `inputs` to the tails of `outputs`
+    /// until no input has a stack that any output can accept. Returns how many stacks were moved.
+    /// Changing the number of outputs between ticks resets the per-output tallies.
+    pub fn run(&mut self, inputs: &mut [&mut Belt], outputs: &mut [&mut Belt]) -> u32 {
+        let input_len = inputs.len();
+        let output_len = outputs.len();
+        if self.delivered.len() != output_len {
+            self.delivered = vec![0; output_len];
+        }
+        if input_len == 0 || output_len == 0 {
+            self.input_rr_index = 0;
+            self.output_rr_index = 0;
+            return 0;
+        }
+
+        self.input_rr_index %= input_len;
+        self.output_rr_index %= output_len;
+
+        let mut moved = 0;
+        let mut progress = true;
+        while progress {
+            progress = false;
+
+            for offset in 0..input_len {
+                let idx = (self.input_rr_index + offset) % input_len;
+                let belt = &mut *inputs[idx];
+
+                let Some((stack, _)) = belt.peek_front_stack() else {
+                    continue;
+                };
+                let Some(target) = self.pick_output(outputs, |output| output.can_add(&stack))
+                else {
+                    continue;
+                };
+
+                let added = outputs[target].add_item(stack);
+                debug_assert!(added);
+                let removed = belt.remove_item();
+                debug_assert!(removed.is_some());
+
+                self.delivered[target] += 1;
+                self.output_rr_index = (target + 1) % output_len;
+                self.input_rr_index = (idx + 1) % input_len;
+                moved += 1;
+                progress = true;
+                break;
+            }
+        }
+
+        // Only the differences between outputs matter, so keep the tallies small.
+        let least = self.delivered.iter().copied().min().unwrap_or(0);
+        for delivered in self.delivered.iter_mut() {
+            *delivered -= least;
+        }
+
+        moved
+    }
+
+    // Returns the least served output that `accepts` admits, preferring the round-robin order on ties.
+    fn pick_output(&self, outputs: &[&mut Belt], accepts: impl Fn(&Belt) -> bool) -> Option<usize> {
+        let output_len = outputs.len();
+        (0..output_len)
+            .map(|offset| (self.output_rr_index + offset) % output_len)
+            .filter(|&idx| accepts(outputs[idx]))
+            .min_by_key(|&idx| self.delivered[idx])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logistics::Stack;
+    use crate::types::ITEM_WIDTH;
+
+    /// Feeds every input one stack per tick, runs the balancer, and drains every output that is not blocked.
+    /// Returns how many stacks each output received, including the ones still on it.
+    fn simulate(
+        input_count: usize,
+        output_count: usize,
+        ticks: u32,
+        blocked: impl Fn(u32, usize) -> bool,
+    ) -> Vec<usize> {
+        let mut balancer = Balancer::new();
+        let mut inputs: Vec<Belt> = (0..input_count)
+            .map(|_| Belt::new(ITEM_WIDTH * 2, ITEM_WIDTH))
+            .collect();
+        let mut outputs: Vec<Belt> = (0..output_count)
+            .map(|_| Belt::new(ITEM_WIDTH * 2, ITEM_WIDTH))
+            .collect();
+        let mut received = vec![0; output_count];
+
+        for tick in 0..ticks {
+            for input in inputs.iter_mut() {
+                input.add_item(Stack::new(1, 1));
+            }
+
+            let mut input_refs: Vec<&mut Belt> = inputs.iter_mut().collect();
+            let mut output_refs: Vec<&mut Belt> = outputs.iter_mut().collect();
+            balancer.run(&mut input_refs, &mut output_refs);
+
+            for input in inputs.iter_mut() {
+                input.run(1);
+            }
+            for (idx, output) in outputs.iter_mut().enumerate() {
+                if blocked(tick, idx) {
+                    continue;
+                }
+                output.run(1);
+                while output.remove_item().is_some() {
+                    received[idx] += 1;
+                }
+            }
+        }
+
+        for (idx, output) in outputs.iter().enumerate() {
+            received[idx] += output.item_count();
+        }
+        received
+    }
+
+    fn assert_balanced(received: &[usize]) {
+        let least = received.iter().min().unwrap();
+        let most = received.iter().max().unwrap();
+        assert!(most - least <= 1, "unbalanced outputs: {received:?}");
+    }
+
+    #[test]
+    fn balances_two_to_four() {
+        let received = simulate(2, 4, 200, |_, _| false);
+        assert_balanced(&received);
+        assert!(received.iter().sum::<usize>() > 300);
+    }
+
+    #[test]
+    fn balances_two_to_four_with_blocked_output() {
+        // Output 3 stalls for 10 ticks out of every 40, then catches up.
+        let received = simulate(2, 4, 400, |tick, idx| idx == 3 && tick % 40 < 10);
+        assert_balanced(&received);
+    }
+
+    #[test]
+    fn balances_three_to_three_with_blocked_output() {
+        // With three inputs the outputs run at full speed, so a stalled output falls behind for good;
+        // the free outputs still share evenly.
+        let received = simulate(3, 3, 300, |tick, idx| idx == 0 && tick % 30 < 5);
+        assert_balanced(&received[1..]);
+        assert!(received[0] < received[1]);
+
+        // Once the supply is lower than the outputs can carry, the stalled output catches up.
+        let received = simulate(2, 3, 300, |tick, idx| idx == 0 && tick % 30 < 5);
+        assert_balanced(&received);
+    }
+
+    #[test]
+    fn idle_without_outputs() {
+        let mut balancer = Balancer::new();
+        let mut input = Belt::new(ITEM_WIDTH, 1);
+        assert!(input.add_item(Stack::new(1, 1)));
+
+        assert_eq!(balancer.run(&mut [&mut input], &mut []), 0);
+        assert_eq!(input.item_count(), 1);
+    }
+}
@@ -1,14 +1,9 @@
-use crate::logistics::Stack;
 use crate::logistics::belt_connection::{
-    BeltInputConnection, BeltOutputConnection, Connection, OutputBatch,
+    BeltInputConnection, BeltOutputConnection, Connection, OutputBatch, transfer,
 };
+use crate::logistics::{LogisticsError, Stack};
 use crate::types::{ITEM_WIDTH, ItemType};
-use std::collections::VecDeque;
-
-// Physical width of a single stack on the belt measured in belt distance units.
-//
-// This value should remain a power of two for performance reasons when integrating with
-// simulation backends that rely on bit masking.
+use std::collections::{HashMap, VecDeque};
 
 /**
  * Represents an item on a conveyor belt. Each item keeps track of what it is carrying, if it is
@@ -18,6 +13,7 @@ use std::collections::VecDeque;
  * where items will be processed in groups, and most accesses are at the ends of the belt.
  */
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct BeltItem {
     stack: Stack,
     // distance to the next item on the belt
@@ -28,15 +24,70 @@ struct BeltItem {
     is_group_tail: bool,
     // if we are head or tail of the group, track the group size
     group_size: u32,
+    // Ticks spent on the belt while age tracking is on. Merged entries keep the oldest age.
+    #[cfg_attr(feature = "serde", serde(default))]
+    age: u32,
+}
+
+/// Summary of a single `Belt::run_status` call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// Number of stacks handed to the output connection.
+    pub items_delivered: u32,
+    /// `true` if the output connection refused stacks that had reached the belt front.
+    pub output_blocked: bool,
+    /// `true` if the belt had room for at least one stack at its back but nothing was fed in,
+    /// either because there is no input connection or because it had nothing to offer.
+    pub input_starved: bool,
+}
+
+/// Counters accumulated by `Belt::run` and `Belt::run_status` since the belt was created or
+/// `reset_metrics` was last called.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BeltMetrics {
+    /// Stacks handed to the output connection.
+    pub items_delivered: u64,
+    /// Stacks taken from the input connection.
+    pub items_fed: u64,
+    /// Ticks of runs in which the output connection refused stacks at the belt front.
+    pub ticks_blocked: u64,
+    /// Distance the belt's contents moved, measured as the space that opened at the back.
+    pub distance_moved: u64,
+    /// Ticks simulated.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ticks: u64,
+}
+
+/// One group of contiguous stacks on a belt, as reported by `Belt::groups`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupInfo {
+    /// Index of the group's first entry, counting entries from the head of the belt.
+    pub start_index: usize,
+    /// Number of entries in the group. Identical stacks folded into one entry count once.
+    pub size: u32,
+    /// Item type of the stack at the front of the group.
+    pub head_type: ItemType,
+    /// Items carried by the whole group, counting every copy of multiplied stacks.
+    pub total_items: u32,
+}
+
+#[cfg(feature = "serde")]
+fn default_item_width() -> u32 {
+    ITEM_WIDTH
 }
 
 /// Models a Satisfactory-style conveyor belt that primarily supports pushing items on the back
 /// and popping them from the front in FIFO order. Random access is intentionally deprioritized
 /// because the belt is expected to be consumed from its ends.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Belt {
     length: u32,
     speed: u32,
+    // Physical width of a single stack on the belt measured in belt distance units.
+    #[cfg_attr(feature = "serde", serde(default = "default_item_width"))]
+    item_width: u32,
     // Consider moving from VecDeque to YCQueue
     items: VecDeque<BeltItem>,
     // how many empty spaces in the queue until we hit a stack
@@ -45,21 +96,182 @@ pub struct Belt {
     empty_space_back: u32,
     input_connection: Option<BeltOutputConnection>,
     output_connection: Option<BeltInputConnection>,
+    // When set, `add_item` only accepts these item types.
+    #[cfg_attr(feature = "serde", serde(default))]
+    item_filter: Option<Vec<ItemType>>,
+    // Movement toward the output that was too short to release the head stack during the last
+    // `run`. It is added to the next run so throughput averages out to `speed`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    distance_carry: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    metrics: BeltMetrics,
+    // One bit per recent tick, newest in the lowest bit, set if the output was blocked that tick.
+    #[cfg_attr(feature = "serde", serde(default))]
+    blocked_history: u128,
+    // When set, `run` adds the ticks run to the age of every stack on the belt.
+    #[cfg_attr(feature = "serde", serde(default))]
+    track_age: bool,
+    // Largest number of belt slots a group may span. Touching groups past the cap stay separate.
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_group_size: Option<u32>,
+}
+
+// How many recent ticks `blocked_history` remembers.
+const BLOCKED_HISTORY_TICKS: u32 = u128::BITS;
+
+// Returns a mask of the lowest `ticks` bits of the blocked history.
+fn history_mask(ticks: u32) -> u128 {
+    if ticks >= BLOCKED_HISTORY_TICKS {
+        u128::MAX
+    } else {
+        (1 << ticks) - 1
+    }
+}
+
+/// Two belts are equal when they have the same configuration, the same stacks (including
+/// multiplicity) with the same spacing, and equal connections with identical buffers. The run
+/// metrics are not compared, so belts that reach the same state along different paths compare equal.
+impl PartialEq for Belt {
+    fn eq(&self, other: &Self) -> bool {
+        let same_items = self.items.len() == other.items.len()
+            && self.items.iter().zip(other.items.iter()).all(|(a, b)| {
+                a.stack.identical(&b.stack)
+                    && a.next_item_dist == b.next_item_dist
+                    && a.is_group_head == b.is_group_head
+                    && a.is_group_tail == b.is_group_tail
+                    && a.group_size == b.group_size
+                    && a.age == b.age
+            });
+
+        same_items
+            && self.length == other.length
+            && self.speed == other.speed
+            && self.item_width == other.item_width
+            && self.empty_space_front == other.empty_space_front
+            && self.empty_space_back == other.empty_space_back
+            && self.distance_carry == other.distance_carry
+            && self.item_filter == other.item_filter
+            && self.track_age == other.track_age
+            && self.max_group_size == other.max_group_size
+            && self.input_connection == other.input_connection
+            && self.output_connection == other.output_connection
+    }
 }
 
 impl Belt {
     /// Creates a belt with the provided physical `length` and movement `speed`.
     /// Initially the belt is empty, so the entire length is available as empty space.
+    /// A speed of zero gives a stopped belt, like one that is powered off: `run` never moves
+    /// its stacks or hands them to the output connection, but stacks can still be added at the
+    /// tail and removed from the head, so it works as plain storage.
     pub fn new(length: u32, speed: u32) -> Self {
+        Self::with_item_width(length, speed, ITEM_WIDTH)
+    }
+
+    /// Creates a belt whose stacks each take up `item_width` units of belt instead of the
+    /// default `ITEM_WIDTH`, e.g. to model a pipe with a different granularity.
+    /// Widths that are a power of two are cheaper for simulation backends that rely on bit masking.
+    pub fn with_item_width(length: u32, speed: u32, item_width: u32) -> Self {
+        debug_assert!(item_width > 0, "item width must be non-zero");
+
         Self {
             length,
             speed,
+            item_width,
             items: VecDeque::new(),
             empty_space_front: length,
             empty_space_back: length,
             input_connection: None,
             output_connection: None,
+            item_filter: None,
+            distance_carry: 0,
+            metrics: BeltMetrics::default(),
+            blocked_history: 0,
+            track_age: false,
+            max_group_size: None,
+        }
+    }
+
+    /// Creates a belt already holding `items`, listed from head to tail. Each entry pairs a stack
+    /// with the gap in front of it: the first gap is the empty space at the belt's front, the
+    /// others the space after the previous stack. Stacks may carry a multiplicity to place
+    /// several identical copies back to back. Returns `None` if a stack is empty or the layout
+    /// does not fit on the belt.
+    pub fn from_layout(length: u32, speed: u32, items: &[(Stack, u32)]) -> Option<Self> {
+        let mut belt = Self::new(length, speed);
+        let mut used: u64 = 0;
+        for (stack, gap) in items {
+            if stack.is_empty() || stack.multiplicity == 0 {
+                return None;
+            }
+            used += *gap as u64 + stack.multiplicity as u64 * belt.item_width as u64;
+        }
+        if used > length as u64 {
+            return None;
         }
+
+        belt.set_layout(items, std::iter::repeat(0));
+        Some(belt)
+    }
+
+    /// Replaces the belt contents with `items`, laid out as described by `from_layout`, giving
+    /// the entries the ages yielded by `ages` in order. The caller must ensure the layout fits.
+    fn set_layout(&mut self, items: &[(Stack, u32)], ages: impl Iterator<Item = u32>) {
+        let Some(((_, front_gap), rest)) = items.split_first() else {
+            self.items.clear();
+            self.empty_space_front = self.length;
+            self.empty_space_back = self.length;
+            return;
+        };
+
+        let used: u32 = items
+            .iter()
+            .map(|(stack, gap)| gap + stack.multiplicity * self.item_width)
+            .sum();
+        debug_assert!(used <= self.length);
+        self.empty_space_front = *front_gap;
+        self.empty_space_back = self.length - used;
+        let next_gaps = rest.iter().map(|(_, gap)| Some(*gap)).chain([None]);
+        self.items = items
+            .iter()
+            .zip(next_gaps)
+            .zip(ages)
+            .map(|(((stack, _), next_item_dist), age)| BeltItem {
+                stack: stack.clone(),
+                next_item_dist,
+                group_size: 1,
+                is_group_head: true,
+                is_group_tail: true,
+                age,
+            })
+            .collect();
+        self.relink_groups();
+    }
+
+    /// Returns the physical length of the belt.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Returns how far the belt advances per tick.
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    /// Returns how much of the belt's length is covered by stacks, counting every copy of
+    /// multiplied stacks.
+    pub fn occupied_length(&self) -> u32 {
+        self.items
+            .iter()
+            .map(|item| item.stack.multiplicity * self.item_width)
+            .sum()
+    }
+
+    /// Changes how far the belt advances per tick. Items keep their current positions; the new
+    /// speed applies from the next `run` or `remove_while_run` call onwards. Setting it to zero
+    /// stops the belt, see `new`.
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed;
     }
 
     /// Attaches an input connection to the back of the belt. Passing `None` detaches the
@@ -94,48 +306,267 @@ impl Belt {
         self.output_connection.as_mut()
     }
 
+    /// Detaches both connections and returns them as `(input, output)`, buffers intact, so
+    /// buffered items are not lost when the belt is torn down.
+    pub fn take_connections(
+        &mut self,
+    ) -> (Option<BeltOutputConnection>, Option<BeltInputConnection>) {
+        (self.input_connection.take(), self.output_connection.take())
+    }
+
+    /// Caps how many belt slots a group of touching stacks may span, like the segments of a real
+    /// belt. When stacks close up behind a full group, or are fed in or added next to one, they
+    /// start a new group right beside it instead of extending it. Groups already on the belt are
+    /// cut to the new cap. `None` lets groups grow without bound.
+    pub fn set_max_group_size(&mut self, max_group_size: Option<u32>) {
+        debug_assert!(max_group_size != Some(0), "group size cap must be non-zero");
+        self.max_group_size = max_group_size;
+        self.rebuild_group_metadata();
+    }
+
+    /// Returns how many belt slots a group may span, or `None` if groups are unbounded.
+    pub fn max_group_size(&self) -> Option<u32> {
+        self.max_group_size
+    }
+
+    // Returns true if a group spanning `slots` belt slots stays within `max_group_size`.
+    fn group_fits(&self, slots: u32) -> bool {
+        self.max_group_size.is_none_or(|cap| slots <= cap)
+    }
+
+    // Returns how many belt slots the group headed by the entry at `start` spans.
+    fn group_slots(&self, start: usize) -> u32 {
+        let size = self.items[start].group_size as usize;
+        (start..start + size)
+            .map(|idx| self.items[idx].stack.multiplicity)
+            .sum()
+    }
+
+    /// Restricts which item types `add_item` and `add_stack_run` accept. `None` accepts every
+    /// type. Stacks fed in by the input connection are governed by that connection's own filter.
+    pub fn set_item_filter(&mut self, item_filter: Option<Vec<ItemType>>) {
+        self.item_filter = item_filter;
+    }
+
+    /// Returns the item types this belt accepts, or `None` if it accepts every type.
+    pub fn item_filter(&self) -> Option<&[ItemType]> {
+        self.item_filter.as_deref()
+    }
+
+    /// Returns `true` if the belt's item filter lets `item_type` on.
+    pub fn accepts_item_type(&self, item_type: ItemType) -> bool {
+        self.item_filter
+            .as_ref()
+            .is_none_or(|filter| filter.contains(&item_type))
+    }
+
+    /// Returns how many whole stack slots are free at the back of the belt.
+    pub fn free_slots(&self) -> u32 {
+        self.empty_space_back / self.item_width
+    }
+
+    /// Returns `true` if `add_item` would accept `stack` right now.
+    pub fn can_add(&self, stack: &Stack) -> bool {
+        stack.multiplicity == 1
+            && !stack.is_empty()
+            && self.empty_space_back >= self.item_width
+            && self.accepts_item_type(stack.item_type)
+    }
+
     /// Adds an item to the back of the belt without advancing the belt.
-    /// Returns `false` if there is no trailing space left for another stack.
+    /// Returns `false` if there is no trailing space left for another stack, the stack is empty,
+    /// or the belt's item filter rejects it. Use `try_add_item` to find out which.
     pub fn add_item(&mut self, stack: Stack) -> bool {
+        self.try_add_item(stack).is_ok()
+    }
+
+    /// Like `add_item`, but reports why the stack was refused.
+    pub fn try_add_item(&mut self, stack: Stack) -> Result<(), LogisticsError> {
         if stack.multiplicity != 1 {
+            return Err(LogisticsError::MultiplicityUnsupported);
+        } else if stack.is_empty() {
+            return Err(LogisticsError::InvalidStackSize);
+        } else if !self.accepts_item_type(stack.item_type) {
+            return Err(LogisticsError::FilterRejected);
+        } else if self.empty_space_back < self.item_width {
+            return Err(LogisticsError::NoSpace);
+        }
+
+        self.push_back_run(stack);
+        Ok(())
+    }
+
+    /// Like `add_item`, but only places the stack if it can keep at least `min_gap` of empty belt
+    /// between itself and the current tail, so with a non-zero `min_gap` it never joins the tail
+    /// group. A `min_gap` of 0 behaves like `add_item`. Returns `false` without placing anything
+    /// if the trailing space is shorter than the stack plus `min_gap`.
+    pub fn add_item_with_gap(&mut self, stack: Stack, min_gap: u32) -> bool {
+        if self.empty_space_back < self.item_width.saturating_add(min_gap) {
             return false;
         }
 
-        if self.empty_space_back < ITEM_WIDTH {
+        self.add_item(stack)
+    }
+
+    /// Places a single stack at the very front of the belt, right at the output, without
+    /// advancing the belt. The rest of the front gap is kept between the new stack and the old
+    /// head; if there is none left, the new stack becomes the head of the front group, or starts
+    /// a group of its own if the front group already spans `max_group_size` slots. Returns
+    /// `false` if the front gap is narrower than a stack, the stack is empty or has a multiplicity
+    /// above 1, or the belt's item filter rejects it.
+    pub fn prepend_item(&mut self, stack: Stack) -> bool {
+        if !(stack.multiplicity == 1
+            && !stack.is_empty()
+            && self.empty_space_front >= self.item_width
+            && self.accepts_item_type(stack.item_type))
+        {
             return false;
         }
 
+        let gap = self.empty_space_front - self.item_width;
+        self.empty_space_front = 0;
+        if self.items.is_empty() {
+            // On an empty belt the whole length was both gaps; the stack now sits at the front.
+            self.empty_space_back -= self.item_width;
+            self.items.push_back(BeltItem {
+                stack,
+                next_item_dist: None,
+                group_size: 1,
+                is_group_head: true,
+                is_group_tail: true,
+                age: 0,
+            });
+            return true;
+        }
+
+        if gap > 0 || !self.group_fits(self.group_slots(0) + 1) {
+            self.items.push_front(BeltItem {
+                stack,
+                next_item_dist: Some(gap),
+                group_size: 1,
+                is_group_head: true,
+                is_group_tail: true,
+                age: 0,
+            });
+            return true;
+        }
+
+        let head = &mut self.items[0];
+        if head.stack == stack {
+            // Identical copies touching the head fold into its multiplicity.
+            head.stack.multiplicity += 1;
+            return true;
+        }
+
+        // The new stack joins the front group as its head, so the group's head and tail both
+        // record the larger size.
+        let group_size = head.group_size + 1;
+        head.is_group_head = false;
+        self.items.push_front(BeltItem {
+            stack,
+            next_item_dist: Some(0),
+            group_size,
+            is_group_head: true,
+            is_group_tail: false,
+            age: 0,
+        });
+        self.items[group_size as usize - 1].group_size = group_size;
+        true
+    }
+
+    /// Adds up to `stack.multiplicity` copies of `stack` contiguously to the back of the belt
+    /// without advancing the belt, merging into the tail group where they touch it and it has
+    /// room under `max_group_size`. Returns how many copies fit in the trailing space.
+    pub fn add_stack_run(&mut self, mut stack: Stack) -> u32 {
+        if stack.is_empty() || !self.accepts_item_type(stack.item_type) {
+            return 0;
+        }
+
+        let placed = stack.multiplicity.min(self.free_slots());
+        if placed == 0 {
+            return 0;
+        }
+
+        stack.multiplicity = placed;
+        self.push_back_run(stack);
+        placed
+    }
+
+    /// Like `add_stack_run`, but hands back the copies that did not fit as a stack with the
+    /// remaining multiplicity, or `None` if every copy was placed. A stack the belt cannot take at
+    /// all is returned unchanged.
+    pub fn add_item_saturating(&mut self, mut stack: Stack) -> Option<Stack> {
+        let placed = self.add_stack_run(stack.clone());
+        stack.multiplicity -= placed;
+        (stack.multiplicity > 0).then_some(stack)
+    }
+
+    /// Places `stack` (all of its multiplicity) flush against the back of the belt.
+    /// The caller must ensure there is enough trailing space for the whole run.
+    fn push_back_run(&mut self, mut stack: Stack) {
+        let span = stack.multiplicity * self.item_width;
+        debug_assert!(stack.multiplicity > 0);
+        debug_assert!(self.empty_space_back >= span);
+
+        let mut spacing = self.empty_space_back - span;
+        if self.items.is_empty() {
+            debug_assert!(self.empty_space_front >= span);
+            self.empty_space_front -= span;
+        }
+        self.empty_space_back = 0;
+
+        if let Some(cap) = self.max_group_size {
+            // Split the run so each piece fills what is left of the tail group, then whole groups.
+            loop {
+                let room = match self.items.len().checked_sub(1) {
+                    Some(tail) if spacing == 0 => {
+                        let head = tail + 1 - self.items[tail].group_size as usize;
+                        cap.saturating_sub(self.group_slots(head))
+                    }
+                    _ => cap,
+                };
+                let piece = if room == 0 { cap } else { room };
+                if stack.multiplicity <= piece {
+                    break;
+                }
+
+                let mut split = stack.clone();
+                split.multiplicity = piece;
+                stack.multiplicity -= piece;
+                self.push_back_entry(split, spacing);
+                spacing = 0;
+            }
+        }
+
+        self.push_back_entry(stack, spacing);
+    }
+
+    // Appends `stack` `spacing` behind the current tail, joining the tail group when it touches
+    // it and the group has room left.
+    fn push_back_entry(&mut self, stack: Stack, spacing: u32) {
         let mut is_group_head = true;
         let mut group_size = 1;
-        match self.items.back_mut() {
-            Some(item) => {
-                debug_assert_eq!(item.next_item_dist, None);
-                debug_assert!(item.is_group_tail);
-                let spacing = self.empty_space_back - ITEM_WIDTH;
-                if spacing == 0 && item.stack == stack {
-                    item.stack.multiplicity += stack.multiplicity;
-                    self.empty_space_back = 0;
-                    return true;
-                }
-                item.next_item_dist = Some(spacing);
-                // check if we are extending a group
-                if spacing == 0 {
-                    item.is_group_tail = false;
-                    is_group_head = false;
-                    group_size = item.group_size + 1;
-
-                    // now update the group head's record of group size
-                    // We work backwards from the previous tail (current len - group_size) to reach the group head.
-                    // Adding 1 accounts for the item we just pushed to the queue.
-                    let group_head_index = 1 + self.items.len() - group_size as usize;
-                    self.items[group_head_index].group_size = group_size;
-                }
-                self.empty_space_back = 0;
+        if let Some(tail_group_size) = self.items.back().map(|item| item.group_size) {
+            let group_head_index = self.items.len() - tail_group_size as usize;
+            let joins = spacing == 0
+                && self.group_fits(self.group_slots(group_head_index) + stack.multiplicity);
+            let item = self.items.back_mut().expect("belt has a tail");
+            debug_assert_eq!(item.next_item_dist, None);
+            debug_assert!(item.is_group_tail);
+            if joins && item.stack == stack {
+                item.stack.multiplicity += stack.multiplicity;
+                return;
             }
-            None => {
-                debug_assert!(self.empty_space_front >= ITEM_WIDTH);
-                self.empty_space_front -= ITEM_WIDTH;
-                self.empty_space_back = 0;
+
+            item.next_item_dist = Some(spacing);
+            // check if we are extending a group
+            if joins {
+                item.is_group_tail = false;
+                is_group_head = false;
+                group_size = tail_group_size + 1;
+
+                // now update the group head's record of group size
+                self.items[group_head_index].group_size = group_size;
             }
         }
 
@@ -145,15 +576,21 @@ impl Belt {
             group_size,
             is_group_head,
             is_group_tail: true,
+            age: 0,
         });
-
-        true
     }
 
     /// Removes and returns the next item that reached the front without simulating belt movement.
     /// The call fails with `None` if the belt currently has leading empty space and no stack at the head.
     pub fn remove_item(&mut self) -> Option<Stack> {
-        if self.empty_space_front > 0 {
+        self.remove_front_run(1)
+    }
+
+    /// Removes up to `max_copies` of the identical stacks at the head of the belt at once, leaving
+    /// a gap where they were. The returned stack's multiplicity is the number of copies removed.
+    /// Like `remove_item`, fails with `None` if there is leading empty space or `max_copies` is 0.
+    pub fn remove_front_run(&mut self, max_copies: u32) -> Option<Stack> {
+        if self.empty_space_front > 0 || max_copies == 0 {
             return None;
         }
 
@@ -161,11 +598,15 @@ impl Belt {
         debug_assert!(front_item.is_group_head);
         debug_assert!(front_item.stack.multiplicity > 0);
         let mut stack = front_item.stack.clone();
-        stack.multiplicity = 1;
-        front_item.stack.multiplicity -= 1;
-        self.empty_space_front = ITEM_WIDTH;
+        stack.multiplicity = max_copies.min(front_item.stack.multiplicity);
+        front_item.stack.multiplicity -= stack.multiplicity;
+        self.empty_space_front = stack.multiplicity * self.item_width;
         if front_item.stack.multiplicity == 0 {
             self.pop_front_entry(true).unwrap();
+            if !self.items.is_empty() {
+                // pop_front_entry only accounts for the last copy's slot.
+                self.empty_space_front += (stack.multiplicity - 1) * self.item_width;
+            }
         }
         Some(stack)
     }
@@ -186,6 +627,22 @@ impl Belt {
         Some((stack, multiplicity))
     }
 
+    /// Returns the stack at the belt's tail without removing it. The stack keeps its
+    /// multiplicity. Returns `None` on an empty belt regardless of `empty_space_back`.
+    pub fn peek_back_stack(&self) -> Option<&Stack> {
+        self.items.back().map(|item| &item.stack)
+    }
+
+    /// Returns the item type of the entry that will be nearest the output after `ticks` more ticks, or `None`
+    /// if the belt would be empty by then. Stacks leave through the output connection just as they would
+    /// with `run_ticks`, so this predicts what an inserter at the head will see. The belt itself is not
+    /// touched; the lookahead runs on a clone, so its cost grows with the number of entries and ticks.
+    pub fn peek_after_ticks(&self, ticks: u32) -> Option<ItemType> {
+        let mut future = self.clone();
+        future.run_ticks(ticks);
+        future.items.front().map(|item| item.stack.item_type)
+    }
+
     /// Advances the belt by `ticks` and returns every stack that would leave the belt in that time.
     /// This consumes the simulated distance by first closing front gaps and then popping
     /// complete items.
@@ -196,80 +653,94 @@ impl Belt {
         items_filter: Option<&[ItemType]>,
         total_items_limit: Option<u32>,
     ) -> Vec<Stack> {
-        let mut distance_to_move = ticks * self.speed;
-        let mut removed_items = Vec::new();
+        self.drain_while_run(ticks, items_filter, total_items_limit)
+            .collect()
+    }
 
-        let mut total_removed: u32 = 0;
-        // Consume the run distance by first skipping empty front space, then pulling full items.
-        while distance_to_move > 0 {
-            if self.empty_space_front > 0 {
-                if distance_to_move < self.empty_space_front {
-                    self.empty_space_front -= distance_to_move;
-                    self.empty_space_back += distance_to_move;
-                    break;
-                }
+    /// Works like `remove_while_run`, but returns how many items of each type left the belt
+    /// instead of the stacks themselves.
+    pub fn remove_while_run_grouped(
+        &mut self,
+        ticks: u32,
+        items_filter: Option<&[ItemType]>,
+        total_items_limit: Option<u32>,
+    ) -> HashMap<ItemType, u32> {
+        let mut delivered = HashMap::new();
+        for stack in self.drain_while_run(ticks, items_filter, total_items_limit) {
+            *delivered.entry(stack.item_type).or_insert(0) +=
+                stack.item_count as u32 * stack.multiplicity;
+        }
+        delivered
+    }
 
-                distance_to_move -= self.empty_space_front;
-                self.empty_space_back += self.empty_space_front;
-                self.empty_space_front = 0;
-                continue;
-            }
+    /// Advances the belt by `ticks` like `remove_while_run`, but only stacks whose type is in
+    /// `filter` leave the belt, and they do so even when a non-matching stack is ahead of them.
+    /// Every matching stack that would pass the belt front within the run distance is pulled
+    /// out; the gaps they leave are closed up as the remaining stacks move forward. Removed
+    /// stacks are returned from head to tail.
+    pub fn remove_matching(&mut self, ticks: u32, filter: &[ItemType]) -> Vec<Stack> {
+        let distance = ticks * self.speed;
+        let mut removed = Vec::new();
+        let mut kept: Vec<(Stack, u32)> = Vec::with_capacity(self.items.len());
+        let mut kept_ages = Vec::with_capacity(self.items.len());
+        // Position of the current entry's front, measured from the belt front.
+        let mut position = self.empty_space_front;
+        let mut gap = self.empty_space_front;
 
-            let Some(front_snapshot) = self.items.front() else {
-                break;
+        for item in self.items.iter() {
+            let multiplicity = item.stack.multiplicity;
+            let removable = if filter.contains(&item.stack.item_type) {
+                let passing = distance.saturating_sub(position) / self.item_width;
+                passing.min(multiplicity)
+            } else {
+                0
             };
 
-            if let Some(filter) = items_filter
-                && !filter.contains(&front_snapshot.stack.item_type)
-            {
-                break;
-            }
-
-            let multiplicity = front_snapshot.stack.multiplicity;
-            debug_assert!(multiplicity > 0);
-            let max_by_distance = distance_to_move / ITEM_WIDTH;
-            if max_by_distance == 0 {
-                self.empty_space_front = distance_to_move;
-                self.empty_space_back += distance_to_move;
-                break;
+            if removable > 0 {
+                let mut stack = item.stack.clone();
+                stack.multiplicity = removable;
+                removed.push(stack);
+                gap += removable * self.item_width;
             }
-
-            let removable = max_by_distance.min(multiplicity);
-            let mut stack = front_snapshot.stack.clone();
-            stack.multiplicity = removable;
-
-            removed_items.push(stack);
-            distance_to_move -= removable * ITEM_WIDTH;
-            self.empty_space_back += removable * ITEM_WIDTH;
-
             if removable < multiplicity {
-                if let Some(front_item) = self.items.front_mut() {
-                    front_item.stack.multiplicity -= removable;
-                }
-                if distance_to_move > 0 {
-                    self.empty_space_front = distance_to_move;
-                    self.empty_space_back += distance_to_move;
-                    distance_to_move = 0;
-                } else {
-                    self.empty_space_front = 0;
-                }
-            } else if let Some(removed_item) = self.pop_front_entry(true) {
-                self.empty_space_front = match removed_item.next_item_dist {
-                    Some(offset) => offset,
-                    None => self.length,
-                };
+                let mut stack = item.stack.clone();
+                stack.multiplicity = multiplicity - removable;
+                kept.push((stack, gap));
+                kept_ages.push(item.age);
+                gap = 0;
             }
 
-            // respect the total items limit if provided
-            if let Some(limit) = total_items_limit {
-                total_removed += removable;
-                if total_removed >= limit {
-                    break;
-                }
-            }
+            let next_item_dist = item.next_item_dist.unwrap_or(0);
+            position += multiplicity * self.item_width + next_item_dist;
+            gap += next_item_dist;
+        }
+
+        if !removed.is_empty() {
+            self.set_layout(&kept, kept_ages.into_iter());
         }
+        self.advance_without_connections(distance);
+        removed
+    }
 
-        removed_items
+    /// Lazy version of `remove_while_run`. The returned iterator advances the belt as it is
+    /// driven and yields each stack as it leaves the front, so no intermediate `Vec` is built.
+    /// The belt only moves as far as the iterator has been consumed: dropping it early leaves
+    /// the remaining distance unspent.
+    pub fn drain_while_run<'a>(
+        &'a mut self,
+        ticks: u32,
+        items_filter: Option<&'a [ItemType]>,
+        total_items_limit: Option<u32>,
+    ) -> DrainWhileRun<'a> {
+        let distance_to_move = ticks * self.speed;
+        DrainWhileRun {
+            belt: self,
+            distance_to_move,
+            items_filter,
+            total_items_limit,
+            total_removed: 0,
+            done: false,
+        }
     }
 
     fn pop_front_entry(&mut self, update_back_space: bool) -> Option<BeltItem> {
@@ -281,10 +752,15 @@ impl Belt {
             // Promotion logic: the next physical item becomes the new group head and inherits the shrunk group size.
             next_item.is_group_head = true;
             next_item.group_size = item.group_size - 1;
+
+            // The group tail tracks the size as well.
+            let tail_index = item.group_size as usize - 2;
+            debug_assert!(self.items[tail_index].is_group_tail);
+            self.items[tail_index].group_size = item.group_size - 1;
         }
 
         self.empty_space_front = match item.next_item_dist {
-            Some(offset) => offset + ITEM_WIDTH,
+            Some(offset) => offset + self.item_width,
             None => self.length,
         };
 
@@ -296,21 +772,138 @@ impl Belt {
         Some(item)
     }
 
+    /// Moves the belt's contents forward by `distance` in a single step, closing gaps and merging
+    /// groups exactly as `run` would, but without touching the connections or the metrics. With
+    /// no connections attached this matches running tick by tick over the same distance, which
+    /// makes it a cheap way to settle belts on load.
+    pub fn fast_forward(&mut self, distance: u32) {
+        self.advance_without_connections(distance);
+    }
+
     /// Runs the belt forward for `ticks`, compacting item groups while coordinating with
     /// attached connections. Returns `None` to mirror other APIs while updating internal state.
+    /// Use `run_status` to find out what happened during the run.
     pub fn run(&mut self, ticks: u32) -> Option<()> {
+        self.run_status(ticks);
+        None
+    }
+
+    /// Runs the belt for `ticks` ticks, servicing the connections once per tick. Unlike `run(ticks)`,
+    /// which moves the whole distance in one pass, this is observably the same as calling `run(1)`
+    /// `ticks` times. Without any connections every tick reduces to plain movement, so the whole
+    /// distance is covered in a single advance.
+    pub fn run_ticks(&mut self, ticks: u32) {
+        if self.input_connection.is_none() && self.output_connection.is_none() {
+            self.run_status(ticks);
+            return;
+        }
+
+        for _ in 0..ticks {
+            self.run_status(1);
+        }
+    }
+
+    /// Returns the counters accumulated by `run` and `run_status`.
+    pub fn metrics(&self) -> &BeltMetrics {
+        &self.metrics
+    }
+
+    /// Returns how many stacks per tick the belt carries when packed: `speed / item_width`.
+    pub fn max_throughput_per_tick(&self) -> u32 {
+        self.speed / self.item_width
+    }
+
+    /// Predicts how many stacks the belt holds at equilibrium when fed `input_per_tick` stacks and
+    /// drained of up to `output_per_tick` stacks every tick. If the input outpaces the drain the
+    /// belt backs up until it is full. Otherwise every stack spends `length / speed` ticks on the
+    /// belt, so the occupancy is the input rate times that transit time, clamped to how many
+    /// stacks fit on the belt. A stopped belt fills up under any input.
+    pub fn steady_state_occupancy(&self, input_per_tick: u32, output_per_tick: u32) -> u32 {
+        let capacity = self.length / self.item_width;
+        if input_per_tick == 0 {
+            return 0;
+        }
+        if output_per_tick < input_per_tick || self.speed == 0 {
+            return capacity;
+        }
+
+        let in_transit = input_per_tick as u64 * self.length as u64 / self.speed as u64;
+        in_transit.min(capacity as u64) as u32
+    }
+
+    /// Returns the stacks delivered per tick, averaged over the metrics window. Reports 0.0 before
+    /// any tick has run.
+    pub fn measured_throughput(&self) -> f32 {
+        if self.metrics.ticks == 0 {
+            return 0.0;
+        }
+
+        self.metrics.items_delivered as f32 / self.metrics.ticks as f32
+    }
+
+    /// Returns the fraction of the last `window_ticks` ticks in which the output connection refused
+    /// stacks at the belt front, from 0.0 to 1.0. Only the last 128 ticks are remembered, so longer
+    /// windows are shortened to that, and ticks before the last `reset_metrics` do not count.
+    pub fn congestion_score(&self, window_ticks: u32) -> f32 {
+        let window = window_ticks
+            .min(BLOCKED_HISTORY_TICKS)
+            .min(self.metrics.ticks.min(BLOCKED_HISTORY_TICKS as u64) as u32);
+        if window == 0 {
+            return 0.0;
+        }
+
+        let blocked = (self.blocked_history & history_mask(window)).count_ones();
+        blocked as f32 / window as f32
+    }
+
+    /// Zeroes the `BeltMetrics` counters and forgets the blocked-tick history, so
+    /// `congestion_score` starts over from 0.0 as well.
+    pub fn reset_metrics(&mut self) {
+        self.metrics = BeltMetrics::default();
+        self.blocked_history = 0;
+    }
+
+    // Shifts `ticks` ticks into the blocked history, all marked with `blocked`.
+    fn record_blocked(&mut self, ticks: u32, blocked: bool) {
+        self.blocked_history = self.blocked_history.checked_shl(ticks).unwrap_or(0);
+        if blocked {
+            self.blocked_history |= history_mask(ticks);
+        }
+    }
+
+    /// Runs the belt forward for `ticks` exactly like `run`, and reports how many stacks were
+    /// delivered and whether either end of the belt stalled.
+    pub fn run_status(&mut self, ticks: u32) -> RunOutcome {
         // The main belt update loop has three phases:
         //   1. Hand the front of the belt to the output connection while distance and output connection allows.
         //   2. Advance any remaining belt distance locally, merging adjacent groups.
         //   3. Feed new stacks from the input connection into the space that opened up.
-        let total_distance = ticks * self.speed;
+        // Distance carried over from the previous run counts toward releasing the head stack.
+        // A stopped belt keeps it until it starts moving again.
+        let stopped = self.speed == 0;
+        let total_distance = if stopped {
+            0
+        } else {
+            ticks * self.speed + std::mem::take(&mut self.distance_carry)
+        };
+        if self.track_age {
+            for item in self.items.iter_mut() {
+                item.age = item.age.saturating_add(ticks);
+            }
+        }
 
+        let mut outcome = RunOutcome::default();
+        let back_space_before = self.empty_space_back;
         let mut distance_remaining = total_distance;
         let mut output_connection = self.output_connection.take();
 
-        if let Some(connection) = output_connection.as_mut() {
-            let (consumed, blocked) = self.drain_to_output(distance_remaining, connection);
+        // A stopped belt would otherwise flush its head stack like a zero-distance run does.
+        if let Some(connection) = output_connection.as_mut().filter(|_| !stopped) {
+            let (consumed, delivered, blocked) =
+                self.drain_to_output(distance_remaining, connection);
             distance_remaining = distance_remaining.saturating_sub(consumed);
+            outcome.items_delivered = delivered;
+            outcome.output_blocked = blocked;
 
             if blocked && distance_remaining > 0 {
                 self.advance_without_connections(distance_remaining);
@@ -327,20 +920,40 @@ impl Belt {
         let total_back_space = self.empty_space_back;
         self.empty_space_back = 0;
 
-        self.apply_input_connection(total_back_space);
+        let fed = self.apply_input_connection(total_back_space);
+        outcome.input_starved = total_back_space >= self.item_width && fed == 0;
 
-        None
+        self.metrics.ticks += ticks as u64;
+        self.metrics.items_delivered += outcome.items_delivered as u64;
+        self.metrics.items_fed += fed as u64;
+        if outcome.output_blocked {
+            self.metrics.ticks_blocked += ticks as u64;
+        }
+        self.record_blocked(ticks, outcome.output_blocked);
+        self.metrics.distance_moved += total_back_space.saturating_sub(back_space_before) as u64;
+
+        outcome
     }
 
+    /// Hands stacks at the front of the belt to `connection`. Returns the distance consumed,
+    /// the number of stacks delivered, and whether the connection refused further stacks.
     fn drain_to_output(
         &mut self,
         mut distance_to_move: u32,
         connection: &mut BeltInputConnection,
-    ) -> (u32, bool) {
+    ) -> (u32, u32, bool) {
         let mut consumed = 0u32;
+        let mut delivered = 0u32;
         let mut blocked = false;
+        // A run without any movement flushes whatever already sits at the output. Otherwise
+        // every stack has to travel its full width to leave.
+        let flush = distance_to_move == 0;
 
         loop {
+            if self.items.is_empty() {
+                break;
+            }
+
             // Phase 1: spend movement closing any leading gap before we can present a stack.
             if self.empty_space_front > 0 && distance_to_move > 0 {
                 if distance_to_move < self.empty_space_front {
@@ -368,16 +981,17 @@ impl Belt {
                 multiplicity: 1,
             };
 
-            let allow_immediate = distance_to_move == 0 && self.empty_space_front == 0;
+            let allow_immediate = flush && self.empty_space_front == 0;
             let max_by_distance = if allow_immediate {
                 multiplicity
             } else {
-                distance_to_move / ITEM_WIDTH
+                distance_to_move / self.item_width
             };
 
             if max_by_distance == 0 && !allow_immediate {
-                self.empty_space_front = distance_to_move;
-                self.empty_space_back += distance_to_move;
+                // The head stack is at the output but not far enough along to leave; keep the
+                // progress for the next run instead of losing it.
+                self.distance_carry = distance_to_move;
                 consumed += distance_to_move;
                 break;
             }
@@ -389,11 +1003,12 @@ impl Belt {
                 break;
             }
 
-            let removable = if allow_immediate {
-                multiplicity.min(max_by_connection)
+            let max_by_belt = if allow_immediate {
+                multiplicity
             } else {
-                max_by_distance.min(multiplicity).min(max_by_connection)
+                max_by_distance.min(multiplicity)
             };
+            let removable = max_by_belt.min(max_by_connection);
 
             if removable == 0 {
                 blocked = true;
@@ -411,9 +1026,10 @@ impl Belt {
                 break;
             }
 
-            let moved = removable * ITEM_WIDTH;
+            let moved = removable * self.item_width;
             self.empty_space_back += moved;
             consumed += moved;
+            delivered += removable;
             distance_to_move = distance_to_move.saturating_sub(moved);
 
             if removable < multiplicity {
@@ -421,24 +1037,28 @@ impl Belt {
                     front_item.stack.multiplicity -= removable;
                 }
 
-                // When distance remains after partially removing a stack, the leftover
-                // distance turns into a leading gap that will be consumed.
-                if distance_to_move > 0 {
-                    self.empty_space_front = distance_to_move;
-                    self.empty_space_back += distance_to_move;
+                // The remaining stacks stay pressed against the output. If the connection is
+                // full, the leftover distance is left for the caller to spend compacting the rest
+                // of the belt. Otherwise it is shorter than a stack and carries over to the next run.
+                blocked = max_by_connection < max_by_belt;
+                if !blocked {
+                    self.distance_carry = distance_to_move;
                     consumed += distance_to_move;
                 }
-
                 break;
             }
 
-            self.pop_front_entry(false);
+            // The removed stacks already paid for their distance above, so the next entry sits
+            // exactly its recorded gap away from the output.
+            let popped = self.pop_front_entry(false);
             if self.items.is_empty() {
                 self.empty_space_back = self.length;
+            } else if let Some(offset) = popped.and_then(|item| item.next_item_dist) {
+                self.empty_space_front = offset;
             }
         }
 
-        (consumed, blocked)
+        (consumed, delivered, blocked)
     }
 
     fn advance_without_connections(&mut self, mut distance_to_move: u32) {
@@ -465,7 +1085,9 @@ impl Belt {
             return;
         }
 
-        let group_start = 0usize;
+        // The group the closing gaps pile up behind. It only moves back when a full group
+        // refuses to absorb the next one.
+        let mut group_start = 0usize;
 
         while distance_to_move > 0 && group_start < self.items.len() {
             let group_size = self.items[group_start].group_size;
@@ -500,14 +1122,25 @@ impl Belt {
             let next_group_tail = next_group_start + (next_group_size as usize - 1);
             let tail_next_dist = self.items[next_group_tail].next_item_dist;
 
+            if !self.group_fits(self.group_slots(group_start) + self.group_slots(next_group_start))
+            {
+                // The groups touch but stay separate; keep closing gaps behind the next one.
+                self.items[group_tail_index].next_item_dist = Some(0);
+                group_start = next_group_start;
+                continue;
+            }
+
+            // Multiplicity stands for identical copies, so only stacks with equal counts merge.
             let should_merge =
                 self.items[group_tail_index].stack == self.items[next_group_start].stack;
 
             if should_merge {
                 // Same stack type: fold the next group into the existing multiplicity.
                 let addition = self.items[next_group_start].stack.multiplicity;
+                let addition_age = self.items[next_group_start].age;
                 if let Some(tail) = self.items.get_mut(group_tail_index) {
                     tail.stack.multiplicity += addition;
+                    tail.age = tail.age.max(addition_age);
                 }
 
                 let remaining = next_group_size - 1;
@@ -564,33 +1197,41 @@ impl Belt {
         }
     }
 
-    fn apply_input_connection(&mut self, total_space: u32) {
+    /// Fills `total_space` at the back of the belt from the input connection and returns how
+    /// many stacks were fed in.
+    fn apply_input_connection(&mut self, total_space: u32) -> u32 {
         let mut input_connection = self.input_connection.take();
+        let mut fed = 0;
+        let was_empty = self.items.is_empty();
 
         if let Some(connection) = input_connection.as_mut() {
-            let available_slots = total_space / ITEM_WIDTH;
-            let leftover_units = total_space % ITEM_WIDTH;
+            let available_slots = total_space / self.item_width;
+            let leftover_units = total_space % self.item_width;
 
             let mut leftover_space = leftover_units;
-            if available_slots > 0 {
-                // Pull a batch from the connection sized to the free slots; any unused slots
-                // convert back into empty trailing space.
-                if let Some(batch) = connection.take_output_batch(available_slots) {
-                    let used_slots = batch.num_stacks();
-                    self.append_output_batch(batch);
-                    let unused_slots = available_slots.saturating_sub(used_slots);
-                    leftover_space += unused_slots * ITEM_WIDTH;
-                } else {
-                    leftover_space += available_slots * ITEM_WIDTH;
-                }
+            // Only ask for slots the connection can fill; any unused slots convert back into
+            // empty trailing space.
+            let requested_slots = available_slots.min(connection.deliverable_slots());
+            if let Some(batch) = connection.take_output_batch(requested_slots) {
+                fed = batch.num_stacks();
+                debug_assert_eq!(fed, requested_slots);
+                self.append_output_batch(batch);
             }
+            leftover_space += (available_slots - fed) * self.item_width;
 
-            self.empty_space_back += leftover_space;
+            if was_empty && fed > 0 {
+                // The batch entered at the input end of an empty belt, so the unused space
+                // lies ahead of it.
+                self.empty_space_front = leftover_space;
+            } else {
+                self.empty_space_back += leftover_space;
+            }
         } else {
             self.empty_space_back += total_space;
         }
 
         self.input_connection = input_connection;
+        fed
     }
 
     fn append_output_batch(&mut self, batch: OutputBatch) {
@@ -605,22 +1246,64 @@ impl Belt {
         }
     }
 
-    fn append_stack_from_connection(&mut self, stack: Stack) {
+    fn append_stack_from_connection(&mut self, mut stack: Stack) {
+        if let Some(cap) = self.max_group_size {
+            // Split the run so each piece fills what is left of the tail group, then whole groups.
+            loop {
+                let room = match self.items.len().checked_sub(1) {
+                    Some(tail) => {
+                        let head = tail + 1 - self.items[tail].group_size as usize;
+                        cap.saturating_sub(self.group_slots(head))
+                    }
+                    None => cap,
+                };
+                let piece = if room == 0 { cap } else { room };
+                if stack.multiplicity <= piece {
+                    break;
+                }
+
+                let mut split = stack.clone();
+                split.multiplicity = piece;
+                stack.multiplicity -= piece;
+                self.append_entry_from_connection(split);
+            }
+        }
+
+        self.append_entry_from_connection(stack);
+    }
+
+    fn append_entry_from_connection(&mut self, stack: Stack) {
         if self.items.is_empty() {
-            // Empty belt: drop the incoming stack directly at the head position.
-            let occupied = stack.multiplicity * ITEM_WIDTH;
-            self.empty_space_front = self.empty_space_front.saturating_sub(occupied);
+            // Empty belt: the incoming stack becomes the head. The caller settles the leading
+            // gap once the whole batch has been placed.
             self.items.push_back(BeltItem {
                 stack,
                 next_item_dist: None,
                 group_size: 1,
                 is_group_head: true,
                 is_group_tail: true,
+                age: 0,
             });
             return;
         }
 
         let tail_group_size = self.items.back().map(|item| item.group_size).unwrap_or(1);
+        let tail_group_head = self.items.len() - tail_group_size as usize;
+        if !self.group_fits(self.group_slots(tail_group_head) + stack.multiplicity) {
+            // The tail group is full, so the stack starts a new group right behind it.
+            if let Some(tail) = self.items.back_mut() {
+                tail.next_item_dist = Some(0);
+            }
+            self.items.push_back(BeltItem {
+                stack,
+                next_item_dist: None,
+                group_size: 1,
+                is_group_head: true,
+                is_group_tail: true,
+                age: 0,
+            });
+            return;
+        }
 
         if let Some(tail) = self.items.back_mut() {
             // Keep the existing group contiguous; this new stack slides in immediately after.
@@ -643,129 +1326,2186 @@ impl Belt {
             group_size: new_group_size,
             is_group_head: false,
             is_group_tail: true,
+            age: 0,
+        });
+    }
+
+    /// Splices `other` onto the back of this belt, so `other` becomes the upstream part of one
+    /// longer belt. The seam is where this belt's input end meets `other`'s output end, so the
+    /// splice fails and hands `other` back untouched if this belt has a `BeltOutputConnection`
+    /// feeding it or `other` has a `BeltInputConnection` draining it. The merged belt keeps this
+    /// belt's speed and output connection and takes over `other`'s input connection. Belts with
+    /// different item widths cannot be spliced. Identical stacks that touch across the seam merge
+    /// into a single entry.
+    #[allow(clippy::result_large_err)]
+    pub fn append_belt(&mut self, mut other: Belt) -> Result<(), Belt> {
+        if self.input_connection.is_some()
+            || other.output_connection.is_some()
+            || self.item_width != other.item_width
+        {
+            return Err(other);
+        }
+
+        match (self.items.back_mut(), other.items.is_empty()) {
+            (Some(tail), false) => {
+                tail.next_item_dist = Some(self.empty_space_back + other.empty_space_front);
+                self.empty_space_back = other.empty_space_back;
+            }
+            (Some(_), true) => {
+                self.empty_space_back += other.length;
+            }
+            (None, false) => {
+                self.empty_space_front = self.length + other.empty_space_front;
+                self.empty_space_back = other.empty_space_back;
+            }
+            (None, true) => {
+                self.empty_space_front += other.length;
+                self.empty_space_back += other.length;
+            }
+        }
+
+        self.length += other.length;
+        self.items.append(&mut other.items);
+        self.input_connection = other.input_connection.take();
+        self.relink_groups();
+
+        Ok(())
+    }
+
+    /// Cuts the belt `distance` units from its output end. This belt keeps the head half along
+    /// with its output connection, and the returned belt holds everything upstream of the cut
+    /// along with the input connection. Both halves keep this belt's speed, item filter, group
+    /// size cap, and age tracking setting. An entry that straddles the cut stays on the head
+    /// side; the cut then moves back to the end of that entry so no stack hangs off either belt.
+    pub fn split_at(&mut self, distance: u32) -> Belt {
+        let distance = distance.min(self.length);
+
+        // Find the first entry whose head lies past the cut, tracking where the kept entries end.
+        let mut keep = 0;
+        let mut head_end = 0;
+        let mut pos = self.empty_space_front;
+        for item in self.items.iter() {
+            if pos >= distance {
+                break;
+            }
+
+            pos += item.stack.multiplicity * self.item_width;
+            head_end = pos;
+            keep += 1;
+            pos += item.next_item_dist.unwrap_or(0);
+        }
+        let cut = distance.max(head_end);
+
+        let mut tail = Belt::with_item_width(self.length - cut, self.speed, self.item_width);
+        tail.item_filter = self.item_filter.clone();
+        tail.max_group_size = self.max_group_size;
+        tail.track_age = self.track_age;
+        tail.items = self.items.split_off(keep);
+        if !tail.items.is_empty() {
+            tail.empty_space_front = pos - cut;
+            tail.empty_space_back = self.empty_space_back;
+        }
+        tail.input_connection = self.input_connection.take();
+
+        match self.items.back_mut() {
+            Some(item) => {
+                item.next_item_dist = None;
+                self.empty_space_back = cut - head_end;
+            }
+            None => {
+                self.empty_space_front = cut;
+                self.empty_space_back = cut;
+            }
+        }
+
+        self.length = cut;
+        self.relink_groups();
+        tail.relink_groups();
+
+        tail
+    }
+
+    /// Changes the belt length at its input end. Growing adds empty space behind the tail.
+    /// Shrinking first removes empty space behind the tail; stacks that no longer fit are taken
+    /// off the belt and returned in head-to-tail order as `Err`, after the belt has been resized.
+    /// An entry of several identical copies only loses the copies that hang off the end.
+    pub fn resize(&mut self, new_length: u32) -> Result<(), Vec<Stack>> {
+        if self.items.is_empty() {
+            self.length = new_length;
+            self.empty_space_front = new_length;
+            self.empty_space_back = new_length;
+            return Ok(());
+        }
+
+        if new_length >= self.length || self.length - new_length <= self.empty_space_back {
+            self.empty_space_back = self.empty_space_back + new_length - self.length;
+            self.length = new_length;
+            return Ok(());
+        }
+
+        let mut evicted = Vec::new();
+        // Back edge of the current tail entry.
+        let mut end = self.length - self.empty_space_back;
+        while end > new_length {
+            let item_width = self.item_width;
+            let Some(tail) = self.items.back_mut() else {
+                break;
+            };
+            let start = end - tail.stack.multiplicity * item_width;
+            let fitting = new_length.saturating_sub(start) / item_width;
+            if fitting > 0 {
+                evicted.push(Stack {
+                    multiplicity: tail.stack.multiplicity - fitting,
+                    ..tail.stack.clone()
+                });
+                tail.stack.multiplicity = fitting;
+                end = start + fitting * item_width;
+                break;
+            }
+
+            let tail = self.items.pop_back().expect("tail entry exists");
+            evicted.push(tail.stack);
+            match self.items.back_mut() {
+                Some(previous) => {
+                    end = start - previous.next_item_dist.take().unwrap_or(0);
+                }
+                None => end = 0,
+            }
+        }
+        evicted.reverse();
+
+        self.length = new_length;
+        if self.items.is_empty() {
+            self.empty_space_front = new_length;
+            self.empty_space_back = new_length;
+        } else {
+            self.empty_space_back = new_length - end;
+        }
+        self.relink_groups();
+        Err(evicted)
+    }
+
+    /// Flips the direction of the belt: the tail becomes the head and every gap keeps its size
+    /// but ends up on the mirrored side. The connections swap ends as well, keeping their
+    /// buffers. The new feeding connection reuses the old feeder's output stack size, or emits
+    /// single-item stacks if the belt had no feeder.
+    pub fn reverse(&mut self) {
+        let gaps: Vec<u32> = self
+            .items
+            .iter()
+            .filter_map(|item| item.next_item_dist)
+            .collect();
+        self.items.make_contiguous().reverse();
+        let last = self.items.len().saturating_sub(1);
+        for (idx, item) in self.items.iter_mut().enumerate() {
+            item.next_item_dist = if idx < last {
+                Some(gaps[last - 1 - idx])
+            } else {
+                None
+            };
+        }
+        std::mem::swap(&mut self.empty_space_front, &mut self.empty_space_back);
+
+        let old_input = self.input_connection.take();
+        let output_stack_size = old_input
+            .as_ref()
+            .map(|connection| connection.output_stack_size())
+            .unwrap_or(1);
+        self.input_connection = self
+            .output_connection
+            .take()
+            .map(|connection| connection.into_output_connection(output_stack_size));
+        self.output_connection = old_input.map(|connection| connection.into_input_connection());
+
+        self.relink_groups();
+    }
+
+    /// Closes every gap between stacks without moving the head stack, so all stacks form one
+    /// contiguous group behind the unchanged `empty_space_front`. The freed space moves to the
+    /// back of the belt and adjacent identical stacks merge.
+    pub fn compact(&mut self) {
+        let Some(tail) = self.items.len().checked_sub(1) else {
+            return;
+        };
+
+        for item in self.items.range_mut(..tail) {
+            let gap = item.next_item_dist.replace(0).unwrap_or(0);
+            self.empty_space_back += gap;
+        }
+
+        self.relink_groups();
+    }
+
+    /// Rebuilds the grouping metadata from the recorded item spacing. Identical stacks with no
+    /// gap between them are folded into one entry, mirroring the merge rules of
+    /// `advance_without_connections`, and groups are cut wherever they would pass `max_group_size`.
+    fn relink_groups(&mut self) {
+        let mut idx = 0;
+        while idx + 1 < self.items.len() {
+            if self.items[idx].next_item_dist == Some(0)
+                && self.items[idx].stack == self.items[idx + 1].stack
+                && self.group_fits(
+                    self.items[idx].stack.multiplicity + self.items[idx + 1].stack.multiplicity,
+                )
+            {
+                let next = self.items.remove(idx + 1).expect("index checked above");
+                let item = &mut self.items[idx];
+                item.stack.multiplicity += next.stack.multiplicity;
+                item.next_item_dist = next.next_item_dist;
+                item.age = item.age.max(next.age);
+            } else {
+                idx += 1;
+            }
+        }
+
+        self.rebuild_group_metadata();
+    }
+
+    // Recomputes every entry's group head, tail, and size from the spacing between entries,
+    // cutting groups wherever they would pass `max_group_size`. Entries are never merged.
+    fn rebuild_group_metadata(&mut self) {
+        let mut group_start = 0;
+        while group_start < self.items.len() {
+            let mut group_tail = group_start;
+            let mut slots = self.items[group_start].stack.multiplicity;
+            while group_tail + 1 < self.items.len()
+                && self.items[group_tail].next_item_dist == Some(0)
+                && self.group_fits(slots + self.items[group_tail + 1].stack.multiplicity)
+            {
+                group_tail += 1;
+                slots += self.items[group_tail].stack.multiplicity;
+            }
+
+            let group_size = (group_tail - group_start + 1) as u32;
+            for idx in group_start..=group_tail {
+                let item = &mut self.items[idx];
+                item.group_size = group_size;
+                item.is_group_head = idx == group_start;
+                item.is_group_tail = idx == group_tail;
+            }
+            group_start = group_tail + 1;
+        }
+    }
+
+    /// Rebuilds the grouping metadata of every entry from the spacing between them, the way the
+    /// belt lays out groups itself, and returns `true` if anything had to be corrected. Use this
+    /// to recover a belt whose group heads, tails, or sizes were corrupted, e.g. one deserialized
+    /// from hand-edited data. Entries, positions, and gaps are left as they are; touching
+    /// identical entries are not folded together, since valid belts may hold them side by side.
+    pub fn repair(&mut self) -> bool {
+        // Only heads and tails are required to carry an accurate group size.
+        let metadata = |belt: &Belt| -> Vec<(bool, bool, Option<u32>)> {
+            belt.items
+                .iter()
+                .map(|item| {
+                    let edge = item.is_group_head || item.is_group_tail;
+                    (
+                        item.is_group_head,
+                        item.is_group_tail,
+                        edge.then_some(item.group_size),
+                    )
+                })
+                .collect()
+        };
+
+        let before = metadata(self);
+        self.rebuild_group_metadata();
+        metadata(self) != before
+    }
+
+    /// Turns age tracking on or off. While it is on, every `run` adds the ticks run to the age of
+    /// each stack on the belt, so `remove_expired` can find perishable stacks. Stacks start at age
+    /// 0 when placed, and identical stacks merged into one entry share the age of the oldest.
+    /// Tracking is off by default since it visits every entry on each run.
+    pub fn set_track_age(&mut self, track_age: bool) {
+        self.track_age = track_age;
+    }
+
+    /// Returns `true` if `run` ages the stacks on the belt.
+    pub fn tracks_age(&self) -> bool {
+        self.track_age
+    }
+
+    /// Removes every stack older than `max_age` ticks and returns them in head-to-tail order.
+    /// The space they held stays empty, so the stacks behind keep their positions.
+    pub fn remove_expired(&mut self, max_age: u32) -> Vec<Stack> {
+        if self.items.iter().all(|item| item.age <= max_age) {
+            return Vec::new();
+        }
+
+        let mut removed = Vec::new();
+        let mut kept: VecDeque<BeltItem> = VecDeque::with_capacity(self.items.len());
+        // Empty space in front of the next kept entry.
+        let mut gap = self.empty_space_front;
+        for mut item in self.items.drain(..) {
+            let next_item_dist = item.next_item_dist.unwrap_or(0);
+            if item.age > max_age {
+                gap += item.stack.multiplicity * self.item_width + next_item_dist;
+                removed.push(item.stack);
+                continue;
+            }
+
+            match kept.back_mut() {
+                Some(previous) => previous.next_item_dist = Some(gap),
+                None => self.empty_space_front = gap,
+            }
+            gap = next_item_dist;
+            item.next_item_dist = None;
+            kept.push_back(item);
+        }
+
+        if kept.is_empty() {
+            self.empty_space_front = self.length;
+            self.empty_space_back = self.length;
+        } else {
+            self.empty_space_back += gap;
+        }
+        self.items = kept;
+        self.relink_groups();
+        removed
+    }
+
+    /// Removes every stack from the belt and returns them in head-to-tail order. Identical
+    /// contiguous stacks are returned as one entry with their multiplicity intact. Attached
+    /// connections and their buffers are left untouched.
+    pub fn clear(&mut self) -> Vec<Stack> {
+        let removed = self.items.drain(..).map(|item| item.stack).collect();
+        self.empty_space_front = self.length;
+        self.empty_space_back = self.length;
+        self.distance_carry = 0;
+        removed
+    }
+
+    /// Returns `true` when the belt contains no stacks.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns `true` when the belt is packed end to end: no space at either end and no gaps
+    /// between stacks, so nothing can be added until the front moves.
+    pub fn is_saturated(&self) -> bool {
+        !self.items.is_empty()
+            && self.empty_space_front == 0
+            && self.empty_space_back == 0
+            && self
+                .items
+                .iter()
+                .all(|item| item.next_item_dist.is_none_or(|dist| dist == 0))
+    }
+
+    /// Iterates over the belt contents from head to tail without modifying the belt.
+    /// Each entry yields its stack and the distance to the next entry, measured in belt units
+    /// (the gap between the back of this entry and the front of the next). A distance of
+    /// `Some(0)` means the next entry belongs to the same group, while `None` marks the tail
+    /// entry. An entry occupies `stack.multiplicity * self.item_width` units of belt.
+    pub fn iter(&self) -> impl Iterator<Item = (&Stack, Option<u32>)> {
+        self.items
+            .iter()
+            .map(|item| (&item.stack, item.next_item_dist))
+    }
+
+    /// Returns the empty distance between the belt output and the head stack.
+    pub fn front_gap(&self) -> u32 {
+        self.empty_space_front
+    }
+
+    /// Returns the distance from the belt output to the leading edge of the `n`th entry
+    /// yielded by `iter`, or `None` if there is no such entry.
+    pub fn distance_to_output(&self, n: usize) -> Option<u32> {
+        if n >= self.items.len() {
+            return None;
+        }
+
+        let distance = self
+            .items
+            .iter()
+            .take(n)
+            .fold(self.empty_space_front, |acc, item| {
+                acc + item.stack.multiplicity * self.item_width + item.next_item_dist.unwrap_or(0)
+            });
+        Some(distance)
+    }
+
+    /// Returns the number of stack entries currently tracked on the belt.
+    pub fn item_count(&self) -> usize {
+        self.items
+            .iter()
+            .map(|item| item.stack.multiplicity as usize)
+            .sum()
+    }
+
+    /// Returns `item_count` plus whatever the attached connections are holding in their buffers.
+    /// Connection buffers are not split into stacks, so they contribute their raw item counts.
+    pub fn item_count_including_connections(&self) -> usize {
+        let input_buffered = self
+            .input_connection
+            .as_ref()
+            .map(|connection| connection.buffered_item_count() as usize)
+            .unwrap_or(0);
+        let output_buffered = self
+            .output_connection
+            .as_ref()
+            .map(|connection| connection.buffered_item_count() as usize)
+            .unwrap_or(0);
+
+        self.item_count() + input_buffered + output_buffered
+    }
+
+    /// Returns how many stacks of `item_type` are currently on the belt.
+    pub fn count_of(&self, item_type: ItemType) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.stack.item_type == item_type)
+            .map(|item| item.stack.multiplicity as usize)
+            .sum()
+    }
+
+    /// Iterates over the groups of contiguous stacks from the head of the belt to the tail.
+    pub fn groups(&self) -> impl Iterator<Item = GroupInfo> + '_ {
+        let mut start_index = 0;
+        std::iter::from_fn(move || {
+            let head = self.items.get(start_index)?;
+            debug_assert!(head.is_group_head);
+            let size = head.group_size;
+            let total_items = (start_index..start_index + size as usize)
+                .map(|idx| &self.items[idx].stack)
+                .map(|stack| stack.item_count as u32 * stack.multiplicity)
+                .sum();
+
+            let info = GroupInfo {
+                start_index,
+                size,
+                head_type: head.stack.item_type,
+                total_items,
+            };
+            start_index += size as usize;
+            Some(info)
+        })
+    }
+
+    /// Returns the number of stacks on the belt for every item type present.
+    pub fn type_histogram(&self) -> HashMap<ItemType, usize> {
+        let mut histogram = HashMap::new();
+        for item in self.items.iter() {
+            *histogram.entry(item.stack.item_type).or_insert(0) += item.stack.multiplicity as usize;
+        }
+        histogram
+    }
+
+    /// Test-only view of the belt's internal entries from head to tail. Each tuple is
+    /// `(stack, is_group_head, is_group_tail, group_size, next_item_dist)`, with stacks keeping
+    /// their multiplicity. The tuple layout is stable so downstream tests can assert belt
+    /// structure; it is only compiled for this crate's tests or with the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn layout_snapshot(&self) -> Vec<(Stack, bool, bool, u32, Option<u32>)> {
+        self.items
+            .iter()
+            .map(|item| {
+                (
+                    item.stack.clone(),
+                    item.is_group_head,
+                    item.is_group_tail,
+                    item.group_size,
+                    item.next_item_dist,
+                )
+            })
+            .collect()
+    }
+
+    /// Draws the belt as one character per `item_width` slot, with the output end on the left.
+    /// Empty slots are `.` and occupied slots show a symbol for their item type: the id cycles
+    /// through `0-9`, `a-z` and `A-Z`, so distinct types may share a symbol past 62 ids. A stack
+    /// that does not start on a slot boundary is drawn in the slot nearest its leading edge.
+    pub fn render_ascii(&self) -> String {
+        const SYMBOLS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+        let slots = (self.length / self.item_width) as usize;
+        let mut rendered = vec![b'.'; slots];
+        let mut position = self.empty_space_front;
+        for item in self.items.iter() {
+            let symbol = SYMBOLS[item.stack.item_type.id() as usize % SYMBOLS.len()];
+            for _ in 0..item.stack.multiplicity {
+                let slot = ((position + self.item_width / 2) / self.item_width) as usize;
+                if let Some(cell) = rendered.get_mut(slot.min(slots.saturating_sub(1))) {
+                    *cell = symbol;
+                }
+                position += self.item_width;
+            }
+            position += item.next_item_dist.unwrap_or(0);
+        }
+
+        String::from_utf8(rendered).expect("symbols are ASCII")
+    }
+
+    #[cfg(debug_assertions)]
+    /// Verifies the internal invariants of the belt, panicking in debug builds when something is inconsistent.
+    pub fn sanity_check(&self) {
+        debug_assert!(self.empty_space_front <= self.length);
+        debug_assert!(self.empty_space_back <= self.length);
+        let occupied_length = self.items.iter().fold(0u32, |acc, item| {
+            acc + item.stack.multiplicity * self.item_width
         });
+        debug_assert!(occupied_length <= self.length);
+
+        if self.items.is_empty() {
+            debug_assert_eq!(self.empty_space_front, self.length);
+            debug_assert_eq!(self.empty_space_back, self.length);
+            return;
+        }
+
+        debug_assert!(self.empty_space_front + self.empty_space_back <= self.length);
+
+        let mut cur_pos = self.empty_space_front;
+        for item in self.items.iter() {
+            cur_pos += item.stack.multiplicity * self.item_width;
+            if let Some(distance) = item.next_item_dist {
+                cur_pos += distance;
+            } else {
+                debug_assert_eq!(self.length - cur_pos, self.empty_space_back);
+            }
+            debug_assert!(cur_pos <= self.length);
+        }
+
+        debug_assert_eq!(cur_pos + self.empty_space_back, self.length);
+
+        // Groups run from a head to the next tail, only across touching entries.
+        let mut group_head = 0;
+        for (idx, item) in self.items.iter().enumerate() {
+            debug_assert_eq!(item.is_group_head, idx == group_head);
+            if item.next_item_dist != Some(0) {
+                debug_assert!(item.is_group_tail);
+            }
+            if item.is_group_tail {
+                let group_size = (idx - group_head + 1) as u32;
+                debug_assert_eq!(self.items[group_head].group_size, group_size);
+                debug_assert_eq!(item.group_size, group_size);
+                // Only a single entry wider than the cap may span more slots than it allows.
+                debug_assert!(group_size == 1 || self.group_fits(self.group_slots(group_head)));
+                group_head = idx + 1;
+            }
+        }
+        debug_assert_eq!(group_head, self.items.len());
+    }
+}
+
+/// Runs a line of belts for `ticks` ticks, tick by tick. `belts` must be listed from the input end
+/// of the line to its output end, each belt's output connection feeding the input connection of
+/// the next one. Every tick the belts run from the output end backwards: each belt first pulls
+/// what the belt before it delivered into its own input connection and then runs, so downstream
+/// buffers are drained before the upstream belt tries to hand off into them. Seams where either
+/// connection is missing move nothing.
+pub fn run_chain(belts: &mut [&mut Belt], ticks: u32) {
+    for _ in 0..ticks {
+        for index in (0..belts.len()).rev() {
+            if index > 0 {
+                let (upstream, downstream) = belts.split_at_mut(index);
+                let upstream = &mut *upstream[index - 1];
+                let downstream = &mut *downstream[0];
+                if let (Some(from), Some(to)) = (
+                    upstream.output_connection_mut(),
+                    downstream.input_connection_mut(),
+                ) {
+                    transfer(from, to, u32::MAX);
+                }
+            }
+            belts[index].run(1);
+        }
+    }
+}
+
+/// Iterator returned by `Belt::drain_while_run`. Each call to `next` spends belt distance until
+/// the next stack leaves the front, mirroring the loop in `remove_while_run`.
+#[derive(Debug)]
+pub struct DrainWhileRun<'a> {
+    belt: &'a mut Belt,
+    distance_to_move: u32,
+    items_filter: Option<&'a [ItemType]>,
+    total_items_limit: Option<u32>,
+    total_removed: u32,
+    done: bool,
+}
+
+impl Iterator for DrainWhileRun<'_> {
+    type Item = Stack;
+
+    fn next(&mut self) -> Option<Stack> {
+        if self.done {
+            return None;
+        }
+
+        let belt = &mut *self.belt;
+        // Consume the run distance by first skipping empty front space, then pulling full items.
+        while self.distance_to_move > 0 {
+            if belt.empty_space_front > 0 {
+                if self.distance_to_move < belt.empty_space_front {
+                    belt.empty_space_front -= self.distance_to_move;
+                    belt.empty_space_back += self.distance_to_move;
+                    break;
+                }
+
+                self.distance_to_move -= belt.empty_space_front;
+                belt.empty_space_back += belt.empty_space_front;
+                belt.empty_space_front = 0;
+                continue;
+            }
+
+            let Some(front_snapshot) = belt.items.front() else {
+                break;
+            };
+
+            if let Some(filter) = self.items_filter
+                && !filter.contains(&front_snapshot.stack.item_type)
+            {
+                break;
+            }
+
+            let multiplicity = front_snapshot.stack.multiplicity;
+            debug_assert!(multiplicity > 0);
+            let max_by_distance = self.distance_to_move / belt.item_width;
+            if max_by_distance == 0 {
+                belt.empty_space_front = self.distance_to_move;
+                belt.empty_space_back += self.distance_to_move;
+                break;
+            }
+
+            let removable = max_by_distance.min(multiplicity);
+            let mut stack = front_snapshot.stack.clone();
+            stack.multiplicity = removable;
+
+            self.distance_to_move -= removable * belt.item_width;
+            belt.empty_space_back += removable * belt.item_width;
+
+            if removable < multiplicity {
+                if let Some(front_item) = belt.items.front_mut() {
+                    front_item.stack.multiplicity -= removable;
+                }
+                if self.distance_to_move > 0 {
+                    belt.empty_space_front = self.distance_to_move;
+                    belt.empty_space_back += self.distance_to_move;
+                    self.distance_to_move = 0;
+                } else {
+                    belt.empty_space_front = 0;
+                }
+            } else if let Some(removed_item) = belt.pop_front_entry(true) {
+                belt.empty_space_front = match removed_item.next_item_dist {
+                    Some(offset) => offset,
+                    None => belt.length,
+                };
+            }
+
+            // respect the total items limit if provided
+            if let Some(limit) = self.total_items_limit {
+                self.total_removed += removable;
+                if self.total_removed >= limit {
+                    self.done = true;
+                }
+            }
+
+            return Some(stack);
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_stack(id: u16) -> Stack {
+        Stack::new(id, 1)
+    }
+
+    fn belt_with_slots(slots: u32, speed: u32) -> Belt {
+        Belt::new(slots * ITEM_WIDTH, speed)
+    }
+
+    fn slot_distance(slots: u32) -> u32 {
+        slots * ITEM_WIDTH
+    }
+
+    fn ticks_for_distance(belt: &Belt, distance: u32) -> u32 {
+        assert!(belt.speed > 0, "a stopped belt never covers any distance");
+        if distance == 0 {
+            0
+        } else {
+            distance.div_ceil(belt.speed)
+        }
+    }
+
+    fn run_distance(belt: &mut Belt, distance: u32) {
+        let ticks = ticks_for_distance(belt, distance);
+        if ticks > 0 {
+            belt.run(ticks);
+        }
+    }
+
+    #[test]
+    fn add_run_remove_single_item() {
+        let mut belt = belt_with_slots(5, 1);
+        // Start: empty length-5 belt (speed 1) awaiting a single stack insertion.
+
+        assert!(belt.add_item(sample_stack(42)));
+        belt.sanity_check();
+
+        assert_eq!(belt.item_count(), 1);
+        assert_eq!(belt.empty_space_front, belt.length - ITEM_WIDTH);
+        assert_eq!(belt.empty_space_back, 0);
+
+        let head = belt.items.front().expect("item present");
+        assert_eq!(head.stack, sample_stack(42));
+        assert!(head.is_group_head);
+        assert!(head.is_group_tail);
+        assert_eq!(head.group_size, 1);
+        assert_eq!(head.next_item_dist, None);
+
+        let steps_to_front = belt.length - ITEM_WIDTH;
+        run_distance(&mut belt, steps_to_front);
+        belt.sanity_check();
+        assert_eq!(belt.empty_space_front, 0);
+
+        let removed = belt.remove_item();
+        assert_eq!(removed, Some(sample_stack(42)));
+        assert!(belt.is_empty());
+        assert_eq!(belt.item_count(), 0);
+        assert_eq!(belt.empty_space_front, belt.length);
+        assert_eq!(belt.empty_space_back, belt.length);
+    }
+
+    #[test]
+    fn add_item_rejects_multiplicity_stacks() {
+        let mut belt = belt_with_slots(3, 1);
+        let mut stack = sample_stack(7);
+        stack.multiplicity = 2;
+
+        assert!(!belt.add_item(stack));
+        assert!(belt.is_empty());
+        assert_eq!(belt.empty_space_front, belt.length);
+        assert_eq!(belt.empty_space_back, belt.length);
+    }
+
+    #[test]
+    fn add_item_rejects_empty_stacks() {
+        let mut belt = belt_with_slots(3, 1);
+
+        assert!(!belt.add_item(Stack::new(7, 0)));
+        assert_eq!(belt.add_stack_run(Stack::new(7, 0)), 0);
+        assert!(belt.is_empty());
+    }
+
+    #[test]
+    fn peek_front_stack_on_empty_belt() {
+        let belt = belt_with_slots(3, 1);
+        assert_eq!(belt.peek_front_stack(), None);
+    }
+
+    #[test]
+    fn peek_front_stack_requires_closed_front_gap() {
+        let mut belt = belt_with_slots(4, 1);
+        assert!(belt.add_item(sample_stack(5)));
+        assert!(belt.empty_space_front > 0);
+
+        assert_eq!(belt.peek_front_stack(), None);
+        assert_eq!(belt.remove_item(), None);
+
+        let to_front = belt.empty_space_front;
+        run_distance(&mut belt, to_front);
+        let (stack, multiplicity) = belt.peek_front_stack().expect("stack at front");
+        assert_eq!(stack, sample_stack(5));
+        assert_eq!(multiplicity, 1);
+    }
+
+    #[test]
+    fn peek_front_stack_matches_merged_head() {
+        let mut belt = belt_with_slots(6, 1);
+        let stack = sample_stack(31);
+
+        assert!(belt.add_item(stack.clone()));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(stack.clone()));
+        belt.run(belt.length);
+
+        let front_before = belt.empty_space_front;
+        let back_before = belt.empty_space_back;
+        let (peeked, multiplicity) = belt.peek_front_stack().expect("merged head at front");
+        assert_eq!(peeked.multiplicity, 1);
+        assert_eq!(multiplicity, 2);
+
+        // Peeking must not disturb the belt.
+        assert_eq!(belt.empty_space_front, front_before);
+        assert_eq!(belt.empty_space_back, back_before);
+        assert_eq!(belt.items.front().unwrap().stack.multiplicity, 2);
+
+        let removed = belt.remove_item().expect("head removable");
+        assert_eq!(removed, peeked);
+        assert_eq!(removed.multiplicity, peeked.multiplicity);
+    }
+
+    #[test]
+    fn iter_reports_stacks_and_spacing() {
+        let mut belt = belt_with_slots(8, 1);
+        assert_eq!(belt.iter().count(), 0);
+
+        // group of two, then a lone item two slots behind
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(3));
+        assert!(belt.add_item(sample_stack(3)));
+
+        let front_before = belt.empty_space_front;
+        let back_before = belt.empty_space_back;
+        let layout: Vec<_> = belt
+            .iter()
+            .map(|(stack, dist)| (stack.clone(), dist))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                (sample_stack(1), Some(0)),
+                (sample_stack(2), Some(slot_distance(2))),
+                (sample_stack(3), None),
+            ]
+        );
+        assert_eq!(belt.empty_space_front, front_before);
+        assert_eq!(belt.empty_space_back, back_before);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_preserves_layout_and_connections() {
+        let mut belt = belt_with_slots(8, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(2));
+        assert!(belt.add_item(sample_stack(3)));
+
+        let mut input = BeltOutputConnection::new(10, 2, Some(vec![ItemType::new(4)]));
+        assert!(input.accept_stack(&Stack::new(4, 3)));
+        belt.set_input_connection(Some(input));
+        belt.set_output_connection(Some(BeltInputConnection::new(5, None)));
+
+        let json = serde_json::to_string(&belt).expect("belt serializes");
+        let mut restored: Belt = serde_json::from_str(&json).expect("belt deserializes");
+        restored.sanity_check();
+
+        assert_eq!(restored.length, belt.length);
+        assert_eq!(restored.speed, belt.speed);
+        assert_eq!(restored.empty_space_front, belt.empty_space_front);
+        assert_eq!(restored.empty_space_back, belt.empty_space_back);
+        assert!(restored.iter().eq(belt.iter()));
+        for (restored_item, item) in restored.items.iter().zip(belt.items.iter()) {
+            assert_eq!(restored_item.is_group_head, item.is_group_head);
+            assert_eq!(restored_item.is_group_tail, item.is_group_tail);
+            assert_eq!(restored_item.group_size, item.group_size);
+        }
+
+        let restored_input = restored.input_connection().expect("input restored");
+        assert_eq!(restored_input.output_stack_size(), 2);
+        assert_eq!(restored_input.item_filter(), Some(&[ItemType::new(4)][..]));
+        assert_eq!(restored_input.buffered_item_count(), 3);
+        assert_eq!(restored.output_connection().unwrap().item_limit(), 5);
+
+        // The restored belt keeps simulating like the original.
+        let drained = belt.remove_while_run(slot_distance(6), None, None);
+        assert_eq!(
+            restored.remove_while_run(slot_distance(6), None, None),
+            drained
+        );
+        restored.sanity_check();
+        assert!(restored.iter().eq(belt.iter()));
+        assert!(restored.add_item(sample_stack(9)) == belt.add_item(sample_stack(9)));
+        restored.sanity_check();
+    }
+
+    #[test]
+    fn append_belt_preserves_spacing() {
+        let mut downstream = belt_with_slots(4, 1);
+        assert!(downstream.add_item(sample_stack(1)));
+        run_distance(&mut downstream, slot_distance(2));
+
+        let mut upstream = belt_with_slots(4, 1);
+        assert!(upstream.add_item(sample_stack(2)));
+        run_distance(&mut upstream, slot_distance(1));
+        assert!(upstream.add_item(sample_stack(3)));
+
+        let expected_gap = downstream.empty_space_back + upstream.empty_space_front;
+        assert!(downstream.append_belt(upstream).is_ok());
+        downstream.sanity_check();
+
+        assert_eq!(downstream.length, slot_distance(8));
+        assert_eq!(downstream.item_count(), 3);
+        let layout: Vec<_> = downstream
+            .iter()
+            .map(|(stack, dist)| (stack.clone(), dist))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                (sample_stack(1), Some(expected_gap)),
+                (sample_stack(2), Some(0)),
+                (sample_stack(3), None),
+            ]
+        );
+        assert!(downstream.items[1].is_group_head);
+        assert_eq!(downstream.items[1].group_size, 2);
+        assert!(downstream.items[2].is_group_tail);
+
+        // Items keep flowing across the former seam.
+        let drained = downstream.remove_while_run(downstream.length, None, None);
+        assert_eq!(
+            drained,
+            vec![sample_stack(1), sample_stack(2), sample_stack(3)]
+        );
+    }
+
+    #[test]
+    fn append_belt_merges_identical_stacks_at_seam() {
+        let stack = sample_stack(8);
+        let mut downstream = belt_with_slots(2, 1);
+        assert!(downstream.add_item(stack.clone()));
+
+        let mut upstream = belt_with_slots(3, 1);
+        assert!(upstream.add_item(stack.clone()));
+        upstream.run(upstream.length);
+        assert_eq!(upstream.empty_space_front, 0);
+
+        assert!(downstream.append_belt(upstream).is_ok());
+        downstream.sanity_check();
+
+        assert_eq!(downstream.items.len(), 1);
+        let head = downstream.items.front().unwrap();
+        assert_eq!(head.stack.multiplicity, 2);
+        assert!(head.is_group_head && head.is_group_tail);
+        assert_eq!(downstream.empty_space_front, slot_distance(1));
+        assert_eq!(downstream.empty_space_back, slot_distance(2));
+    }
+
+    #[test]
+    fn append_belt_onto_empty_belt() {
+        let mut downstream = belt_with_slots(3, 1);
+        let mut upstream = belt_with_slots(2, 1);
+        assert!(upstream.add_item(sample_stack(4)));
+
+        assert!(downstream.append_belt(upstream).is_ok());
+        downstream.sanity_check();
+        assert_eq!(downstream.empty_space_front, slot_distance(4));
+        assert_eq!(downstream.empty_space_back, 0);
+
+        let mut empty = belt_with_slots(2, 1);
+        assert!(empty.append_belt(belt_with_slots(1, 1)).is_ok());
+        empty.sanity_check();
+        assert!(empty.is_empty());
+        assert_eq!(empty.length, slot_distance(3));
+    }
+
+    #[test]
+    fn append_belt_rejects_connected_seam() {
+        let mut downstream = belt_with_slots(2, 1);
+        downstream.set_input_connection(Some(BeltOutputConnection::new(4, 1, None)));
+        let mut upstream = belt_with_slots(2, 1);
+        assert!(upstream.add_item(sample_stack(1)));
+
+        let returned = downstream
+            .append_belt(upstream)
+            .expect_err("seam is connected");
+        assert_eq!(returned.item_count(), 1);
+        assert_eq!(downstream.length, slot_distance(2));
+
+        let mut downstream = belt_with_slots(2, 1);
+        downstream.set_output_connection(Some(BeltInputConnection::new(4, None)));
+        let mut upstream = belt_with_slots(2, 1);
+        upstream.set_output_connection(Some(BeltInputConnection::new(4, None)));
+        assert!(downstream.append_belt(upstream).is_err());
+
+        // Connections on the far ends carry over to the merged belt.
+        let mut downstream = belt_with_slots(2, 1);
+        downstream.set_output_connection(Some(BeltInputConnection::new(4, None)));
+        let mut upstream = belt_with_slots(2, 1);
+        upstream.set_input_connection(Some(BeltOutputConnection::new(6, 1, None)));
+        assert!(downstream.append_belt(upstream).is_ok());
+        assert_eq!(downstream.output_connection().unwrap().item_limit(), 4);
+        assert_eq!(downstream.input_connection().unwrap().item_limit(), 6);
+    }
+
+    #[test]
+    fn split_at_divides_items_and_connections() {
+        let mut belt = belt_with_slots(10, 1);
+        belt.set_input_connection(Some(BeltOutputConnection::new(4, 1, None)));
+        belt.set_output_connection(Some(BeltInputConnection::new(4, None)));
+        belt.set_max_group_size(Some(2));
+        belt.set_track_age(true);
+
+        // Items end up at slots 3-4 (group of two) and slot 7, with the back two slots free.
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(3));
+        assert!(belt.add_item(sample_stack(3)));
+        run_distance(&mut belt, slot_distance(2));
+        assert_eq!(belt.empty_space_front, slot_distance(3));
+
+        let tail = belt.split_at(slot_distance(6));
+        belt.sanity_check();
+        tail.sanity_check();
+
+        assert_eq!(belt.length, slot_distance(6));
+        assert_eq!(belt.item_count(), 2);
+        assert_eq!(belt.empty_space_front, slot_distance(3));
+        assert_eq!(belt.empty_space_back, slot_distance(1));
+        assert!(belt.output_connection().is_some());
+        assert!(belt.input_connection().is_none());
+
+        assert_eq!(tail.length, slot_distance(4));
+        assert_eq!(tail.item_count(), 1);
+        assert_eq!(tail.empty_space_front, slot_distance(1));
+        assert_eq!(tail.empty_space_back, slot_distance(2));
+        assert!(tail.input_connection().is_some());
+        assert!(tail.output_connection().is_none());
+        assert_eq!(tail.max_group_size(), Some(2));
+        assert!(tail.tracks_age());
+    }
+
+    #[test]
+    fn split_at_keeps_straddling_stack_on_head_side() {
+        let mut belt = belt_with_slots(6, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(2));
+        assert!(belt.add_item(sample_stack(2)));
+
+        // The first stack occupies [3, 4) slots from the front; cut in its middle.
+        let cut = slot_distance(3) + ITEM_WIDTH / 2;
+        let tail = belt.split_at(cut);
+        belt.sanity_check();
+        tail.sanity_check();
+
+        assert_eq!(belt.length, slot_distance(4));
+        assert_eq!(belt.item_count(), 1);
+        assert_eq!(belt.empty_space_back, 0);
+        assert_eq!(tail.length, slot_distance(2));
+        assert_eq!(tail.item_count(), 1);
+        assert_eq!(tail.empty_space_front, slot_distance(1));
+    }
+
+    #[test]
+    fn split_at_handles_empty_halves() {
+        let mut belt = belt_with_slots(6, 1);
+        assert!(belt.add_item(sample_stack(1)));
+
+        let tail = belt.split_at(slot_distance(2));
+        belt.sanity_check();
+        tail.sanity_check();
+        assert!(belt.is_empty());
+        assert_eq!(belt.empty_space_front, slot_distance(2));
+        assert_eq!(tail.item_count(), 1);
+        assert_eq!(tail.empty_space_front, slot_distance(3));
+
+        let mut belt = belt_with_slots(6, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        let tail = belt.split_at(slot_distance(6));
+        belt.sanity_check();
+        tail.sanity_check();
+        assert_eq!(belt.item_count(), 1);
+        assert!(tail.is_empty());
+        assert_eq!(tail.length, 0);
+    }
+
+    #[test]
+    fn can_add_agrees_with_add_item() {
+        let mut belt = belt_with_slots(3, 1);
+        assert_eq!(belt.free_slots(), 3);
+
+        let mut multi = sample_stack(1);
+        multi.multiplicity = 2;
+        assert!(!belt.can_add(&multi));
+        assert!(!belt.add_item(multi));
+
+        // empty belt
+        assert!(belt.can_add(&sample_stack(1)));
+        assert!(belt.add_item(sample_stack(1)));
+        assert_eq!(belt.free_slots(), 0);
+
+        // full at the back
+        assert!(!belt.can_add(&sample_stack(2)));
+        assert!(!belt.add_item(sample_stack(2)));
+
+        // near-full: a single slot opens up
+        run_distance(&mut belt, slot_distance(1));
+        assert_eq!(belt.free_slots(), 1);
+        assert!(belt.can_add(&sample_stack(2)));
+        assert!(belt.add_item(sample_stack(2)));
+        assert_eq!(belt.free_slots(), 0);
+
+        // a partial slot is not enough
+        run_distance(&mut belt, ITEM_WIDTH / 2);
+        assert_eq!(belt.free_slots(), 0);
+        assert!(!belt.can_add(&sample_stack(3)));
+        assert!(!belt.add_item(sample_stack(3)));
+    }
+
+    #[test]
+    fn counts_by_item_type() {
+        let mut belt = belt_with_slots(8, 1);
+        assert_eq!(belt.count_of(ItemType::new(1)), 0);
+        assert!(belt.type_histogram().is_empty());
+
+        // two identical stacks merge into one multiplicity-2 entry
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(2));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(2));
+        assert!(belt.add_item(sample_stack(1)));
+        assert_eq!(belt.items[0].stack.multiplicity, 2);
+
+        assert_eq!(belt.count_of(ItemType::new(1)), 3);
+        assert_eq!(belt.count_of(ItemType::new(2)), 1);
+        assert_eq!(belt.count_of(ItemType::new(3)), 0);
+        assert_eq!(
+            belt.type_histogram(),
+            HashMap::from([(ItemType::new(1), 3), (ItemType::new(2), 1)])
+        );
+        assert_eq!(
+            belt.type_histogram().values().sum::<usize>(),
+            belt.item_count()
+        );
+    }
+
+    #[test]
+    fn set_speed_only_changes_future_advance() {
+        let mut belt = belt_with_slots(10, 8);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(2));
+        assert!(belt.add_item(sample_stack(2)));
+
+        let front_before = belt.empty_space_front;
+        let layout_before: Vec<_> = belt.iter().map(|(s, d)| (s.clone(), d)).collect();
+
+        belt.set_speed(32);
+        assert_eq!(belt.empty_space_front, front_before);
+        assert!(
+            belt.iter()
+                .map(|(s, d)| (s.clone(), d))
+                .eq(layout_before.clone())
+        );
+
+        belt.run(1);
+        assert_eq!(belt.empty_space_front, front_before - 32);
+        assert!(belt.iter().map(|(s, d)| (s.clone(), d)).eq(layout_before));
+        assert_eq!(belt.empty_space_back, 32);
+    }
+
+    #[test]
+    fn clear_returns_all_stacks() {
+        let mut belt = belt_with_slots(8, 1);
+        belt.set_output_connection(Some(BeltInputConnection::new(4, None)));
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(2));
+        assert!(belt.add_item(sample_stack(2)));
+
+        let prior_count = belt.item_count();
+        let removed = belt.clear();
+        belt.sanity_check();
+
+        assert!(belt.is_empty());
+        assert_eq!(
+            removed
+                .iter()
+                .map(|s| s.multiplicity as usize)
+                .sum::<usize>(),
+            prior_count
+        );
+        assert_eq!(removed[0], sample_stack(1));
+        assert_eq!(removed[0].multiplicity, 2);
+        assert_eq!(removed[1], sample_stack(2));
+        assert_eq!(belt.empty_space_front, belt.length);
+        assert_eq!(belt.empty_space_back, belt.length);
+        assert!(belt.output_connection().is_some());
+        assert!(belt.add_item(sample_stack(3)));
+    }
+
+    #[test]
+    fn drain_while_run_matches_remove_while_run() {
+        let build = || {
+            let mut belt = belt_with_slots(12, 1);
+            assert!(belt.add_item(sample_stack(1)));
+            run_distance(&mut belt, slot_distance(1));
+            assert!(belt.add_item(sample_stack(1)));
+            run_distance(&mut belt, slot_distance(2));
+            assert!(belt.add_item(sample_stack(2)));
+            run_distance(&mut belt, slot_distance(3));
+            assert!(belt.add_item(sample_stack(3)));
+            belt
+        };
+
+        for (ticks, filter, limit) in [
+            (slot_distance(12), None, None),
+            (slot_distance(9), None, None),
+            (
+                slot_distance(12),
+                Some(&[ItemType::new(1), ItemType::new(2)][..]),
+                None,
+            ),
+            (slot_distance(12), None, Some(2)),
+        ] {
+            let mut collected = build();
+            let mut lazy = build();
+            let expected = collected.remove_while_run(ticks, filter, limit);
+            let drained: Vec<_> = lazy.drain_while_run(ticks, filter, limit).collect();
+            assert_eq!(drained, expected);
+            assert!(lazy.iter().eq(collected.iter()));
+            assert_eq!(lazy.empty_space_front, collected.empty_space_front);
+            assert_eq!(lazy.empty_space_back, collected.empty_space_back);
+            lazy.sanity_check();
+        }
+    }
+
+    #[test]
+    fn remove_while_run_grouped_sums_each_type() {
+        let layout = [
+            (Stack::new(1, 4), 0),
+            (Stack::new(2, 3), 0),
+            (Stack::new(1, 5), slot_distance(1)),
+            (Stack::new(2, 2), 0),
+        ];
+        let mut belt = Belt::from_layout(slot_distance(8), ITEM_WIDTH, &layout).unwrap();
+
+        let delivered = belt.remove_while_run_grouped(3, None, None);
+        assert_eq!(
+            delivered,
+            HashMap::from([(ItemType::new(1), 4), (ItemType::new(2), 3)])
+        );
+        assert_eq!(belt.count_of(ItemType::new(1)), 1);
+        assert_eq!(belt.count_of(ItemType::new(2)), 1);
+
+        let mut expected = Belt::from_layout(slot_distance(8), ITEM_WIDTH, &layout).unwrap();
+        let stacks = expected.remove_while_run(3, None, None);
+        assert_eq!(stacks, vec![Stack::new(1, 4), Stack::new(2, 3)]);
+        assert!(belt.iter().eq(expected.iter()));
+    }
+
+    #[test]
+    fn drain_while_run_is_lazy() {
+        let mut belt = belt_with_slots(4, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        let to_front = belt.empty_space_front;
+        run_distance(&mut belt, to_front);
+
+        let first = belt.drain_while_run(slot_distance(4), None, None).next();
+        assert_eq!(first, Some(sample_stack(1)));
+
+        // Only the first stack left the belt; the rest of the distance was never spent.
+        assert_eq!(belt.item_count(), 1);
+        assert_eq!(belt.empty_space_front, 0);
+        belt.sanity_check();
+    }
+
+    #[test]
+    fn custom_item_width_belt() {
+        let width = 100;
+        let mut belt = Belt::with_item_width(5 * width, 10, width);
+        assert_eq!(belt.free_slots(), 5);
+
+        assert!(belt.add_item(sample_stack(1)));
+        assert_eq!(belt.empty_space_front, 4 * width);
+        belt.run(5);
+        assert!(!belt.add_item(sample_stack(2)));
+        belt.run(5);
+        assert!(belt.add_item(sample_stack(2)));
+        belt.sanity_check();
+        assert_eq!(belt.items[0].next_item_dist, Some(0));
+
+        let to_front = belt.empty_space_front;
+        run_distance(&mut belt, to_front);
+        assert_eq!(belt.remove_item(), Some(sample_stack(1)));
+        assert_eq!(belt.empty_space_front, width);
+
+        // close the gap left by the removed stack, then carry the next one off
+        let drained = belt.remove_while_run(2 * width / 10, None, None);
+        assert_eq!(drained, vec![sample_stack(2)]);
+        assert!(belt.is_empty());
+        belt.sanity_check();
+
+        // belts of different granularity cannot be spliced
+        let other = belt_with_slots(2, 1);
+        assert!(belt.append_belt(other).is_err());
+    }
+
+    #[test]
+    fn from_layout_matches_incremental_construction() {
+        let mut incremental = belt_with_slots(10, 1);
+        assert!(incremental.add_item(sample_stack(1)));
+        run_distance(&mut incremental, slot_distance(2));
+        assert!(incremental.add_item(sample_stack(2)));
+        run_distance(&mut incremental, slot_distance(1));
+        assert!(incremental.add_item(sample_stack(2)));
+        run_distance(&mut incremental, slot_distance(3));
+        assert!(incremental.add_item(sample_stack(3)));
+        run_distance(&mut incremental, slot_distance(1));
+        assert!(incremental.add_item(sample_stack(3)));
+
+        let built = Belt::from_layout(
+            slot_distance(10),
+            1,
+            &[
+                (sample_stack(1), slot_distance(2)),
+                (sample_stack(2), slot_distance(1)),
+                (sample_stack(2), 0),
+                (sample_stack(3), slot_distance(2)),
+                (sample_stack(3), 0),
+            ],
+        )
+        .expect("layout fits");
+        built.sanity_check();
+
+        assert_eq!(built.empty_space_front, incremental.empty_space_front);
+        assert_eq!(built.empty_space_back, incremental.empty_space_back);
+        assert_eq!(built.items.len(), incremental.items.len());
+        for (a, b) in built.items.iter().zip(incremental.items.iter()) {
+            assert!(a.stack.identical(&b.stack));
+            assert_eq!(a.next_item_dist, b.next_item_dist);
+            assert_eq!(a.group_size, b.group_size);
+            assert_eq!(a.is_group_head, b.is_group_head);
+            assert_eq!(a.is_group_tail, b.is_group_tail);
+        }
+    }
+
+    #[test]
+    fn from_layout_rejects_overfull_layout() {
+        assert!(
+            Belt::from_layout(slot_distance(2), 1, &[(sample_stack(1), slot_distance(2))])
+                .is_none()
+        );
+        assert!(Belt::from_layout(slot_distance(2), 1, &[(Stack::new(1, 0), 0)]).is_none());
+        let empty = Belt::from_layout(slot_distance(2), 1, &[]).expect("empty layout");
+        assert!(empty.is_empty());
+        empty.sanity_check();
+    }
+
+    #[test]
+    fn remove_matching_skips_other_types() {
+        let mut belt = Belt::from_layout(
+            slot_distance(8),
+            ITEM_WIDTH,
+            &[
+                (sample_stack(1), 0),
+                (sample_stack(2), 0),
+                (sample_stack(1), slot_distance(1)),
+                (sample_stack(3), 0),
+                (sample_stack(1), 0),
+            ],
+        )
+        .expect("layout fits");
+
+        // Four ticks reach the first four slots: both type-1 stacks there leave, the one in
+        // the sixth slot stays.
+        let removed = belt.remove_matching(4, &[ItemType::new(1)]);
+        belt.sanity_check();
+        assert_eq!(removed, vec![sample_stack(1), sample_stack(1)]);
+        assert_eq!(belt.item_count(), 3);
+        assert_eq!(belt.empty_space_front, 0);
+        let layout: Vec<_> = belt.iter().map(|(s, d)| (s.clone(), d)).collect();
+        assert_eq!(
+            layout,
+            vec![
+                (sample_stack(2), Some(0)),
+                (sample_stack(3), Some(0)),
+                (sample_stack(1), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_matching_splits_multiplied_entries() {
+        let mut run = sample_stack(4);
+        run.multiplicity = 3;
+        let mut belt = Belt::from_layout(slot_distance(5), ITEM_WIDTH, &[(run, slot_distance(1))])
+            .expect("layout fits");
+
+        let removed = belt.remove_matching(3, &[ItemType::new(4)]);
+        belt.sanity_check();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].multiplicity, 2);
+        assert_eq!(belt.item_count(), 1);
+
+        assert!(belt.remove_matching(1, &[ItemType::new(9)]).is_empty());
+        assert_eq!(belt.item_count(), 1);
+    }
+
+    #[test]
+    fn is_saturated_requires_packed_belt() {
+        let mut belt = belt_with_slots(3, 1);
+        assert!(!belt.is_saturated());
+
+        assert!(belt.add_item(sample_stack(1)));
+        assert!(!belt.is_saturated());
+
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(3)));
+        belt.sanity_check();
+        assert!(belt.is_saturated());
+
+        let mut gappy = belt_with_slots(4, 1);
+        assert!(gappy.add_item(sample_stack(1)));
+        run_distance(&mut gappy, slot_distance(3));
+        assert!(gappy.add_item(sample_stack(2)));
+        run_distance(&mut gappy, slot_distance(1));
+        assert!(gappy.add_item(sample_stack(3)));
+        assert_eq!(gappy.empty_space_front, 0);
+        assert_eq!(gappy.empty_space_back, 0);
+        // 3 stacks on 4 slots: the spare slot sits between stacks, so the belt is not saturated.
+        assert!(!gappy.is_saturated());
+    }
+
+    #[test]
+    fn compact_closes_internal_gaps() {
+        let mut belt = belt_with_slots(10, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(2));
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(3));
+        assert!(belt.add_item(sample_stack(2)));
+
+        let front_before = belt.empty_space_front;
+        let count_before = belt.item_count();
+        belt.compact();
+        belt.sanity_check();
+
+        assert_eq!(belt.empty_space_front, front_before);
+        assert_eq!(belt.item_count(), count_before);
+        assert_eq!(belt.empty_space_back, slot_distance(10 - 4) - front_before);
+        let layout: Vec<_> = belt.iter().map(|(s, d)| (s.clone(), d)).collect();
+        let mut ones = sample_stack(1);
+        ones.multiplicity = 2;
+        let mut twos = sample_stack(2);
+        twos.multiplicity = 2;
+        assert_eq!(layout, vec![(ones, Some(0)), (twos, None)]);
+        assert!(belt.items[0].stack.identical(&layout[0].0));
+        assert!(belt.items[1].stack.identical(&layout[1].0));
+        assert_eq!(belt.items[0].group_size, 2);
+        assert!(belt.items[0].is_group_head && belt.items[1].is_group_tail);
+    }
+
+    #[test]
+    fn resize_grows_an_occupied_belt() {
+        let layout = [(sample_stack(1), slot_distance(1)), (sample_stack(2), 0)];
+        let mut belt = Belt::from_layout(slot_distance(4), ITEM_WIDTH, &layout).unwrap();
+
+        assert_eq!(belt.resize(slot_distance(6)), Ok(()));
+        belt.sanity_check();
+        assert_eq!(belt.length(), slot_distance(6));
+        assert_eq!(belt.free_slots(), 3);
+        assert_eq!(belt.render_ascii(), ".12...");
+
+        // shrinking into the empty space behind the tail keeps every stack
+        assert_eq!(belt.resize(slot_distance(3)), Ok(()));
+        belt.sanity_check();
+        assert_eq!(belt.render_ascii(), ".12");
+    }
+
+    #[test]
+    fn resize_evicts_what_no_longer_fits() {
+        let layout = [
+            (sample_stack(1), 0),
+            (
+                Stack {
+                    multiplicity: 3,
+                    ..sample_stack(2)
+                },
+                slot_distance(1),
+            ),
+            (sample_stack(3), slot_distance(1)),
+        ];
+        let mut belt = Belt::from_layout(slot_distance(8), ITEM_WIDTH, &layout).unwrap();
+        assert_eq!(belt.render_ascii(), "1.222.3.");
+
+        assert_eq!(belt.resize(slot_distance(6)), Err(vec![sample_stack(3)]));
+        belt.sanity_check();
+        assert_eq!(belt.render_ascii(), "1.222.");
+
+        // only the copies hanging off the end are evicted
+        let evicted = belt.resize(slot_distance(3)).unwrap_err();
+        assert_eq!(evicted.len(), 1);
+        assert!(evicted[0].identical(&Stack {
+            multiplicity: 2,
+            ..sample_stack(2)
+        }));
+        belt.sanity_check();
+        assert_eq!(belt.render_ascii(), "1.2");
+        assert_eq!(belt.item_count(), 2);
+    }
+
+    #[test]
+    fn repair_restores_corrupted_groups() {
+        let layout = [
+            (sample_stack(1), 0),
+            (sample_stack(2), 0),
+            (sample_stack(3), 0),
+            (sample_stack(4), slot_distance(1)),
+        ];
+        let mut belt = Belt::from_layout(slot_distance(6), ITEM_WIDTH, &layout).unwrap();
+        assert!(!belt.repair());
+        let healthy = belt.layout_snapshot();
+
+        belt.items[0].group_size = 7;
+        belt.items[1].is_group_head = true;
+        belt.items[2].is_group_tail = false;
+        assert!(belt.repair());
+        belt.sanity_check();
+        assert_eq!(belt.layout_snapshot(), healthy);
+        assert!(!belt.repair());
+    }
+
+    #[test]
+    fn repair_leaves_a_belt_fed_by_its_input_connection_alone() {
+        let mut belt = belt_with_slots(5, ITEM_WIDTH);
+        belt.set_input_connection(Some(BeltOutputConnection::new(10, 1, None)));
+        for _ in 0..5 {
+            assert!(
+                belt.input_connection_mut()
+                    .unwrap()
+                    .accept_stack(&Stack::new(7, 1))
+            );
+            belt.run(1);
+        }
+        belt.sanity_check();
+        let fed = belt.layout_snapshot();
+        assert_eq!(fed.len(), 5);
+
+        assert!(!belt.repair());
+        assert_eq!(belt.layout_snapshot(), fed);
+    }
+
+    #[test]
+    fn reverse_mirrors_layout() {
+        let mut belt = belt_with_slots(10, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(3));
+        assert!(belt.add_item(sample_stack(3)));
+        run_distance(&mut belt, slot_distance(1));
+
+        let mut feeder = BeltOutputConnection::new(6, 2, None);
+        assert!(feeder.accept_stack(&Stack::new(9, 3)));
+        belt.set_input_connection(Some(feeder));
+        belt.set_output_connection(Some(BeltInputConnection::new(
+            4,
+            Some(vec![ItemType::new(3)]),
+        )));
+
+        let layout_before: Vec<_> = belt.iter().map(|(s, d)| (s.clone(), d)).collect();
+        let front_before = belt.empty_space_front;
+        let back_before = belt.empty_space_back;
+
+        belt.reverse();
+        belt.sanity_check();
+        assert_eq!(belt.empty_space_front, back_before);
+        assert_eq!(belt.empty_space_back, front_before);
+        let layout: Vec<_> = belt.iter().map(|(s, d)| (s.clone(), d)).collect();
+        assert_eq!(
+            layout,
+            vec![
+                (sample_stack(3), Some(slot_distance(2))),
+                (sample_stack(2), Some(0)),
+                (sample_stack(1), None),
+            ]
+        );
+        assert!(belt.items[1].is_group_head);
+        assert_eq!(belt.items[1].group_size, 2);
+
+        // connections swapped ends and kept their buffers
+        let output = belt.output_connection().expect("old feeder now drains");
+        assert_eq!(output.buffered_item_count(), 3);
+        let input = belt.input_connection().expect("old drain now feeds");
+        assert_eq!(input.item_filter(), Some(&[ItemType::new(3)][..]));
+        assert_eq!(input.output_stack_size(), 2);
+
+        belt.reverse();
+        belt.sanity_check();
+        assert_eq!(belt.empty_space_front, front_before);
+        assert_eq!(belt.empty_space_back, back_before);
+        assert!(belt.iter().map(|(s, d)| (s.clone(), d)).eq(layout_before));
+        assert_eq!(belt.input_connection().unwrap().buffered_item_count(), 3);
+        assert_eq!(belt.output_connection().unwrap().item_limit(), 4);
+    }
+
+    #[test]
+    fn reverse_front_yields_previous_tail() {
+        let mut belt = belt_with_slots(4, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+
+        belt.reverse();
+        belt.sanity_check();
+        assert_eq!(belt.empty_space_front, 0);
+        assert_eq!(belt.remove_item(), Some(sample_stack(2)));
+
+        let mut empty = belt_with_slots(3, 1);
+        empty.reverse();
+        empty.sanity_check();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn peek_back_stack_reports_tail() {
+        let mut belt = belt_with_slots(5, 1);
+        assert!(belt.peek_back_stack().is_none());
+
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        assert_eq!(belt.peek_back_stack(), Some(&sample_stack(2)));
+
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        let tail = belt.peek_back_stack().expect("tail stack");
+        assert_eq!(tail.multiplicity, 2);
+        assert_eq!(belt.item_count(), 3);
+    }
+
+    #[test]
+    fn add_stack_run_places_whole_run() {
+        let mut belt = belt_with_slots(6, 1);
+        let mut run = sample_stack(1);
+        run.multiplicity = 3;
+        assert_eq!(belt.add_stack_run(run), 3);
+        belt.sanity_check();
+        assert_eq!(belt.item_count(), 3);
+        assert_eq!(belt.free_slots(), 0);
+        assert_eq!(belt.empty_space_front, slot_distance(3));
+
+        // a matching run flush against the tail merges into the tail entry
+        run_distance(&mut belt, slot_distance(2));
+        let mut more = sample_stack(1);
+        more.multiplicity = 2;
+        assert_eq!(belt.add_stack_run(more), 2);
+        belt.sanity_check();
+        assert_eq!(belt.items.len(), 1);
+        assert_eq!(belt.peek_back_stack().unwrap().multiplicity, 5);
+    }
+
+    #[test]
+    fn add_stack_run_partial_when_space_runs_out() {
+        let mut belt = belt_with_slots(4, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(2));
+
+        let mut run = sample_stack(2);
+        run.multiplicity = 5;
+        assert_eq!(belt.add_stack_run(run.clone()), 2);
+        belt.sanity_check();
+        assert_eq!(belt.item_count(), 3);
+        assert_eq!(belt.count_of(ItemType::new(2)), 2);
+        let mut placed = sample_stack(2);
+        placed.multiplicity = 2;
+        assert_eq!(
+            belt.layout_snapshot(),
+            vec![
+                (sample_stack(1), true, false, 2, Some(0)),
+                (placed, false, true, 2, None),
+            ]
+        );
+
+        assert_eq!(belt.add_stack_run(run), 0);
+        assert_eq!(belt.item_count(), 3);
+    }
+
+    #[test]
+    fn fast_forward_matches_ticked_runs() {
+        let layout = [
+            (Stack::new(1, 1), slot_distance(2)),
+            (Stack::new(2, 1), slot_distance(3)),
+            (Stack::new(2, 1), slot_distance(1)),
+            (Stack::new(3, 1), slot_distance(4)),
+        ];
+        let speed = ITEM_WIDTH / 4;
+
+        for ticks in [0, 3, 17, 40, 200] {
+            let mut ticked = Belt::from_layout(slot_distance(16), speed, &layout).unwrap();
+            let mut skipped = Belt::from_layout(slot_distance(16), speed, &layout).unwrap();
+            for _ in 0..ticks {
+                ticked.run(1);
+            }
+            skipped.fast_forward(ticks * speed);
+            skipped.sanity_check();
+
+            assert_eq!(skipped.layout_snapshot(), ticked.layout_snapshot());
+            assert_eq!(skipped.item_count(), ticked.item_count());
+            assert_eq!(skipped.front_gap(), ticked.front_gap());
+            assert_eq!(skipped.free_slots(), ticked.free_slots());
+        }
+    }
+
+    #[test]
+    fn run_ticks_matches_single_tick_runs() {
+        fn connected_belt() -> Belt {
+            let mut belt = Belt::new(slot_distance(6), ITEM_WIDTH / 2);
+            let mut feeder = BeltOutputConnection::new(30, 2, None);
+            feeder.set_max_item_types(2);
+            assert_eq!(feeder.inc_item_count(ItemType::new(1), 9), 0);
+            assert_eq!(feeder.inc_item_count(ItemType::new(2), 5), 0);
+            belt.set_input_connection(Some(feeder));
+            belt.set_output_connection(Some(BeltInputConnection::new(6, None)));
+            belt
+        }
+
+        for ticks in [1, 4, 9, 30] {
+            let mut stepped = connected_belt();
+            let mut batched = connected_belt();
+            for _ in 0..ticks {
+                stepped.run(1);
+            }
+            batched.run_ticks(ticks);
+            batched.sanity_check();
+
+            assert_eq!(batched.layout_snapshot(), stepped.layout_snapshot());
+            assert_eq!(batched.front_gap(), stepped.front_gap());
+            assert_eq!(batched.free_slots(), stepped.free_slots());
+            assert_eq!(batched.metrics(), stepped.metrics());
+            assert_eq!(
+                batched.output_connection().unwrap().buffered_item_count(),
+                stepped.output_connection().unwrap().buffered_item_count()
+            );
+            assert_eq!(
+                batched.input_connection().unwrap().buffered_item_count(),
+                stepped.input_connection().unwrap().buffered_item_count()
+            );
+        }
+
+        // the stepped path differs from one long run once connections are attached
+        let mut stepped = connected_belt();
+        let mut single = connected_belt();
+        stepped.run_ticks(4);
+        single.run(4);
+        assert_ne!(stepped.metrics(), single.metrics());
+    }
+
+    #[test]
+    fn accessors_report_configuration_and_occupancy() {
+        let mut belt = Belt::from_layout(
+            slot_distance(8),
+            5,
+            &[(Stack::new(1, 1), slot_distance(1)), (Stack::new(2, 1), 0)],
+        )
+        .unwrap();
+        assert_eq!(belt.length(), slot_distance(8));
+        assert_eq!(belt.speed(), 5);
+        assert_eq!(belt.occupied_length(), slot_distance(2));
+
+        let mut run = sample_stack(3);
+        run.multiplicity = 3;
+        assert_eq!(belt.add_stack_run(run), 3);
+        belt.set_speed(7);
+        assert_eq!(belt.speed(), 7);
+        assert_eq!(belt.occupied_length(), slot_distance(5));
+    }
+
+    #[test]
+    fn packed_belt_runs_at_max_throughput() {
+        let layout = vec![(sample_stack(1), 0); 8];
+        let mut belt = Belt::from_layout(slot_distance(8), ITEM_WIDTH, &layout).unwrap();
+        belt.set_output_connection(Some(BeltInputConnection::new(100, None)));
+        assert_eq!(belt.max_throughput_per_tick(), 1);
+        assert_eq!(belt.measured_throughput(), 0.0);
+
+        for _ in 0..4 {
+            belt.run(1);
+        }
+        assert_eq!(belt.metrics().ticks, 4);
+        assert_eq!(belt.measured_throughput(), 1.0);
+
+        // once the belt runs dry the measured rate falls below the maximum
+        for _ in 0..8 {
+            belt.run(1);
+        }
+        assert_eq!(belt.measured_throughput(), 8.0 / 12.0);
+        assert_eq!(
+            Belt::new(slot_distance(1), ITEM_WIDTH / 2).max_throughput_per_tick(),
+            0
+        );
+    }
+
+    #[test]
+    fn clones_compare_equal_until_they_diverge() {
+        let mut belt = belt_with_slots(6, ITEM_WIDTH / 2);
+        let mut feeder = BeltOutputConnection::new(20, 1, None);
+        assert_eq!(feeder.inc_item_count(ItemType::new(3), 20), 0);
+        belt.set_input_connection(Some(feeder));
+        belt.set_output_connection(Some(BeltInputConnection::new(2, None)));
+        belt.run(3);
+        assert!(!belt.input_connection().unwrap().is_empty());
+
+        let mut copy = belt.clone();
+        assert_eq!(copy, belt);
+        assert_eq!(
+            copy.input_connection().unwrap().buffered_item_count(),
+            belt.input_connection().unwrap().buffered_item_count()
+        );
+
+        // the clone owns its own connection buffers
+        copy.input_connection_mut().unwrap().dec_item_count(1);
+        assert_ne!(copy, belt);
+        belt.input_connection_mut().unwrap().dec_item_count(1);
+        assert_eq!(copy, belt);
+
+        for _ in 0..5 {
+            copy.run(1);
+        }
+        belt.run_ticks(5);
+        assert_eq!(copy, belt);
+
+        belt.set_speed(1);
+        assert_ne!(copy, belt);
     }
 
-    /// Returns `true` when the belt contains no stacks.
-    pub fn is_empty(&self) -> bool {
-        self.items.is_empty()
+    #[test]
+    fn take_connections_keeps_buffers() {
+        let mut belt = belt_with_slots(2, ITEM_WIDTH);
+        let mut feeder = BeltOutputConnection::new(10, 1, None);
+        assert_eq!(feeder.inc_item_count(ItemType::new(4), 7), 0);
+        belt.set_input_connection(Some(feeder));
+        let mut drain = BeltInputConnection::new(10, None);
+        assert_eq!(drain.inc_item_count(ItemType::new(5), 3), 0);
+        belt.set_output_connection(Some(drain));
+
+        let (input, output) = belt.take_connections();
+        assert!(belt.input_connection().is_none());
+        assert!(belt.output_connection().is_none());
+
+        let input = input.unwrap();
+        assert_eq!(input.buffered_item_count(), 7);
+        assert_eq!(input.current_item_type(), Some(ItemType::new(4)));
+        let output = output.unwrap();
+        assert_eq!(output.buffered_item_count(), 3);
+        assert_eq!(output.current_item_type(), Some(ItemType::new(5)));
+
+        assert_eq!(belt.take_connections(), (None, None));
     }
 
-    /// Returns the number of stack entries currently tracked on the belt.
-    pub fn item_count(&self) -> usize {
-        self.items
-            .iter()
-            .map(|item| item.stack.multiplicity as usize)
-            .sum()
+    #[test]
+    fn render_ascii_shows_gaps_and_groups() {
+        let belt = Belt::from_layout(
+            slot_distance(10),
+            ITEM_WIDTH,
+            &[
+                (Stack::new(1, 1), slot_distance(1)),
+                (Stack::new(1, 1), 0),
+                (Stack::new(11, 1), slot_distance(2)),
+                (
+                    Stack {
+                        multiplicity: 2,
+                        ..Stack::new(37, 1)
+                    },
+                    0,
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(belt.render_ascii(), ".11..bBB..");
+
+        assert_eq!(belt_with_slots(4, ITEM_WIDTH).render_ascii(), "....");
     }
 
-    #[cfg(debug_assertions)]
-    /// Verifies the internal invariants of the belt, panicking in debug builds when something is inconsistent.
-    pub fn sanity_check(&self) {
-        debug_assert!(self.empty_space_front <= self.length);
-        debug_assert!(self.empty_space_back <= self.length);
-        let occupied_length = self
-            .items
-            .iter()
-            .fold(0u32, |acc, item| acc + item.stack.multiplicity * ITEM_WIDTH);
-        debug_assert!(occupied_length <= self.length);
+    #[test]
+    fn groups_reports_each_group() {
+        let belt = Belt::from_layout(
+            slot_distance(10),
+            1,
+            &[
+                (Stack::new(1, 2), 0),
+                (Stack::new(2, 3), 0),
+                (Stack::new(3, 1), slot_distance(1)),
+                (Stack::new(3, 1), 0),
+                (Stack::new(4, 5), slot_distance(2)),
+            ],
+        )
+        .unwrap();
+
+        let groups: Vec<_> = belt.groups().collect();
+        assert_eq!(
+            groups,
+            vec![
+                GroupInfo {
+                    start_index: 0,
+                    size: 2,
+                    head_type: ItemType::new(1),
+                    total_items: 5,
+                },
+                GroupInfo {
+                    start_index: 2,
+                    size: 1,
+                    head_type: ItemType::new(3),
+                    total_items: 2,
+                },
+                GroupInfo {
+                    start_index: 3,
+                    size: 1,
+                    head_type: ItemType::new(4),
+                    total_items: 5,
+                },
+            ]
+        );
+    }
 
-        if self.items.is_empty() {
-            debug_assert_eq!(self.empty_space_front, self.length);
-            debug_assert_eq!(self.empty_space_back, self.length);
-            return;
-        }
+    #[test]
+    fn remove_front_run_takes_several_copies() {
+        let mut belt = Belt::from_layout(
+            slot_distance(8),
+            1,
+            &[
+                (
+                    Stack {
+                        multiplicity: 3,
+                        ..sample_stack(1)
+                    },
+                    0,
+                ),
+                (sample_stack(2), slot_distance(1)),
+            ],
+        )
+        .unwrap();
+
+        let removed = belt.remove_front_run(2).unwrap();
+        assert_eq!(removed.multiplicity, 2);
+        assert_eq!(belt.front_gap(), slot_distance(2));
+        assert_eq!(belt.remove_front_run(2), None);
+        belt.sanity_check();
 
-        debug_assert!(self.empty_space_front + self.empty_space_back <= self.length);
+        run_distance(&mut belt, slot_distance(2));
+        assert_eq!(belt.remove_front_run(5).unwrap().multiplicity, 1);
+        // the gap behind the run and the run's own slot both sit in front of the next stack
+        assert_eq!(belt.front_gap(), slot_distance(2));
+        belt.sanity_check();
+        assert_eq!(belt.remove_front_run(0), None);
+    }
 
-        let mut cur_pos = self.empty_space_front;
-        for item in self.items.iter() {
-            cur_pos += item.stack.multiplicity * ITEM_WIDTH;
-            if let Some(distance) = item.next_item_dist {
-                cur_pos += distance;
-            } else {
-                debug_assert_eq!(self.length - cur_pos, self.empty_space_back);
-            }
-            debug_assert!(cur_pos <= self.length);
+    #[test]
+    fn add_item_with_gap_avoids_grouping() {
+        let gap = ITEM_WIDTH / 2;
+        let mut grouped = belt_with_slots(4, ITEM_WIDTH / 2);
+        let mut gapped = belt_with_slots(4, ITEM_WIDTH / 2);
+        for belt in [&mut grouped, &mut gapped] {
+            assert!(belt.add_item(sample_stack(1)));
+            run_distance(belt, ITEM_WIDTH);
         }
 
-        debug_assert_eq!(cur_pos + self.empty_space_back, self.length);
+        // with exactly one slot free, a plain add joins the tail group but a gapped add waits
+        assert!(grouped.add_item(sample_stack(2)));
+        assert!(!gapped.add_item_with_gap(sample_stack(2), gap));
+        assert_eq!(grouped.groups().count(), 1);
+
+        run_distance(&mut gapped, gap);
+        assert!(gapped.add_item_with_gap(sample_stack(2), gap));
+        gapped.sanity_check();
+        assert_eq!(gapped.groups().count(), 2);
+        assert_eq!(gapped.layout_snapshot()[0].4, Some(gap));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn add_item_saturating_returns_remainder() {
+        let mut belt = belt_with_slots(3, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(2));
 
-    fn sample_stack(id: u16) -> Stack {
-        Stack::new(id, 1)
+        let mut run = sample_stack(2);
+        run.multiplicity = 3;
+        let leftover = belt.add_item_saturating(run.clone()).unwrap();
+        belt.sanity_check();
+        assert_eq!(leftover.multiplicity, 1);
+        assert_eq!(leftover, run);
+        assert_eq!(belt.count_of(ItemType::new(2)), 2);
+
+        // a full belt hands the stack back untouched, and a stack that fits leaves nothing over
+        assert_eq!(belt.add_item_saturating(leftover.clone()), Some(leftover));
+        let mut empty = belt_with_slots(3, 1);
+        assert_eq!(empty.add_item_saturating(run), None);
+        assert_eq!(empty.item_count(), 3);
     }
 
-    fn belt_with_slots(slots: u32, speed: u32) -> Belt {
-        Belt::new(slots * ITEM_WIDTH, speed)
-    }
+    #[test]
+    fn distance_to_output_walks_entries() {
+        let mut belt = belt_with_slots(10, 1);
+        assert_eq!(belt.front_gap(), slot_distance(10));
+        assert_eq!(belt.distance_to_output(0), None);
 
-    fn slot_distance(slots: u32) -> u32 {
-        slots * ITEM_WIDTH
-    }
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(3));
+        assert!(belt.add_item(sample_stack(2)));
 
-    fn ticks_for_distance(belt: &Belt, distance: u32) -> u32 {
-        if distance == 0 {
-            0
-        } else {
-            distance.div_ceil(belt.speed)
-        }
+        assert_eq!(belt.front_gap(), slot_distance(5));
+        assert_eq!(belt.distance_to_output(0), Some(slot_distance(5)));
+        assert_eq!(belt.distance_to_output(1), Some(slot_distance(9)));
+        assert_eq!(belt.distance_to_output(2), None);
     }
 
-    fn run_distance(belt: &mut Belt, distance: u32) {
-        let ticks = ticks_for_distance(belt, distance);
-        if ticks > 0 {
-            belt.run(ticks);
+    #[test]
+    fn metrics_count_congestion() {
+        let mut belt = belt_with_slots(4, ITEM_WIDTH);
+        let mut feeder = BeltOutputConnection::new(10, 1, None);
+        assert_eq!(feeder.inc_item_count(ItemType::new(2), 10), 0);
+        belt.set_input_connection(Some(feeder));
+        belt.set_output_connection(Some(BeltInputConnection::new(1, None)));
+
+        for _ in 0..10 {
+            belt.run(1);
         }
+        belt.sanity_check();
+
+        let metrics = *belt.metrics();
+        assert_eq!(metrics.items_delivered, 1);
+        assert_eq!(metrics.items_fed, belt.item_count() as u64 + 1);
+        assert!(metrics.ticks_blocked > 0);
+        // the first feed packs the belt, so only the delivered slot is ever closed up
+        assert_eq!(metrics.distance_moved, slot_distance(1) as u64);
+
+        // once blocked, every further tick counts and nothing moves
+        belt.run(1);
+        assert_eq!(belt.metrics().ticks_blocked, metrics.ticks_blocked + 1);
+        assert_eq!(belt.metrics().distance_moved, metrics.distance_moved);
+
+        belt.reset_metrics();
+        assert_eq!(*belt.metrics(), BeltMetrics::default());
     }
 
     #[test]
-    fn add_run_remove_single_item() {
-        let mut belt = belt_with_slots(5, 1);
-        // Start: empty length-5 belt (speed 1) awaiting a single stack insertion.
+    fn run_status_reports_delivery_and_stalls() {
+        let mut belt = belt_with_slots(6, ITEM_WIDTH);
+        assert!(belt.add_item(Stack::new(7, 1)));
+        belt.run(1);
+        assert!(belt.add_item(Stack::new(7, 1)));
+        belt.run(1);
+        assert!(belt.add_item(Stack::new(8, 1)));
+        belt.set_output_connection(Some(BeltInputConnection::new(10, None)));
 
-        assert!(belt.add_item(sample_stack(42)));
+        let outcome = belt.run_status(2);
+        belt.sanity_check();
+        assert_eq!(
+            outcome,
+            RunOutcome {
+                items_delivered: 0,
+                output_blocked: false,
+                input_starved: true,
+            }
+        );
+        assert_eq!(belt.front_gap(), slot_distance(1));
+
+        // closing the gap brings the stacks of type 7 to the output, and two more ticks carry
+        // them out, but the connection only buffers one item type so type 8 is refused
+        let outcome = belt.run_status(1);
         belt.sanity_check();
+        assert_eq!(outcome.items_delivered, 0);
+        assert_eq!(belt.front_gap(), 0);
+        let outcome = belt.run_status(2);
+        belt.sanity_check();
+        assert_eq!(outcome.items_delivered, 2);
+        assert!(!outcome.output_blocked);
+        assert_eq!(belt.peek_front_stack(), Some((Stack::new(8, 1), 1)));
 
+        let outcome = belt.run_status(1);
+        belt.sanity_check();
+        assert_eq!(outcome.items_delivered, 0);
+        assert!(outcome.output_blocked);
         assert_eq!(belt.item_count(), 1);
-        assert_eq!(belt.empty_space_front, belt.length - ITEM_WIDTH);
-        assert_eq!(belt.empty_space_back, 0);
+    }
 
-        let head = belt.items.front().expect("item present");
-        assert_eq!(head.stack, sample_stack(42));
-        assert!(head.is_group_head);
-        assert!(head.is_group_tail);
-        assert_eq!(head.group_size, 1);
-        assert_eq!(head.next_item_dist, None);
+    #[test]
+    fn run_status_reports_input_starvation() {
+        let mut belt = belt_with_slots(4, ITEM_WIDTH);
+        let mut feeder = BeltOutputConnection::new(10, 1, None);
+        assert!(feeder.accept_stack(&Stack::new(3, 1)));
+        belt.set_input_connection(Some(feeder));
 
-        let steps_to_front = belt.length - ITEM_WIDTH;
-        run_distance(&mut belt, steps_to_front);
+        let outcome = belt.run_status(1);
         belt.sanity_check();
-        assert_eq!(belt.empty_space_front, 0);
+        assert!(!outcome.input_starved);
+        assert_eq!(belt.item_count(), 1);
 
-        let removed = belt.remove_item();
-        assert_eq!(removed, Some(sample_stack(42)));
-        assert!(belt.is_empty());
-        assert_eq!(belt.item_count(), 0);
-        assert_eq!(belt.empty_space_front, belt.length);
-        assert_eq!(belt.empty_space_back, belt.length);
+        let outcome = belt.run_status(1);
+        belt.sanity_check();
+        assert!(outcome.input_starved);
+        assert_eq!(belt.item_count(), 1);
     }
 
     #[test]
-    fn add_item_rejects_multiplicity_stacks() {
-        let mut belt = belt_with_slots(3, 1);
-        let mut stack = sample_stack(7);
-        stack.multiplicity = 2;
+    fn item_count_including_connections_adds_buffers() {
+        let mut belt = belt_with_slots(4, ITEM_WIDTH);
+        assert!(belt.add_item(Stack::new(1, 1)));
+        assert_eq!(belt.item_count_including_connections(), 1);
+
+        let mut feeder = BeltOutputConnection::new(10, 1, None);
+        assert!(feeder.accept_stack(&Stack::new(2, 4)));
+        let mut drain = BeltInputConnection::new(10, None);
+        assert!(drain.accept_stack(&Stack::new(3, 2)));
+        belt.set_input_connection(Some(feeder));
+        belt.set_output_connection(Some(drain));
 
-        assert!(!belt.add_item(stack));
+        assert_eq!(belt.item_count(), 1);
+        assert_eq!(belt.item_count_including_connections(), 7);
+    }
+
+    #[test]
+    fn item_filter_limits_added_types() {
+        let mut belt = belt_with_slots(4, ITEM_WIDTH);
+        belt.set_item_filter(Some(vec![ItemType::new(5)]));
+        assert_eq!(belt.item_filter(), Some(&[ItemType::new(5)][..]));
+
+        assert!(!belt.can_add(&Stack::new(4, 1)));
+        assert!(!belt.add_item(Stack::new(4, 1)));
+        let mut run = Stack::new(4, 1);
+        run.multiplicity = 2;
+        assert_eq!(belt.add_stack_run(run), 0);
         assert!(belt.is_empty());
-        assert_eq!(belt.empty_space_front, belt.length);
-        assert_eq!(belt.empty_space_back, belt.length);
+
+        assert!(belt.add_item(Stack::new(5, 1)));
+        belt.set_item_filter(None);
+        belt.run(1);
+        assert!(belt.add_item(Stack::new(4, 1)));
+        assert_eq!(belt.item_count(), 2);
+    }
+
+    #[test]
+    fn slow_belt_throughput_matches_speed() {
+        const SPEED: u32 = 7;
+        const WINDOW_TICKS: u32 = ITEM_WIDTH * 10;
+
+        let mut belt = Belt::new(ITEM_WIDTH * 4, SPEED);
+        let mut feeder = BeltOutputConnection::new(u16::MAX, 1, None);
+        assert!(feeder.accept_stack(&Stack::new(1, u16::MAX)));
+        belt.set_input_connection(Some(feeder));
+        belt.set_output_connection(Some(BeltInputConnection::new(u16::MAX, None)));
+
+        // let the belt fill up before measuring
+        for _ in 0..ITEM_WIDTH {
+            belt.run(1);
+            belt.sanity_check();
+        }
+
+        let mut delivered = 0;
+        for _ in 0..WINDOW_TICKS {
+            delivered += belt.run_status(1).items_delivered;
+            belt.sanity_check();
+        }
+
+        // WINDOW_TICKS * SPEED covers exactly 7 * 10 stack widths
+        assert!(
+            delivered.abs_diff(SPEED * 10) <= 1,
+            "delivered {delivered} stacks"
+        );
     }
 
     #[test]
@@ -777,10 +3517,11 @@ mod tests {
         belt.set_input_connection(Some(connection));
 
         belt.run(1);
+        belt.sanity_check();
 
         assert_eq!(belt.item_count(), 2);
         let head = belt.items.front().expect("expected head stack");
-        assert_eq!(head.stack.item_type, 42);
+        assert_eq!(head.stack.item_type, ItemType::new(42));
         assert_eq!(head.stack.item_count, 3);
         assert_eq!(head.stack.multiplicity, 2);
         assert!(belt.input_connection().unwrap().is_empty());
@@ -1330,4 +4071,324 @@ mod tests {
         }
         assert_eq!(belt.item_count(), 6);
     }
+
+    #[test]
+    fn peek_after_ticks_predicts_the_next_head() {
+        let mut belt = belt_with_slots(4, ITEM_WIDTH);
+        belt.set_output_connection(Some(BeltInputConnection::new(10, None)));
+        assert!(belt.add_item(sample_stack(1)));
+        belt.run(2);
+        assert!(belt.add_item(sample_stack(2)));
+        let before = belt.clone();
+
+        // the first stack reaches the output after one tick and leaves on the next
+        assert_eq!(belt.peek_after_ticks(0), Some(ItemType::new(1)));
+        assert_eq!(belt.peek_after_ticks(1), Some(ItemType::new(1)));
+        assert_eq!(belt.peek_after_ticks(2), Some(ItemType::new(2)));
+        // the output still buffers the first type, so the second stack waits at the head
+        assert_eq!(belt.peek_after_ticks(20), Some(ItemType::new(2)));
+        assert_eq!(belt, before);
+    }
+
+    #[test]
+    fn try_add_item_reports_the_reason() {
+        let mut belt = belt_with_slots(1, 1);
+        belt.set_item_filter(Some(vec![ItemType::new(1)]));
+
+        let mut run = sample_stack(1);
+        run.multiplicity = 2;
+        assert_eq!(
+            belt.try_add_item(run),
+            Err(LogisticsError::MultiplicityUnsupported)
+        );
+        assert_eq!(
+            belt.try_add_item(Stack::new(1, 0)),
+            Err(LogisticsError::InvalidStackSize)
+        );
+        assert_eq!(
+            belt.try_add_item(sample_stack(2)),
+            Err(LogisticsError::FilterRejected)
+        );
+        assert_eq!(belt.try_add_item(sample_stack(1)), Ok(()));
+        assert_eq!(
+            belt.try_add_item(sample_stack(1)),
+            Err(LogisticsError::NoSpace)
+        );
+        assert!(!belt.add_item(sample_stack(1)));
+    }
+
+    #[test]
+    fn congestion_score_flags_a_blocked_output() {
+        let mut full_output = BeltInputConnection::new(1, None);
+        assert!(full_output.accept_stack(&sample_stack(9)));
+        let mut congested = belt_with_slots(4, ITEM_WIDTH);
+        congested.set_output_connection(Some(full_output));
+        let mut clear = belt_with_slots(4, ITEM_WIDTH);
+        clear.set_output_connection(Some(BeltInputConnection::new(u16::MAX, None)));
+
+        for belt in [&mut congested, &mut clear] {
+            assert_eq!(belt.congestion_score(10), 0.0);
+            for tick in 0..40 {
+                if tick % 2 == 0 {
+                    belt.add_item(sample_stack(1));
+                }
+                belt.run(1);
+            }
+        }
+
+        assert_eq!(congested.congestion_score(10), 1.0);
+        assert!(congested.congestion_score(1000) > 0.9);
+        assert_eq!(clear.congestion_score(10), 0.0);
+
+        congested.reset_metrics();
+        assert_eq!(congested.congestion_score(10), 0.0);
+        congested.run(1);
+        assert_eq!(congested.congestion_score(10), 1.0);
+    }
+
+    #[test]
+    fn remove_expired_drops_the_oldest_stacks() {
+        let mut belt = belt_with_slots(8, ITEM_WIDTH);
+        belt.set_track_age(true);
+        assert!(belt.add_item(sample_stack(1)));
+        belt.run(3);
+        assert!(belt.add_item(sample_stack(2)));
+        belt.run(2);
+        assert!(belt.add_item(sample_stack(3)));
+        belt.run(1);
+
+        // ages are now 6, 3 and 1
+        assert!(belt.remove_expired(6).is_empty());
+        assert_eq!(belt.remove_expired(5), vec![sample_stack(1)]);
+        belt.sanity_check();
+        assert_eq!(belt.item_count(), 2);
+        assert_eq!(belt.front_gap(), slot_distance(4));
+        assert_eq!(belt.layout_snapshot()[0].4, Some(slot_distance(1)));
+
+        assert_eq!(belt.remove_expired(2), vec![sample_stack(2)]);
+        belt.sanity_check();
+        assert_eq!(belt.peek_back_stack(), Some(&sample_stack(3)));
+        assert_eq!(belt.front_gap(), slot_distance(6));
+
+        // without tracking stacks never age
+        belt.set_track_age(false);
+        belt.run(10);
+        assert!(belt.remove_expired(2).is_empty());
+    }
+
+    #[test]
+    fn prepend_item_onto_empty_belt() {
+        let mut belt = belt_with_slots(3, 1);
+        assert!(!belt.prepend_item(Stack::new(1, 0)));
+        assert!(belt.prepend_item(sample_stack(1)));
+        belt.sanity_check();
+        assert_eq!(belt.front_gap(), 0);
+        assert_eq!(belt.peek_front_stack(), Some((sample_stack(1), 1)));
+        assert_eq!(belt.free_slots(), 2);
+
+        // the output end is taken now
+        assert!(!belt.prepend_item(sample_stack(2)));
+    }
+
+    #[test]
+    fn prepend_item_keeps_the_front_gap() {
+        let mut belt = belt_with_slots(4, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+
+        assert!(belt.prepend_item(sample_stack(2)));
+        belt.sanity_check();
+        assert_eq!(belt.front_gap(), 0);
+        assert_eq!(
+            belt.layout_snapshot(),
+            vec![
+                (sample_stack(2), true, true, 1, Some(slot_distance(1))),
+                (sample_stack(1), true, true, 1, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn prepend_item_heads_the_front_group() {
+        let mut belt = belt_with_slots(4, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        run_distance(&mut belt, slot_distance(1));
+        assert!(belt.add_item(sample_stack(2)));
+        run_distance(&mut belt, slot_distance(1));
+        assert_eq!(belt.front_gap(), slot_distance(1));
+
+        assert!(belt.prepend_item(sample_stack(3)));
+        belt.sanity_check();
+        let snapshot = belt.layout_snapshot();
+        assert_eq!(snapshot[0], (sample_stack(3), true, false, 3, Some(0)));
+        assert_eq!(snapshot[2], (sample_stack(2), false, true, 3, None));
+        assert_eq!(
+            belt.groups().collect::<Vec<_>>(),
+            vec![GroupInfo {
+                start_index: 0,
+                size: 3,
+                head_type: ItemType::new(3),
+                total_items: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn prepend_identical_item_folds_into_head() {
+        let mut belt = belt_with_slots(2, 1);
+        assert!(belt.add_item(sample_stack(1)));
+        assert!(belt.prepend_item(sample_stack(1)));
+        belt.sanity_check();
+        assert_eq!(belt.peek_front_stack(), Some((sample_stack(1), 2)));
+        assert!(belt.is_saturated());
+    }
+
+    #[test]
+    fn run_chain_carries_an_item_down_the_line() {
+        let mut first = belt_with_slots(2, ITEM_WIDTH);
+        first.set_output_connection(Some(BeltInputConnection::new(1, None)));
+        let mut middle = belt_with_slots(2, ITEM_WIDTH);
+        middle.set_input_connection(Some(BeltOutputConnection::new(1, 1, None)));
+        middle.set_output_connection(Some(BeltInputConnection::new(1, None)));
+        let mut last = belt_with_slots(2, ITEM_WIDTH);
+        last.set_input_connection(Some(BeltOutputConnection::new(1, 1, None)));
+        last.set_output_connection(Some(BeltInputConnection::new(10, None)));
+        assert!(first.add_item(sample_stack(1)));
+
+        let mut ticks = 0;
+        while last.output_connection().unwrap().is_empty() {
+            assert!(ticks < 20, "item never reached the end of the line");
+            run_chain(&mut [&mut first, &mut middle, &mut last], 1);
+            ticks += 1;
+        }
+        // two ticks to cross each belt, plus the tick in which each downstream belt is fed
+        assert_eq!(ticks, 8);
+        for belt in [&first, &middle, &last] {
+            belt.sanity_check();
+            assert!(belt.is_empty());
+        }
+    }
+
+    #[test]
+    fn max_group_size_splits_long_runs() {
+        let mut belt = belt_with_slots(10, ITEM_WIDTH);
+        belt.set_max_group_size(Some(3));
+        let mut feeder = BeltOutputConnection::new(10, 1, None);
+        assert!(feeder.accept_stack(&Stack::new(1, 7)));
+        belt.set_input_connection(Some(feeder));
+
+        for _ in 0..20 {
+            belt.run(1);
+            belt.sanity_check();
+        }
+
+        // the run piles up at the front as touching groups of at most three slots
+        let slots: Vec<_> = belt.groups().map(|group| group.total_items).collect();
+        assert_eq!(slots, [3, 3, 1]);
+        let snapshot = belt.layout_snapshot();
+        assert_eq!(snapshot[0].4, Some(0));
+        assert_eq!(snapshot[1].4, Some(0));
+        assert_eq!(belt.front_gap(), 0);
+
+        // rebuilding the groups keeps them apart as well
+        belt.compact();
+        belt.sanity_check();
+        assert_eq!(belt.groups().count(), 3);
+
+        belt.set_output_connection(Some(BeltInputConnection::new(10, None)));
+        for _ in 0..10 {
+            belt.run(1);
+            belt.sanity_check();
+        }
+        assert!(belt.is_empty());
+        assert_eq!(belt.output_connection().unwrap().buffered_item_count(), 7);
+    }
+
+    #[test]
+    fn max_group_size_caps_items_added_at_either_end() {
+        let mut belt = belt_with_slots(5, 1);
+        belt.set_max_group_size(Some(2));
+        for item_type in 1..=3 {
+            assert!(belt.add_item(sample_stack(item_type)));
+            belt.sanity_check();
+            run_distance(&mut belt, slot_distance(1));
+        }
+
+        // the third stack touches the full pair but starts a group of its own
+        let sizes = |belt: &Belt| belt.groups().map(|group| group.size).collect::<Vec<_>>();
+        assert_eq!(sizes(&belt), [2, 1]);
+        assert_eq!(belt.front_gap(), slot_distance(1));
+        assert!(!belt.repair());
+
+        // prepending onto the full front pair does the same at the other end
+        assert!(belt.prepend_item(sample_stack(4)));
+        belt.sanity_check();
+        assert_eq!(sizes(&belt), [1, 2, 1]);
+        assert_eq!(belt.layout_snapshot()[0].4, Some(0));
+
+        // a run longer than the cap is cut into groups as it is placed
+        let mut belt = belt_with_slots(4, 1);
+        belt.set_max_group_size(Some(2));
+        let run = Stack {
+            multiplicity: 3,
+            ..sample_stack(5)
+        };
+        assert_eq!(belt.add_stack_run(run), 3);
+        belt.sanity_check();
+        let slots: Vec<_> = belt.groups().map(|group| group.total_items).collect();
+        assert_eq!(slots, [2, 1]);
+        assert!(!belt.repair());
+    }
+
+    #[test]
+    fn stopped_belt_holds_items() {
+        let mut belt = Belt::from_layout(
+            slot_distance(4),
+            0,
+            &[(sample_stack(1), 0), (sample_stack(2), 0)],
+        )
+        .unwrap();
+        belt.set_output_connection(Some(BeltInputConnection::new(10, None)));
+        let before = belt.clone();
+
+        for _ in 0..100 {
+            belt.run(1);
+        }
+        belt.run_ticks(50);
+        belt.sanity_check();
+        assert_eq!(belt, before);
+        assert!(belt.output_connection().unwrap().is_empty());
+        assert_eq!(belt.max_throughput_per_tick(), 0);
+
+        assert!(belt.add_item(sample_stack(3)));
+        assert_eq!(belt.remove_item(), Some(sample_stack(1)));
+        belt.run(10);
+        belt.sanity_check();
+        assert_eq!(belt.item_count(), 2);
+        assert_eq!(belt.front_gap(), slot_distance(1));
+
+        // powering the belt back on moves the stacks again
+        belt.set_speed(ITEM_WIDTH);
+        belt.run(1);
+        assert_eq!(belt.front_gap(), 0);
+    }
+
+    #[test]
+    fn steady_state_occupancy_regimes() {
+        // 20 slots long, carrying up to two stacks per tick, so a stack crosses in 10 ticks
+        let belt = belt_with_slots(20, ITEM_WIDTH * 2);
+
+        // balanced: whatever enters leaves again, one stack per tick
+        assert_eq!(belt.steady_state_occupancy(1, 1), 10);
+        // input-bound: a faster drain does not thin the belt out further
+        assert_eq!(belt.steady_state_occupancy(1, 3), 10);
+        assert_eq!(belt.steady_state_occupancy(0, 3), 0);
+        // feeding faster than the belt moves packs it
+        assert_eq!(belt.steady_state_occupancy(5, 5), 20);
+        // output-bound: the belt backs up until it is full
+        assert_eq!(belt.steady_state_occupancy(2, 1), 20);
+
+        let stopped = belt_with_slots(20, 0);
+        assert_eq!(stopped.steady_state_occupancy(1, 1), 20);
+    }
 }
@@ -1,11 +1,83 @@
-use crate::logistics::Stack;
+use crate::logistics::{LogisticsError, Stack};
 use crate::types::ItemType;
+use std::collections::VecDeque;
+
+/// How a connection interprets its item filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FilterMode {
+    /// Only the listed item types are accepted.
+    #[default]
+    Allow,
+    /// Every item type except the listed ones is accepted.
+    Deny,
+}
 
-#[derive(Debug, Clone)]
+impl FilterMode {
+    /// Returns `true` if `filter`, read in this mode, lets `item_type` through. No filter lets
+    /// everything through.
+    pub(crate) fn allows(self, filter: Option<&[ItemType]>, item_type: ItemType) -> bool {
+        match filter {
+            None => true,
+            Some(filter) => match self {
+                FilterMode::Allow => filter.contains(&item_type),
+                FilterMode::Deny => !filter.contains(&item_type),
+            },
+        }
+    }
+}
+
+/// A hook fired when a connection's buffer crosses a threshold; see
+/// `BeltInputConnection::set_on_empty` and `set_on_full`. The `Send + Sync` bounds keep
+/// connections, and the belts holding them, usable across threads.
+pub type ConnectionCallback = Box<dyn FnMut() + Send + Sync>;
+
+// The callbacks registered on a connection. They are runtime hooks rather than state: clones
+// start without any, they never affect equality, and they are not serialized.
+#[derive(Default)]
+struct ConnectionCallbacks {
+    on_empty: Option<ConnectionCallback>,
+    on_full: Option<ConnectionCallback>,
+}
+
+impl std::fmt::Debug for ConnectionCallbacks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionCallbacks")
+            .field("on_empty", &self.on_empty.is_some())
+            .field("on_full", &self.on_full.is_some())
+            .finish()
+    }
+}
+
+impl Clone for ConnectionCallbacks {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for ConnectionCallbacks {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct ConnectionState {
     item_limit: u16,
     item_filter: Option<Vec<ItemType>>,
-    buffer: Option<Stack>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    item_filter_mode: FilterMode,
+    // Buffered items in the order they arrived. Each entry has multiplicity 1 and holds a run of
+    // consecutively accepted items of one type; a type may appear in several runs.
+    buffer: VecDeque<Stack>,
+    // How many distinct item types may be buffered at once.
+    max_item_types: usize,
+    // Optional cap below `item_limit` on how many items may be buffered.
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_buffer: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    callbacks: ConnectionCallbacks,
 }
 
 impl ConnectionState {
@@ -13,135 +85,208 @@ impl ConnectionState {
         Self {
             item_limit,
             item_filter,
-            buffer: None,
+            item_filter_mode: FilterMode::Allow,
+            buffer: VecDeque::new(),
+            max_item_types: 1,
+            max_buffer: None,
+            callbacks: ConnectionCallbacks::default(),
         }
     }
 
+    // The number of items the buffer may actually hold: `item_limit`, lowered by `max_buffer`.
+    fn effective_limit(&self) -> u16 {
+        self.max_buffer
+            .map_or(self.item_limit, |cap| cap.min(self.item_limit))
+    }
+
+    fn set_max_item_types(&mut self, max_item_types: usize) {
+        debug_assert!(
+            max_item_types > 0,
+            "connection must accept at least one item type"
+        );
+        self.max_item_types = max_item_types;
+    }
+
     fn item_filter(&self) -> Option<&[ItemType]> {
         self.item_filter.as_deref()
     }
 
+    fn filter_allows(&self, item_type: ItemType) -> bool {
+        self.item_filter_mode.allows(self.item_filter(), item_type)
+    }
+
     fn buffered_item_count(&self) -> u16 {
-        self.buffer
-            .as_ref()
-            .map(|stack| stack.item_count)
-            .unwrap_or(0)
+        self.buffer.iter().map(|stack| stack.item_count).sum()
     }
 
     fn is_empty(&self) -> bool {
-        self.buffer.is_none()
+        self.buffer.is_empty()
     }
 
     fn current_item_type(&self) -> Option<ItemType> {
-        self.buffer.as_ref().map(|stack| stack.item_type)
+        self.buffer.front().map(|stack| stack.item_type)
     }
 
-    fn can_take_item_type(&self, item_type: ItemType) -> bool {
-        if let Some(filter) = &self.item_filter {
-            return filter.contains(&item_type);
-        } else if let Some(buffer) = &self.buffer {
-            return (buffer.item_type == item_type) && (buffer.item_count < self.item_limit);
+    fn is_full(&self) -> bool {
+        self.buffered_item_count() >= self.effective_limit()
+    }
+
+    // Fires the callbacks whose threshold was crossed since the buffer was `was_empty` and
+    // `was_full`. Every method that changes the buffered items reports through here.
+    fn notify(&mut self, was_empty: bool, was_full: bool) {
+        if !was_empty
+            && self.is_empty()
+            && let Some(on_empty) = self.callbacks.on_empty.as_mut()
+        {
+            on_empty();
         }
+        if !was_full
+            && self.is_full()
+            && let Some(on_full) = self.callbacks.on_full.as_mut()
+        {
+            on_full();
+        }
+    }
 
-        true
+    fn distinct_item_types(&self) -> usize {
+        self.buffer
+            .iter()
+            .enumerate()
+            .filter(|(idx, stack)| {
+                !self
+                    .buffer
+                    .range(..idx)
+                    .any(|earlier| earlier.same_type(stack))
+            })
+            .count()
     }
 
-    fn can_take_item_count(&self, item_count: u16) -> bool {
-        if let Some(buffer) = &self.buffer {
-            return (buffer.item_count + item_count) <= self.item_limit;
+    // Returns true if `item_type` is already buffered or there is room for another type.
+    fn has_slot_for(&self, item_type: ItemType) -> bool {
+        self.buffer.iter().any(|stack| stack.item_type == item_type)
+            || self.distinct_item_types() < self.max_item_types
+    }
+
+    // Appends `item_count` items to the newest run, starting a new run on a type change.
+    fn push_items(&mut self, item_type: ItemType, item_count: u16) {
+        let was_full = self.is_full();
+        match self.buffer.back_mut() {
+            Some(last) if last.item_type == item_type => last.item_count += item_count,
+            _ => self.buffer.push_back(Stack::new(item_type, item_count)),
         }
+        self.notify(false, was_full);
+    }
 
-        item_count <= self.item_limit
+    // Removes `item_count` items from the run at `index`, dropping the run once it is used up.
+    fn take_from_run(&mut self, index: usize, item_count: u16) {
+        let was_full = self.is_full();
+        let run = &mut self.buffer[index];
+        run.item_count -= item_count;
+        if run.item_count == 0 {
+            self.buffer.remove(index);
+        }
+        self.notify(false, was_full);
     }
 
-    fn can_accept_stack(&self, stack: &Stack) -> bool {
-        if let Some(filter) = &self.item_filter
-            && !filter.contains(&stack.item_type)
-        {
+    fn can_take_item_type(&self, item_type: ItemType) -> bool {
+        if !self.filter_allows(item_type) {
             return false;
+        } else if !self.buffer.is_empty() {
+            return self.has_slot_for(item_type)
+                && self.buffered_item_count() < self.effective_limit();
+        }
+
+        true
+    }
+
+    fn can_take_item_count(&self, item_count: u16) -> bool {
+        (self.buffered_item_count() as u32 + item_count as u32) <= self.effective_limit() as u32
+    }
+
+    fn check_accept_stack(&self, stack: &Stack) -> Result<(), LogisticsError> {
+        if !self.filter_allows(stack.item_type) {
+            return Err(LogisticsError::FilterRejected);
         }
 
         let stack_items = stack.item_count as u32 * stack.multiplicity;
         if stack_items == 0 {
-            return true;
+            return Ok(());
         }
 
-        match &self.buffer {
-            None => stack_items <= self.item_limit as u32,
-            Some(existing) => {
-                if existing.item_type != stack.item_type {
-                    return false;
-                }
+        if !self.has_slot_for(stack.item_type) {
+            return Err(LogisticsError::TypeMismatch);
+        }
 
-                let total = existing.item_count as u32 + stack_items;
-                total <= self.item_limit as u32
-            }
+        let total = self.buffered_item_count() as u32 + stack_items;
+        if total > self.effective_limit() as u32 {
+            return Err(LogisticsError::NoSpace);
         }
+        Ok(())
     }
 
-    fn accept_stack(&mut self, stack: &Stack) -> bool {
-        if !self.can_accept_stack(stack) {
-            return false;
-        }
+    fn can_accept_stack(&self, stack: &Stack) -> bool {
+        self.check_accept_stack(stack).is_ok()
+    }
 
-        match self.buffer.as_mut() {
-            Some(existing) => {
-                existing.item_count += stack.item_count;
-            }
-            None => {
-                self.buffer = Some(stack.clone());
-            }
-        }
+    fn try_accept_stack(&mut self, stack: &Stack) -> Result<(), LogisticsError> {
+        self.check_accept_stack(stack)?;
 
-        true
+        // check_accept_stack bounds the total by item_limit, so this fits in a u16.
+        let stack_items = (stack.item_count as u32 * stack.multiplicity) as u16;
+        if stack_items > 0 {
+            self.push_items(stack.item_type, stack_items);
+        }
+        Ok(())
     }
 
     fn inc_item_count(&mut self, item_type: ItemType, item_count: u16) -> u16 {
-        let buffer = if let Some(buffer) = self.buffer.as_mut() {
-            if buffer.item_type != item_type {
-                return item_count;
-            }
-            buffer
-        } else {
-            self.buffer = Some(Stack {
-                item_type,
-                item_count: 0,
-                multiplicity: 1,
-            });
-            self.buffer.as_mut().expect("buffer just initialized")
-        };
+        if !self.filter_allows(item_type) || !self.has_slot_for(item_type) {
+            return item_count;
+        }
 
-        let current = buffer.item_count;
-        let allowed = self.item_limit - current;
+        let allowed = self.max_acceptable_item_count();
         let amount_to_add = item_count.min(allowed);
-        buffer.item_count += amount_to_add;
+        if amount_to_add == 0 {
+            return item_count;
+        }
+
+        self.push_items(item_type, amount_to_add);
         item_count - amount_to_add
     }
 
+    // Removes items from the oldest run only.
     fn dec_item_count(&mut self, item_count: u16) -> u16 {
-        let buffer = if let Some(buffer) = self.buffer.as_mut() {
-            buffer
-        } else {
+        let Some(buffer) = self.buffer.front() else {
             return item_count;
         };
 
-        let current = buffer.item_count;
-        let amount_to_remove = item_count.min(current);
-        buffer.item_count -= amount_to_remove;
-
-        if buffer.item_count == 0 {
-            self.buffer = None;
-        }
-
+        let amount_to_remove = item_count.min(buffer.item_count);
+        self.take_from_run(0, amount_to_remove);
         item_count - amount_to_remove
     }
 
     fn max_acceptable_item_count(&self) -> u16 {
-        if let Some(buffer) = &self.buffer {
-            self.item_limit - buffer.item_count
-        } else {
-            self.item_limit
+        self.effective_limit()
+            .saturating_sub(self.buffered_item_count())
+    }
+
+    fn accept_stacks(&mut self, stack: &Stack) -> u32 {
+        let single = Stack::new(stack.item_type, stack.item_count);
+        let accepted = stack.multiplicity.min(self.max_acceptable_stacks(&single));
+        // max_acceptable_stacks bounds the total by item_limit, so this fits in a u16.
+        let items = (accepted * stack.item_count as u32) as u16;
+        if items > 0 {
+            self.push_items(stack.item_type, items);
         }
+
+        accepted
+    }
+
+    fn clear(&mut self) -> Vec<Stack> {
+        let (was_empty, was_full) = (self.is_empty(), self.is_full());
+        let drained = self.buffer.drain(..).collect();
+        self.notify(was_empty, was_full);
+        drained
     }
 
     fn max_acceptable_stacks(&self, stack: &Stack) -> u32 {
@@ -149,9 +294,7 @@ impl ConnectionState {
             return 0;
         }
 
-        if let Some(filter) = &self.item_filter
-            && !filter.contains(&stack.item_type)
-        {
+        if !self.filter_allows(stack.item_type) {
             return 0;
         }
 
@@ -160,29 +303,17 @@ impl ConnectionState {
             return u32::MAX;
         }
 
-        match &self.buffer {
-            None => {
-                let limit = self.item_limit as u32;
-                if per_stack_items > limit {
-                    0
-                } else {
-                    limit / per_stack_items
-                }
-            }
-            Some(existing) => {
-                if existing.item_type != stack.item_type {
-                    return 0;
-                }
-
-                let limit = self.item_limit as u32;
-                if existing.item_count as u32 >= limit {
-                    return 0;
-                }
-
-                let remaining = limit - existing.item_count as u32;
-                remaining / per_stack_items
-            }
+        if !self.has_slot_for(stack.item_type) {
+            return 0;
         }
+
+        let limit = self.effective_limit() as u32;
+        let buffered = self.buffered_item_count() as u32;
+        if buffered >= limit {
+            return 0;
+        }
+
+        (limit - buffered) / per_stack_items
     }
 }
 
@@ -190,28 +321,164 @@ pub trait Connection {
     fn item_limit(&self) -> u16;
     fn item_filter(&self) -> Option<&[ItemType]>;
     fn set_item_filter(&mut self, filter: Option<Vec<ItemType>>);
+    fn item_filter_mode(&self) -> FilterMode;
+    fn set_item_filter_mode(&mut self, mode: FilterMode);
+    /// Returns `true` if the item filter lets `item_type` through, regardless of free space.
+    fn accepts_item_type(&self, item_type: ItemType) -> bool;
     fn buffered_item_count(&self) -> u16;
     fn is_empty(&self) -> bool;
     fn current_item_type(&self) -> Option<ItemType>;
+    /// Returns the oldest buffered run: its item type, item count, and a multiplicity of 1.
+    /// With a single item type this is the whole buffer.
+    fn peek_buffer(&self) -> Option<&Stack>;
     fn can_take_item_type(&self, item_type: ItemType) -> bool;
     fn can_take_item_count(&self, item_count: u16) -> bool;
     fn can_accept_stack(&self, stack: &Stack) -> bool;
-    fn accept_stack(&mut self, stack: &Stack) -> bool;
+    /// Buffers every copy of `stack`, or nothing if they do not all fit, and reports why it was refused.
+    fn try_accept_stack(&mut self, stack: &Stack) -> Result<(), LogisticsError>;
+    /// Accepts as many of the `stack.multiplicity` copies of `stack` as fit and returns how
+    /// many were taken.
+    fn accept_stacks(&mut self, stack: &Stack) -> u32;
     fn inc_item_count(&mut self, item_type: ItemType, item_count: u16) -> u16;
     fn dec_item_count(&mut self, item_count: u16) -> u16;
     fn max_acceptable_item_count(&self) -> u16;
     fn max_acceptable_stacks(&self, stack: &Stack) -> u32;
+    /// Returns the cap on buffered items set by `set_max_buffer`, if any.
+    fn max_buffer(&self) -> Option<u16>;
+    /// Caps how many items the buffer may hold below `item_limit`, so backpressure reaches the
+    /// feeding side sooner. Items already buffered above the cap stay, but no more are accepted
+    /// until the buffer drains below it. `None` removes the cap.
+    fn set_max_buffer(&mut self, cap: Option<u16>);
+    /// Empties the buffer and returns what it held, oldest items first. A buffer holding
+    /// several item types yields one stack per run of consecutively accepted items.
+    fn clear(&mut self) -> Vec<Stack>;
+
+    /// Like `try_accept_stack`, but only reports whether the stack was accepted.
+    fn accept_stack(&mut self, stack: &Stack) -> bool {
+        self.try_accept_stack(stack).is_ok()
+    }
+
+    /// Returns `true` if `stack` would be accepted and, once buffered, could leave as at least
+    /// one full stack. Connections without an output stack size only check `can_accept_stack`.
+    fn can_accept_as_full_stack(&self, stack: &Stack) -> bool {
+        self.can_accept_stack(stack)
+    }
+
+    /// Returns how full the buffer is, from 0.0 (empty) to 1.0 (at `item_limit`). A connection
+    /// with a limit of zero reports 0.0.
+    fn fill_ratio(&self) -> f32 {
+        if self.item_limit() == 0 {
+            return 0.0;
+        }
+
+        self.buffered_item_count() as f32 / self.item_limit() as f32
+    }
+
+    /// Caps the buffer at what `items_per_tick` drains in `ticks`, e.g. the rate at which the
+    /// attached belt absorbs items, so the connection never holds more than that many ticks'
+    /// worth of items.
+    fn set_max_buffer_ticks(&mut self, ticks: u32, items_per_tick: u16) {
+        let cap = ticks
+            .saturating_mul(items_per_tick as u32)
+            .min(u16::MAX as u32);
+        self.set_max_buffer(Some(cap as u16));
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Moves up to `max` items from the front of `from`'s buffer into `to`, oldest runs first, and
+/// returns how many were moved. Stops at the first run whose type `to` filters out or has no room
+/// for, so items never overtake each other.
+pub fn transfer(from: &mut dyn Connection, to: &mut dyn Connection, max: u32) -> u32 {
+    let mut moved: u32 = 0;
+    while moved < max {
+        let Some(run) = from.peek_buffer() else {
+            break;
+        };
+        let item_type = run.item_type;
+        if !to.can_take_item_type(item_type) {
+            break;
+        }
+
+        let wanted = (run.item_count as u32).min(max - moved) as u16;
+        let rejected = to.inc_item_count(item_type, wanted);
+        let accepted = wanted - rejected;
+        if accepted == 0 {
+            break;
+        }
+
+        let missing = from.dec_item_count(accepted);
+        debug_assert_eq!(missing, 0);
+        moved += accepted as u32;
+        if rejected > 0 {
+            break;
+        }
+    }
+
+    moved
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BeltInputConnection {
     state: ConnectionState,
 }
 
 impl BeltInputConnection {
     pub fn new(item_limit: u16, item_filter: Option<Vec<ItemType>>) -> Self {
-        Self {
-            state: ConnectionState::new(item_limit, item_filter),
+        BeltInputConnectionBuilder {
+            item_filter,
+            ..Self::builder().item_limit(item_limit)
+        }
+        .build()
+    }
+
+    /// Returns a builder for configuring a connection by named options.
+    pub fn builder() -> BeltInputConnectionBuilder {
+        BeltInputConnectionBuilder::default()
+    }
+
+    /// Returns how many distinct item types this connection may buffer at once.
+    pub fn max_item_types(&self) -> usize {
+        self.state.max_item_types
+    }
+
+    /// Allows up to `max_item_types` distinct item types to share the buffer. `item_limit` still
+    /// caps the total number of buffered items. Defaults to 1.
+    pub fn set_max_item_types(&mut self, max_item_types: usize) {
+        self.state.set_max_item_types(max_item_types);
+    }
+
+    /// Removes up to `count` items from the oldest buffered run if it holds `item_type`, and
+    /// returns how many were taken. Nothing is taken when a different type is at the front.
+    pub fn take_type(&mut self, item_type: ItemType, count: u16) -> u16 {
+        if self.state.current_item_type() != Some(item_type) {
+            return 0;
+        }
+
+        count - self.state.dec_item_count(count)
+    }
+
+    /// Sets a callback fired whenever the buffer goes from holding items to empty. Callbacks
+    /// are not cloned, compared, or serialized along with the connection.
+    pub fn set_on_empty(&mut self, callback: Option<ConnectionCallback>) {
+        self.state.callbacks.on_empty = callback;
+    }
+
+    /// Sets a callback fired whenever the buffer becomes full, i.e. holds as many items as
+    /// `item_limit` and `max_buffer` allow.
+    pub fn set_on_full(&mut self, callback: Option<ConnectionCallback>) {
+        self.state.callbacks.on_full = callback;
+    }
+
+    /// Turns this connection around so it feeds a belt instead of draining one, keeping its
+    /// limit, filter, and buffered items.
+    pub(crate) fn into_output_connection(self, output_stack_size: u16) -> BeltOutputConnection {
+        debug_assert!(output_stack_size > 0, "output stack size must be non-zero");
+
+        BeltOutputConnection {
+            state: self.state,
+            output_stack_size,
+            priority_fn: None,
         }
     }
 }
@@ -229,6 +496,18 @@ impl Connection for BeltInputConnection {
         self.state.item_filter = filter;
     }
 
+    fn item_filter_mode(&self) -> FilterMode {
+        self.state.item_filter_mode
+    }
+
+    fn set_item_filter_mode(&mut self, mode: FilterMode) {
+        self.state.item_filter_mode = mode;
+    }
+
+    fn accepts_item_type(&self, item_type: ItemType) -> bool {
+        self.state.filter_allows(item_type)
+    }
+
     fn buffered_item_count(&self) -> u16 {
         self.state.buffered_item_count()
     }
@@ -241,6 +520,10 @@ impl Connection for BeltInputConnection {
         self.state.current_item_type()
     }
 
+    fn peek_buffer(&self) -> Option<&Stack> {
+        self.state.buffer.front()
+    }
+
     fn can_take_item_type(&self, item_type: ItemType) -> bool {
         self.state.can_take_item_type(item_type)
     }
@@ -253,8 +536,12 @@ impl Connection for BeltInputConnection {
         self.state.can_accept_stack(stack)
     }
 
-    fn accept_stack(&mut self, stack: &Stack) -> bool {
-        self.state.accept_stack(stack)
+    fn try_accept_stack(&mut self, stack: &Stack) -> Result<(), LogisticsError> {
+        self.state.try_accept_stack(stack)
+    }
+
+    fn accept_stacks(&mut self, stack: &Stack) -> u32 {
+        self.state.accept_stacks(stack)
     }
 
     fn inc_item_count(&mut self, item_type: ItemType, item_count: u16) -> u16 {
@@ -272,12 +559,79 @@ impl Connection for BeltInputConnection {
     fn max_acceptable_stacks(&self, stack: &Stack) -> u32 {
         self.state.max_acceptable_stacks(stack)
     }
+
+    fn max_buffer(&self) -> Option<u16> {
+        self.state.max_buffer
+    }
+
+    fn set_max_buffer(&mut self, cap: Option<u16>) {
+        self.state.max_buffer = cap;
+    }
+
+    fn clear(&mut self) -> Vec<Stack> {
+        self.state.clear()
+    }
+}
+
+/// Builds a [`BeltInputConnection`]. Unset options default to an item limit of 1 and no filter.
+#[derive(Debug, Clone)]
+pub struct BeltInputConnectionBuilder {
+    item_limit: u16,
+    item_filter: Option<Vec<ItemType>>,
+}
+
+impl Default for BeltInputConnectionBuilder {
+    fn default() -> Self {
+        Self {
+            item_limit: 1,
+            item_filter: None,
+        }
+    }
+}
+
+impl BeltInputConnectionBuilder {
+    /// Sets how many items the connection may buffer.
+    pub fn item_limit(mut self, item_limit: u16) -> Self {
+        self.item_limit = item_limit;
+        self
+    }
+
+    /// Restricts the connection to the listed item types.
+    pub fn item_filter(mut self, item_filter: Vec<ItemType>) -> Self {
+        self.item_filter = Some(item_filter);
+        self
+    }
+
+    pub fn build(self) -> BeltInputConnection {
+        BeltInputConnection {
+            state: ConnectionState::new(self.item_limit, self.item_filter),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BeltOutputConnection {
     state: ConnectionState,
     output_stack_size: u16,
+    // Ranks buffered item types when choosing what to emit next; higher drains first.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    priority_fn: Option<fn(ItemType) -> u8>,
+}
+
+// Priority functions compare by address, which is only a best effort: the same function may end
+// up with different addresses, so such connections can compare unequal.
+impl PartialEq for BeltOutputConnection {
+    fn eq(&self, other: &Self) -> bool {
+        let same_priority_fn = match (self.priority_fn, other.priority_fn) {
+            (None, None) => true,
+            (Some(a), Some(b)) => std::ptr::fn_addr_eq(a, b),
+            _ => false,
+        };
+        self.state == other.state
+            && self.output_stack_size == other.output_stack_size
+            && same_priority_fn
+    }
 }
 
 impl BeltOutputConnection {
@@ -286,35 +640,123 @@ impl BeltOutputConnection {
         output_stack_size: u16,
         item_filter: Option<Vec<ItemType>>,
     ) -> Self {
-        debug_assert!(output_stack_size > 0, "output stack size must be non-zero");
-
-        Self {
-            state: ConnectionState::new(item_limit, item_filter),
-            output_stack_size,
+        BeltOutputConnectionBuilder {
+            item_filter,
+            ..Self::builder()
+                .item_limit(item_limit)
+                .output_stack_size(output_stack_size)
         }
+        .build()
+    }
+
+    /// Returns a builder for configuring a connection by named options.
+    pub fn builder() -> BeltOutputConnectionBuilder {
+        BeltOutputConnectionBuilder::default()
     }
 
     pub fn output_stack_size(&self) -> u16 {
         self.output_stack_size
     }
 
+    /// Returns how many distinct item types this connection may buffer at once.
+    pub fn max_item_types(&self) -> usize {
+        self.state.max_item_types
+    }
+
+    /// Allows up to `max_item_types` distinct item types to share the buffer. `item_limit` still
+    /// caps the total number of buffered items. Stacks are emitted in the order their items were
+    /// accepted unless a priority function is set. Defaults to 1.
+    pub fn set_max_item_types(&mut self, max_item_types: usize) {
+        self.state.set_max_item_types(max_item_types);
+    }
+
+    /// Sets a function ranking item types for output. When set, stacks of the buffered type with
+    /// the highest priority are emitted first, with ties going to the items accepted earliest.
+    /// Without one, items are emitted in the order they were accepted.
+    pub fn set_priority_fn(&mut self, priority_fn: Option<fn(ItemType) -> u8>) {
+        self.priority_fn = priority_fn;
+    }
+
+    // Index of the buffered run the next output is taken from.
+    fn next_run_index(&self) -> Option<usize> {
+        let Some(priority_fn) = self.priority_fn else {
+            return if self.state.buffer.is_empty() {
+                None
+            } else {
+                Some(0)
+            };
+        };
+
+        let mut best: Option<(usize, u8)> = None;
+        for (index, run) in self.state.buffer.iter().enumerate() {
+            let priority = priority_fn(run.item_type);
+            if best.is_none_or(|(_, best_priority)| priority > best_priority) {
+                best = Some((index, priority));
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+
+    fn consume_from_run(&mut self, index: usize, item_count: u16) {
+        self.state.take_from_run(index, item_count);
+    }
+
+    /// Sets a callback fired whenever the buffer goes from holding items to empty. See
+    /// `BeltInputConnection::set_on_empty`.
+    pub fn set_on_empty(&mut self, callback: Option<ConnectionCallback>) {
+        self.state.callbacks.on_empty = callback;
+    }
+
+    /// Sets a callback fired whenever the buffer becomes full. See
+    /// `BeltInputConnection::set_on_full`.
+    pub fn set_on_full(&mut self, callback: Option<ConnectionCallback>) {
+        self.state.callbacks.on_full = callback;
+    }
+
+    /// Turns this connection around so it drains a belt instead of feeding one, keeping its
+    /// limit, filter, and buffered items.
+    pub(crate) fn into_input_connection(self) -> BeltInputConnection {
+        BeltInputConnection { state: self.state }
+    }
+
+    /// Returns how many belt slots the next `take_output_batch` call could fill if given unlimited
+    /// slots: one per full `output_stack_size` stack of the next run, plus one for a partial stack.
+    pub fn deliverable_slots(&self) -> u32 {
+        let Some(run_index) = self.next_run_index() else {
+            return 0;
+        };
+
+        let items_available = self.state.buffer[run_index].item_count as u32;
+        items_available.div_ceil(self.output_stack_size as u32)
+    }
+
     pub fn take_output_batch(&mut self, max_stacks: u32) -> Option<OutputBatch> {
-        if max_stacks == 0 {
+        self.take_output_batch_sized(max_stacks, self.output_stack_size)
+    }
+
+    /// Like `take_output_batch`, but emits full stacks of `stack_size` items for this call only,
+    /// ignoring `output_stack_size`. Returns `None` if `stack_size` is zero.
+    pub fn take_output_batch_sized(
+        &mut self,
+        max_stacks: u32,
+        stack_size: u16,
+    ) -> Option<OutputBatch> {
+        if max_stacks == 0 || stack_size == 0 {
             return None;
         }
 
-        let buffer = self.state.buffer.as_ref()?;
+        let run_index = self.next_run_index()?;
+        let buffer = &self.state.buffer[run_index];
         if buffer.item_count == 0 {
             return None;
         }
 
-        let output_size = self.output_stack_size as u32;
+        let output_size = stack_size as u32;
         let mut items_available = buffer.item_count as u32;
         let mut slots_remaining = max_stacks;
 
         let mut full_stack_count = 0u32;
-        if output_size > 0 {
-            let possible_full = items_available / output_size;
+        if let Some(possible_full) = items_available.checked_div(output_size) {
             full_stack_count = possible_full.min(slots_remaining);
             items_available -= full_stack_count * output_size;
             slots_remaining -= full_stack_count;
@@ -334,7 +776,7 @@ impl BeltOutputConnection {
         let full_stack = if full_stack_count > 0 {
             Some(Stack {
                 item_type: buffer.item_type,
-                item_count: self.output_stack_size,
+                item_count: stack_size,
                 multiplicity: full_stack_count,
             })
         } else {
@@ -351,12 +793,7 @@ impl BeltOutputConnection {
             None
         };
 
-        let remaining = buffer.item_count as u32 - consumed_items;
-        if remaining == 0 {
-            self.state.buffer = None;
-        } else if let Some(existing) = self.state.buffer.as_mut() {
-            existing.item_count = remaining as u16;
-        }
+        self.consume_from_run(run_index, consumed_items as u16);
 
         Some(OutputBatch {
             full_stack,
@@ -364,8 +801,16 @@ impl BeltOutputConnection {
         })
     }
 
+    /// Drains the next buffered run in one batch: every full stack plus a trailing partial
+    /// stack. `num_stacks` on the result is the number of belt slots the batch needs. With a
+    /// single item type this empties the buffer; with several, call it once per run.
+    pub fn take_all(&mut self) -> Option<OutputBatch> {
+        self.take_output_batch(u32::MAX)
+    }
+
+    /// Previews the stack `take_next_output` would emit next, without removing it.
     pub fn peek_next_output(&self) -> Option<Stack> {
-        let buffer = self.state.buffer.as_ref()?;
+        let buffer = &self.state.buffer[self.next_run_index()?];
         let count = buffer.item_count.min(self.output_stack_size);
 
         if count == 0 {
@@ -379,29 +824,13 @@ impl BeltOutputConnection {
         })
     }
 
+    /// Removes and returns the next stack of up to `output_stack_size` items. Items leave in
+    /// the order they were accepted (or by priority, see `set_priority_fn`), so a stack never
+    /// mixes items from different runs.
     pub fn take_next_output(&mut self) -> Option<Stack> {
-        let count;
-        {
-            let buffer = self.state.buffer.as_ref()?;
-            if buffer.item_count == 0 {
-                return None;
-            }
-            count = buffer.item_count.min(self.output_stack_size);
-        }
-
-        let mut buffer = self.state.buffer.take().expect("buffer existed");
-        let emitted = Stack {
-            item_type: buffer.item_type,
-            item_count: count,
-            multiplicity: 1,
-        };
-
-        buffer.item_count -= count;
-        if buffer.item_count > 0 {
-            buffer.multiplicity = 1;
-            self.state.buffer = Some(buffer);
-        }
-
+        let emitted = self.peek_next_output()?;
+        let run_index = self.next_run_index()?;
+        self.consume_from_run(run_index, emitted.item_count);
         Some(emitted)
     }
 }
@@ -419,6 +848,18 @@ impl Connection for BeltOutputConnection {
         self.state.item_filter = filter;
     }
 
+    fn item_filter_mode(&self) -> FilterMode {
+        self.state.item_filter_mode
+    }
+
+    fn set_item_filter_mode(&mut self, mode: FilterMode) {
+        self.state.item_filter_mode = mode;
+    }
+
+    fn accepts_item_type(&self, item_type: ItemType) -> bool {
+        self.state.filter_allows(item_type)
+    }
+
     fn buffered_item_count(&self) -> u16 {
         self.state.buffered_item_count()
     }
@@ -431,6 +872,10 @@ impl Connection for BeltOutputConnection {
         self.state.current_item_type()
     }
 
+    fn peek_buffer(&self) -> Option<&Stack> {
+        self.state.buffer.front()
+    }
+
     fn can_take_item_type(&self, item_type: ItemType) -> bool {
         self.state.can_take_item_type(item_type)
     }
@@ -443,8 +888,26 @@ impl Connection for BeltOutputConnection {
         self.state.can_accept_stack(stack)
     }
 
-    fn accept_stack(&mut self, stack: &Stack) -> bool {
-        self.state.accept_stack(stack)
+    // The stack joins the newest run if it has the same type, and full stacks are cut from a
+    // single run, so that run has to reach `output_stack_size`.
+    fn can_accept_as_full_stack(&self, stack: &Stack) -> bool {
+        if !self.state.can_accept_stack(stack) {
+            return false;
+        }
+
+        let joined = match self.state.buffer.back() {
+            Some(last) if last.item_type == stack.item_type => last.item_count as u32,
+            _ => 0,
+        };
+        joined + stack.item_count as u32 * stack.multiplicity >= self.output_stack_size as u32
+    }
+
+    fn try_accept_stack(&mut self, stack: &Stack) -> Result<(), LogisticsError> {
+        self.state.try_accept_stack(stack)
+    }
+
+    fn accept_stacks(&mut self, stack: &Stack) -> u32 {
+        self.state.accept_stacks(stack)
     }
 
     fn inc_item_count(&mut self, item_type: ItemType, item_count: u16) -> u16 {
@@ -462,9 +925,22 @@ impl Connection for BeltOutputConnection {
     fn max_acceptable_stacks(&self, stack: &Stack) -> u32 {
         self.state.max_acceptable_stacks(stack)
     }
+
+    fn max_buffer(&self) -> Option<u16> {
+        self.state.max_buffer
+    }
+
+    fn set_max_buffer(&mut self, cap: Option<u16>) {
+        self.state.max_buffer = cap;
+    }
+
+    fn clear(&mut self) -> Vec<Stack> {
+        self.state.clear()
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OutputBatch {
     pub full_stack: Option<Stack>,
     pub partial_stack: Option<Stack>,
@@ -481,6 +957,89 @@ impl OutputBatch {
         }
         used
     }
+
+    /// Returns how many items the batch carries, counting every copy of the full stack.
+    pub fn total_items(&self) -> u32 {
+        let full = self
+            .full_stack
+            .as_ref()
+            .map_or(0, |stack| stack.item_count as u32 * stack.multiplicity);
+        let partial = self
+            .partial_stack
+            .as_ref()
+            .map_or(0, |stack| stack.item_count as u32);
+        full + partial
+    }
+
+    /// Returns the item type the batch carries. Both stacks of a batch come from one run, so
+    /// they always share it.
+    pub fn item_type(&self) -> Option<ItemType> {
+        self.full_stack
+            .as_ref()
+            .or(self.partial_stack.as_ref())
+            .map(|stack| stack.item_type)
+    }
+}
+
+/// Builds a [`BeltOutputConnection`]. Unset options default to an item limit of 1, an output
+/// stack size of 1, and no filter.
+#[derive(Debug, Clone)]
+pub struct BeltOutputConnectionBuilder {
+    item_limit: u16,
+    output_stack_size: u16,
+    item_filter: Option<Vec<ItemType>>,
+}
+
+impl Default for BeltOutputConnectionBuilder {
+    fn default() -> Self {
+        Self {
+            item_limit: 1,
+            output_stack_size: 1,
+            item_filter: None,
+        }
+    }
+}
+
+impl BeltOutputConnectionBuilder {
+    /// Sets how many items the connection may buffer.
+    pub fn item_limit(mut self, item_limit: u16) -> Self {
+        self.item_limit = item_limit;
+        self
+    }
+
+    /// Sets the largest stack the connection emits onto a belt. Must be non-zero.
+    pub fn output_stack_size(mut self, output_stack_size: u16) -> Self {
+        self.output_stack_size = output_stack_size;
+        self
+    }
+
+    /// Restricts the connection to the listed item types.
+    pub fn item_filter(mut self, item_filter: Vec<ItemType>) -> Self {
+        self.item_filter = Some(item_filter);
+        self
+    }
+
+    /// Builds the connection, or returns `InvalidStackSize` if the output stack size is zero.
+    pub fn try_build(self) -> Result<BeltOutputConnection, LogisticsError> {
+        if self.output_stack_size == 0 {
+            return Err(LogisticsError::InvalidStackSize);
+        }
+
+        Ok(self.build())
+    }
+
+    pub fn build(self) -> BeltOutputConnection {
+        debug_assert!(
+            self.output_stack_size > 0,
+            "output stack size must be non-zero"
+        );
+
+        BeltOutputConnection {
+            state: ConnectionState::new(self.item_limit, self.item_filter),
+            output_stack_size: self.output_stack_size,
+            priority_fn: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -491,6 +1050,149 @@ mod tests {
         Stack::new(item_type, count)
     }
 
+    #[test]
+    fn builders_match_positional_constructors() {
+        let filter = vec![ItemType::new(1), ItemType::new(4)];
+
+        let input = BeltInputConnection::builder()
+            .item_limit(12)
+            .item_filter(filter.clone())
+            .build();
+        assert_eq!(input.item_limit(), 12);
+        assert_eq!(input.item_filter(), Some(filter.as_slice()));
+
+        let output = BeltOutputConnection::builder()
+            .output_stack_size(3)
+            .item_limit(5)
+            .build();
+        let positional = BeltOutputConnection::new(5, 3, None);
+        assert_eq!(output.item_limit(), positional.item_limit());
+        assert_eq!(output.output_stack_size(), positional.output_stack_size());
+        assert_eq!(output.item_filter(), None);
+    }
+
+    #[test]
+    fn deliverable_slots_matches_output_batch() {
+        for (output_stack_size, buffered) in [(3, 7), (3, 9), (4, 2), (1, 5), (3, 0)] {
+            let mut output = BeltOutputConnection::new(20, output_stack_size, None);
+            assert_eq!(output.inc_item_count(ItemType::new(1), buffered), 0);
+
+            let slots = output.deliverable_slots();
+            let batch_slots = output
+                .take_output_batch(u32::MAX)
+                .map_or(0, |batch| batch.num_stacks());
+            assert_eq!(
+                slots, batch_slots,
+                "stack size {output_stack_size}, {buffered} buffered"
+            );
+        }
+    }
+
+    #[test]
+    fn sized_batch_overrides_output_stack_size() {
+        let item_type = ItemType::new(1);
+        let mut output = BeltOutputConnection::new(20, 2, None);
+        assert_eq!(output.inc_item_count(item_type, 10), 0);
+
+        assert!(output.take_output_batch_sized(3, 0).is_none());
+        let batch = output.take_output_batch_sized(2, 4).unwrap();
+        let full = batch.full_stack.unwrap();
+        assert_eq!((full.item_count, full.multiplicity), (4, 2));
+        assert!(batch.partial_stack.is_none());
+
+        // the two items left over form a partial stack
+        let batch = output.take_output_batch_sized(2, 4).unwrap();
+        assert!(batch.full_stack.is_none());
+        assert_eq!(batch.partial_stack, Some(Stack::new(item_type, 2)));
+        assert!(output.is_empty());
+
+        // later pulls fall back to the configured size
+        assert_eq!(output.inc_item_count(item_type, 4), 0);
+        let full = output
+            .take_output_batch(u32::MAX)
+            .unwrap()
+            .full_stack
+            .unwrap();
+        assert_eq!((full.item_count, full.multiplicity), (2, 2));
+    }
+
+    #[test]
+    fn output_batch_summarizes_its_contents() {
+        let item_type = ItemType::new(5);
+        let mut output = BeltOutputConnection::new(20, 3, None);
+        assert_eq!(output.inc_item_count(item_type, 11), 0);
+
+        let batch = output.take_all().unwrap();
+        assert!(batch.full_stack.is_some() && batch.partial_stack.is_some());
+        assert_eq!(batch.num_stacks(), 4);
+        assert_eq!(batch.total_items(), 11);
+        assert_eq!(batch.item_type(), Some(item_type));
+
+        let empty = OutputBatch {
+            full_stack: None,
+            partial_stack: None,
+        };
+        assert_eq!(empty.total_items(), 0);
+        assert_eq!(empty.item_type(), None);
+    }
+
+    #[test]
+    fn transfer_stops_when_destination_fills() {
+        let mut from = BeltOutputConnection::new(20, 1, None);
+        assert_eq!(from.inc_item_count(ItemType::new(1), 7), 0);
+        let mut to = BeltInputConnection::new(5, None);
+
+        assert_eq!(transfer(&mut from, &mut to, 3), 3);
+        assert_eq!(transfer(&mut from, &mut to, 10), 2);
+        assert_eq!(to.buffered_item_count(), 5);
+        assert_eq!(from.buffered_item_count(), 2);
+        assert_eq!(transfer(&mut from, &mut to, 10), 0);
+    }
+
+    #[test]
+    fn transfer_respects_destination_filter() {
+        let mut from = BeltInputConnection::new(10, None);
+        from.set_max_item_types(2);
+        assert_eq!(from.inc_item_count(ItemType::new(1), 2), 0);
+        assert_eq!(from.inc_item_count(ItemType::new(2), 3), 0);
+        let mut to = BeltOutputConnection::new(10, 1, Some(vec![ItemType::new(2)]));
+
+        // the type 1 run at the front blocks the type 2 run behind it
+        assert_eq!(transfer(&mut from, &mut to, 10), 0);
+        assert!(to.is_empty());
+
+        assert_eq!(from.dec_item_count(2), 0);
+        assert_eq!(transfer(&mut from, &mut to, 10), 3);
+        assert_eq!(to.current_item_type(), Some(ItemType::new(2)));
+    }
+
+    #[test]
+    fn max_buffer_caps_acceptance_below_item_limit() {
+        let item_type = ItemType::new(3);
+        let mut input = BeltInputConnection::new(20, None);
+        input.set_max_buffer(Some(6));
+        assert_eq!(input.max_buffer(), Some(6));
+
+        assert_eq!(input.inc_item_count(item_type, 10), 4);
+        assert_eq!(input.buffered_item_count(), 6);
+        assert_eq!(input.max_acceptable_item_count(), 0);
+        assert!(!input.can_take_item_type(item_type));
+        assert!(!input.accept_stack(&sample_stack(3, 1)));
+
+        // lowering the cap below what is buffered keeps the items but stops acceptance
+        input.set_max_buffer(Some(2));
+        assert_eq!(input.buffered_item_count(), 6);
+        assert_eq!(input.max_acceptable_stacks(&sample_stack(3, 1)), 0);
+
+        input.set_max_buffer(None);
+        assert_eq!(input.max_acceptable_item_count(), 14);
+
+        let mut output = BeltOutputConnection::new(50, 1, None);
+        output.set_max_buffer_ticks(4, 2);
+        assert_eq!(output.max_buffer(), Some(8));
+        assert_eq!(output.max_acceptable_stacks(&sample_stack(3, 3)), 2);
+    }
+
     #[test]
     fn accept_stack_respects_limit_and_type_for_output() {
         let mut connection = BeltInputConnection::new(10, None);
@@ -511,20 +1213,355 @@ mod tests {
 
     #[test]
     fn item_filter_blocks_disallowed_items_for_input() {
-        let mut connection = BeltOutputConnection::new(5, 3, Some(vec![1]));
+        let mut connection = BeltOutputConnection::new(5, 3, Some(vec![ItemType::new(1)]));
 
         assert!(connection.accept_stack(&sample_stack(1, 2)));
         assert_eq!(connection.buffered_item_count(), 2);
         assert!(!connection.accept_stack(&sample_stack(2, 1)));
     }
 
+    #[test]
+    fn try_accept_stack_reports_the_reason() {
+        let mut connection =
+            BeltInputConnection::new(5, Some(vec![ItemType::new(1), ItemType::new(2)]));
+        assert_eq!(
+            connection.try_accept_stack(&sample_stack(3, 1)),
+            Err(LogisticsError::FilterRejected)
+        );
+        assert_eq!(connection.try_accept_stack(&sample_stack(1, 4)), Ok(()));
+        assert_eq!(
+            connection.try_accept_stack(&sample_stack(2, 1)),
+            Err(LogisticsError::TypeMismatch)
+        );
+        assert_eq!(
+            connection.try_accept_stack(&sample_stack(1, 2)),
+            Err(LogisticsError::NoSpace)
+        );
+        assert_eq!(connection.buffered_item_count(), 4);
+
+        assert_eq!(
+            BeltOutputConnection::builder()
+                .output_stack_size(0)
+                .try_build()
+                .err(),
+            Some(LogisticsError::InvalidStackSize)
+        );
+    }
+
+    #[test]
+    fn can_accept_as_full_stack_needs_output_stack_size() {
+        let mut output = BeltOutputConnection::new(10, 4, None);
+        assert!(output.can_accept_stack(&sample_stack(1, 3)));
+        assert!(!output.can_accept_as_full_stack(&sample_stack(1, 3)));
+        assert!(output.can_accept_as_full_stack(&sample_stack(1, 4)));
+        let mut pair = sample_stack(1, 2);
+        pair.multiplicity = 2;
+        assert!(output.can_accept_as_full_stack(&pair));
+
+        // buffered items of the same type count toward the full stack
+        assert!(output.accept_stack(&sample_stack(1, 3)));
+        assert!(output.can_accept_as_full_stack(&sample_stack(1, 1)));
+        assert!(!output.can_accept_as_full_stack(&sample_stack(2, 1)));
+        // still limited by the buffer
+        assert!(!output.can_accept_as_full_stack(&sample_stack(1, 8)));
+
+        // connections without an output stack size fall back to can_accept_stack
+        let input = BeltInputConnection::new(10, None);
+        assert!(input.can_accept_as_full_stack(&sample_stack(1, 1)));
+    }
+
+    #[test]
+    fn callbacks_fire_on_full_and_empty_transitions() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let emptied = Arc::new(AtomicU32::new(0));
+        let filled = Arc::new(AtomicU32::new(0));
+        let mut connection = BeltOutputConnection::new(6, 2, None);
+        let counter = Arc::clone(&emptied);
+        connection.set_on_empty(Some(Box::new(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })));
+        let counter = Arc::clone(&filled);
+        connection.set_on_full(Some(Box::new(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })));
+
+        assert!(connection.accept_stack(&sample_stack(1, 2)));
+        assert_eq!(connection.inc_item_count(ItemType::new(1), 9), 5);
+        assert_eq!(filled.load(Ordering::Relaxed), 1);
+        assert!(!connection.accept_stack(&sample_stack(1, 1)));
+
+        assert_eq!(connection.dec_item_count(1), 0);
+        assert!(connection.take_next_output().is_some());
+        assert_eq!(emptied.load(Ordering::Relaxed), 0);
+        assert!(connection.take_all().is_some());
+        assert!(connection.is_empty());
+        assert_eq!(connection.clear(), Vec::new());
+
+        assert_eq!(filled.load(Ordering::Relaxed), 1);
+        assert_eq!(emptied.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_preserves_connections() {
+        let mut input = BeltInputConnection::new(10, Some(vec![ItemType::new(1)]));
+        input.set_item_filter_mode(FilterMode::Deny);
+        input.set_max_item_types(2);
+        assert!(input.accept_stack(&sample_stack(2, 3)));
+        assert!(input.accept_stack(&sample_stack(3, 4)));
+        input.set_max_buffer(Some(8));
+
+        let json = serde_json::to_string(&input).expect("input connection serializes");
+        let restored: BeltInputConnection =
+            serde_json::from_str(&json).expect("input connection deserializes");
+        assert_eq!(restored, input);
+        assert_eq!(restored.item_limit(), 10);
+        assert_eq!(restored.item_filter(), Some(&[ItemType::new(1)][..]));
+        assert_eq!(restored.clone().clear(), input.clone().clear());
+
+        let mut output = BeltOutputConnection::new(12, 4, None);
+        assert!(output.accept_stack(&sample_stack(5, 9)));
+        let json = serde_json::to_string(&output).expect("output connection serializes");
+        let mut restored: BeltOutputConnection =
+            serde_json::from_str(&json).expect("output connection deserializes");
+        assert_eq!(restored, output);
+        assert_eq!(restored.output_stack_size(), 4);
+        assert_eq!(restored.buffered_item_count(), 9);
+
+        let batch = restored.take_all().expect("buffered items form a batch");
+        let json = serde_json::to_string(&batch).expect("batch serializes");
+        let restored_batch: OutputBatch = serde_json::from_str(&json).expect("batch deserializes");
+        let full = restored_batch
+            .full_stack
+            .as_ref()
+            .expect("full stacks kept");
+        assert!(full.identical(batch.full_stack.as_ref().unwrap()));
+        assert_eq!(full.multiplicity, 2);
+        assert_eq!(restored_batch.partial_stack, Some(sample_stack(5, 1)));
+    }
+
+    #[test]
+    fn take_type_only_takes_the_front_type() {
+        let mut connection = BeltInputConnection::new(10, None);
+        connection.set_max_item_types(2);
+        assert!(connection.accept_stack(&sample_stack(1, 3)));
+        assert!(connection.accept_stack(&sample_stack(2, 4)));
+
+        assert_eq!(connection.take_type(ItemType::new(2), 2), 0);
+        assert_eq!(connection.buffered_item_count(), 7);
+
+        // only the front run is drained, even if more is asked for
+        assert_eq!(connection.take_type(ItemType::new(1), 5), 3);
+        assert_eq!(connection.current_item_type(), Some(ItemType::new(2)));
+        assert_eq!(connection.take_type(ItemType::new(2), 1), 1);
+        assert_eq!(connection.buffered_item_count(), 3);
+    }
+
+    #[test]
+    fn accept_stack_counts_every_copy() {
+        let mut connection = BeltInputConnection::new(10, None);
+        let mut stack = sample_stack(1, 3);
+        stack.multiplicity = 3;
+
+        assert!(connection.accept_stack(&stack));
+        assert_eq!(connection.buffered_item_count(), 9);
+        assert!(!connection.accept_stack(&stack));
+        assert_eq!(connection.max_acceptable_item_count(), 1);
+    }
+
+    #[test]
+    fn multi_type_buffer_respects_type_cap() {
+        let mut connection = BeltOutputConnection::new(10, 4, None);
+        assert_eq!(connection.max_item_types(), 1);
+        assert!(connection.accept_stack(&sample_stack(1, 2)));
+        assert!(!connection.can_take_item_type(ItemType::new(2)));
+        assert!(!connection.accept_stack(&sample_stack(2, 2)));
+
+        connection.set_max_item_types(2);
+        assert!(connection.can_take_item_type(ItemType::new(2)));
+        assert!(connection.accept_stack(&sample_stack(2, 3)));
+        assert_eq!(connection.inc_item_count(ItemType::new(1), 2), 0);
+        assert_eq!(connection.buffered_item_count(), 7);
+        assert_eq!(connection.current_item_type(), Some(ItemType::new(1)));
+        assert_eq!(connection.max_acceptable_item_count(), 3);
+
+        // the type cap applies to both stacks and raw counts
+        assert!(!connection.can_take_item_type(ItemType::new(3)));
+        assert_eq!(connection.max_acceptable_stacks(&sample_stack(3, 1)), 0);
+        assert_eq!(connection.inc_item_count(ItemType::new(3), 1), 1);
+        assert_eq!(connection.max_acceptable_stacks(&sample_stack(2, 1)), 3);
+
+        // removing items drains the oldest run first
+        assert_eq!(connection.dec_item_count(1), 0);
+        assert_eq!(connection.current_item_type(), Some(ItemType::new(1)));
+        assert_eq!(connection.dec_item_count(3), 2);
+        assert_eq!(connection.current_item_type(), Some(ItemType::new(2)));
+
+        let next = connection.take_next_output().expect("type 2 buffered");
+        assert_eq!(next.item_type, ItemType::new(2));
+        assert_eq!(next.item_count, 3);
+        assert_eq!(connection.current_item_type(), Some(ItemType::new(1)));
+        assert_eq!(connection.buffered_item_count(), 2);
+    }
+
+    #[test]
+    fn output_drains_in_acceptance_order() {
+        let mut connection = BeltOutputConnection::new(12, 5, None);
+        connection.set_max_item_types(2);
+        assert!(connection.accept_stack(&sample_stack(1, 2)));
+        assert!(connection.accept_stack(&sample_stack(2, 3)));
+        assert!(connection.accept_stack(&sample_stack(1, 4)));
+
+        let mut drained = Vec::new();
+        while let Some(peeked) = connection.peek_next_output() {
+            let taken = connection.take_next_output().expect("peeked stack");
+            assert_eq!(peeked, taken);
+            drained.push((taken.item_type.id(), taken.item_count));
+        }
+        assert_eq!(drained, vec![(1, 2), (2, 3), (1, 4)]);
+    }
+
+    #[test]
+    fn clear_returns_buffered_items() {
+        let mut input = BeltInputConnection::new(10, None);
+        assert!(input.clear().is_empty());
+        assert!(input.accept_stack(&sample_stack(4, 3)));
+        assert!(input.accept_stack(&sample_stack(4, 2)));
+        assert_eq!(input.clear(), vec![sample_stack(4, 5)]);
+        assert!(input.is_empty());
+        assert_eq!(input.buffered_item_count(), 0);
+
+        let mut output = BeltOutputConnection::new(10, 2, None);
+        output.set_max_item_types(2);
+        assert!(output.accept_stack(&sample_stack(1, 3)));
+        assert!(output.accept_stack(&sample_stack(2, 1)));
+        let cleared = output.clear();
+        assert_eq!(cleared, vec![sample_stack(1, 3), sample_stack(2, 1)]);
+        assert!(output.is_empty());
+        assert!(output.take_next_output().is_none());
+    }
+
+    #[test]
+    fn deny_filter_blocks_listed_items() {
+        let mut connection = BeltInputConnection::new(10, Some(vec![ItemType::new(2)]));
+        assert_eq!(connection.item_filter_mode(), FilterMode::Allow);
+        connection.set_item_filter_mode(FilterMode::Deny);
+
+        assert!(!connection.can_take_item_type(ItemType::new(2)));
+        assert!(!connection.can_accept_stack(&sample_stack(2, 1)));
+        assert_eq!(connection.max_acceptable_stacks(&sample_stack(2, 1)), 0);
+
+        assert!(connection.can_take_item_type(ItemType::new(1)));
+        assert!(connection.accept_stack(&sample_stack(1, 4)));
+        assert_eq!(connection.max_acceptable_stacks(&sample_stack(1, 2)), 3);
+
+        connection.set_item_filter(None);
+        connection.clear();
+        assert!(connection.accept_stack(&sample_stack(2, 1)));
+    }
+
+    #[test]
+    fn priority_fn_overrides_acceptance_order() {
+        fn prefer_type_two(item_type: ItemType) -> u8 {
+            if item_type == ItemType::new(2) { 10 } else { 1 }
+        }
+
+        let mut connection = BeltOutputConnection::new(12, 2, None);
+        connection.set_max_item_types(3);
+        assert!(connection.accept_stack(&sample_stack(1, 3)));
+        assert!(connection.accept_stack(&sample_stack(2, 3)));
+        assert!(connection.accept_stack(&sample_stack(3, 1)));
+        connection.set_priority_fn(Some(prefer_type_two));
+
+        assert_eq!(connection.peek_next_output(), Some(sample_stack(2, 2)));
+        let mut drained = Vec::new();
+        while let Some(stack) = connection.take_next_output() {
+            drained.push((stack.item_type.id(), stack.item_count));
+        }
+        // equal priorities fall back to acceptance order
+        assert_eq!(drained, vec![(2, 2), (2, 1), (1, 2), (1, 1), (3, 1)]);
+
+        assert!(connection.accept_stack(&sample_stack(1, 2)));
+        assert!(connection.accept_stack(&sample_stack(2, 3)));
+        let batch = connection.take_output_batch(1).expect("batch available");
+        assert_eq!(batch.full_stack, Some(sample_stack(2, 2)));
+    }
+
+    #[test]
+    fn fill_ratio_spans_empty_to_full() {
+        let mut connection = BeltInputConnection::new(8, None);
+        assert_eq!(connection.fill_ratio(), 0.0);
+        assert!(connection.accept_stack(&sample_stack(1, 2)));
+        assert_eq!(connection.fill_ratio(), 0.25);
+        assert!(connection.accept_stack(&sample_stack(1, 6)));
+        assert_eq!(connection.fill_ratio(), 1.0);
+
+        let closed = BeltOutputConnection::new(0, 1, None);
+        assert_eq!(closed.fill_ratio(), 0.0);
+    }
+
+    #[test]
+    fn accept_stacks_takes_what_fits() {
+        let mut connection = BeltInputConnection::new(10, Some(vec![ItemType::new(1)]));
+        let mut stack = sample_stack(1, 3);
+        stack.multiplicity = 5;
+
+        assert_eq!(connection.accept_stacks(&stack), 3);
+        assert_eq!(connection.buffered_item_count(), 9);
+        assert_eq!(connection.accept_stacks(&stack), 0);
+
+        let mut filtered = sample_stack(2, 1);
+        filtered.multiplicity = 2;
+        assert_eq!(connection.accept_stacks(&filtered), 0);
+        assert_eq!(connection.buffered_item_count(), 9);
+    }
+
+    #[test]
+    fn peek_buffer_reflects_contents() {
+        let mut connection = BeltOutputConnection::new(10, 2, None);
+        assert!(connection.peek_buffer().is_none());
+
+        assert!(connection.accept_stack(&sample_stack(5, 3)));
+        assert!(connection.accept_stack(&sample_stack(5, 4)));
+        let buffered = connection.peek_buffer().expect("items buffered");
+        assert_eq!(buffered.item_type, ItemType::new(5));
+        assert_eq!(buffered.item_count, 7);
+        assert_eq!(buffered.multiplicity, 1);
+
+        connection.take_next_output();
+        assert_eq!(
+            connection.peek_buffer().map(|stack| stack.item_count),
+            Some(5)
+        );
+        connection.clear();
+        assert!(connection.peek_buffer().is_none());
+    }
+
+    #[test]
+    fn take_all_empties_buffer() {
+        let mut connection = BeltOutputConnection::new(20, 4, None);
+        assert!(connection.take_all().is_none());
+        assert!(connection.accept_stack(&sample_stack(6, 11)));
+
+        let batch = connection.take_all().expect("items buffered");
+        assert_eq!(batch.num_stacks(), 3);
+        let full = batch.full_stack.expect("two full stacks");
+        assert_eq!(full.item_count, 4);
+        assert_eq!(full.multiplicity, 2);
+        let partial = batch.partial_stack.expect("trailing partial stack");
+        assert_eq!(partial.item_count, 3);
+        assert!(connection.is_empty());
+    }
+
     #[test]
     fn taking_output_consumes_items() {
         let mut connection = BeltOutputConnection::new(6, 2, None);
         assert!(connection.accept_stack(&sample_stack(3, 5)));
 
         let first = connection.take_next_output().expect("stack available");
-        assert_eq!(first.item_type, 3);
+        assert_eq!(first.item_type, ItemType::new(3));
         assert_eq!(first.item_count, 2);
         assert_eq!(connection.buffered_item_count(), 3);
 
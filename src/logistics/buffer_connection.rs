@@ -0,0 +1,270 @@
+use crate::logistics::{
+    BeltOutputConnection, Connection, FilterMode, LogisticsError, OutputBatch, Stack, transfer,
+};
+use crate::types::ItemType;
+
+/**
+ * An inline buffer, such as a chest between two belts, modeled as a single connection. Items
+ * accepted on the in side and emitted on the out side share one buffer of up to `item_limit`
+ * items, which may hold several item types as separate runs (see `set_max_item_types`) and
+ * hands them out oldest first.
+ *
+ * Both sides are rate limited per tick: at most `items_in_per_tick` items are accepted and at
+ * most `items_out_per_tick` items leave through `take_output_batch` or `transfer_out` until the
+ * next call to `tick` refills both allowances. The in limit applies to every accepting
+ * `Connection` method, so `transfer` into the buffer respects it. The generic removal methods
+ * (`dec_item_count`, `clear`) ignore the out limit; use `transfer_out` to move items downstream
+ * at the configured rate.
+ */
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BufferConnection {
+    buffer: BeltOutputConnection,
+    items_in_per_tick: u16,
+    items_out_per_tick: u16,
+    // What is left of this tick's allowances.
+    in_remaining: u16,
+    out_remaining: u16,
+}
+
+impl BufferConnection {
+    /// Creates an empty buffer holding up to `item_limit` items, emitting stacks of up to
+    /// `output_stack_size` items, and moving at most the given number of items in and out
+    /// per tick.
+    pub fn new(
+        item_limit: u16,
+        output_stack_size: u16,
+        items_in_per_tick: u16,
+        items_out_per_tick: u16,
+    ) -> Self {
+        Self {
+            buffer: BeltOutputConnection::new(item_limit, output_stack_size, None),
+            items_in_per_tick,
+            items_out_per_tick,
+            in_remaining: items_in_per_tick,
+            out_remaining: items_out_per_tick,
+        }
+    }
+
+    pub fn items_in_per_tick(&self) -> u16 {
+        self.items_in_per_tick
+    }
+
+    pub fn items_out_per_tick(&self) -> u16 {
+        self.items_out_per_tick
+    }
+
+    pub fn output_stack_size(&self) -> u16 {
+        self.buffer.output_stack_size()
+    }
+
+    /// Allows up to `max_item_types` distinct item types to share the buffer. Defaults to 1.
+    pub fn set_max_item_types(&mut self, max_item_types: usize) {
+        self.buffer.set_max_item_types(max_item_types);
+    }
+
+    /// Starts a new tick, refilling the in and out allowances.
+    pub fn tick(&mut self) {
+        self.in_remaining = self.items_in_per_tick;
+        self.out_remaining = self.items_out_per_tick;
+    }
+
+    /// Takes up to `max_stacks` stacks of the oldest buffered run, like
+    /// `BeltOutputConnection::take_output_batch`, without exceeding what is left of this tick's
+    /// out allowance. Stacks shrink below `output_stack_size` when the allowance is smaller.
+    pub fn take_output_batch(&mut self, max_stacks: u32) -> Option<OutputBatch> {
+        let budget = self.out_remaining;
+        let stack_size = self.buffer.output_stack_size().min(budget);
+        if stack_size == 0 {
+            return None;
+        }
+
+        let max_stacks = max_stacks.min((budget / stack_size) as u32);
+        let batch = self
+            .buffer
+            .take_output_batch_sized(max_stacks, stack_size)?;
+        self.out_remaining -= batch.total_items() as u16;
+        Some(batch)
+    }
+
+    /// Moves buffered items into `to`, oldest first, within what is left of this tick's out
+    /// allowance. Returns how many items were moved.
+    pub fn transfer_out(&mut self, to: &mut dyn Connection) -> u32 {
+        let moved = transfer(&mut self.buffer, to, self.out_remaining as u32);
+        self.out_remaining -= moved as u16;
+        moved
+    }
+
+    fn stack_items(stack: &Stack) -> u32 {
+        stack.item_count as u32 * stack.multiplicity
+    }
+}
+
+impl Connection for BufferConnection {
+    fn item_limit(&self) -> u16 {
+        self.buffer.item_limit()
+    }
+
+    fn item_filter(&self) -> Option<&[ItemType]> {
+        self.buffer.item_filter()
+    }
+
+    fn set_item_filter(&mut self, filter: Option<Vec<ItemType>>) {
+        self.buffer.set_item_filter(filter);
+    }
+
+    fn item_filter_mode(&self) -> FilterMode {
+        self.buffer.item_filter_mode()
+    }
+
+    fn set_item_filter_mode(&mut self, mode: FilterMode) {
+        self.buffer.set_item_filter_mode(mode);
+    }
+
+    fn accepts_item_type(&self, item_type: ItemType) -> bool {
+        self.buffer.accepts_item_type(item_type)
+    }
+
+    fn buffered_item_count(&self) -> u16 {
+        self.buffer.buffered_item_count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    fn current_item_type(&self) -> Option<ItemType> {
+        self.buffer.current_item_type()
+    }
+
+    fn peek_buffer(&self) -> Option<&Stack> {
+        self.buffer.peek_buffer()
+    }
+
+    fn can_take_item_type(&self, item_type: ItemType) -> bool {
+        self.in_remaining > 0 && self.buffer.can_take_item_type(item_type)
+    }
+
+    fn can_take_item_count(&self, item_count: u16) -> bool {
+        item_count <= self.in_remaining && self.buffer.can_take_item_count(item_count)
+    }
+
+    fn can_accept_stack(&self, stack: &Stack) -> bool {
+        self.buffer.can_accept_stack(stack) && Self::stack_items(stack) <= self.in_remaining as u32
+    }
+
+    fn try_accept_stack(&mut self, stack: &Stack) -> Result<(), LogisticsError> {
+        if Self::stack_items(stack) > self.in_remaining as u32 {
+            return Err(LogisticsError::NoSpace);
+        }
+
+        self.buffer.try_accept_stack(stack)?;
+        self.in_remaining -= Self::stack_items(stack) as u16;
+        Ok(())
+    }
+
+    fn accept_stacks(&mut self, stack: &Stack) -> u32 {
+        let single = Stack::new(stack.item_type, stack.item_count);
+        let accepted = stack.multiplicity.min(self.max_acceptable_stacks(&single));
+        let taken = self.buffer.accept_stacks(&Stack {
+            multiplicity: accepted,
+            ..stack.clone()
+        });
+        self.in_remaining -= (taken * stack.item_count as u32) as u16;
+        taken
+    }
+
+    fn inc_item_count(&mut self, item_type: ItemType, item_count: u16) -> u16 {
+        let offered = item_count.min(self.in_remaining);
+        let rejected = self.buffer.inc_item_count(item_type, offered);
+        self.in_remaining -= offered - rejected;
+        item_count - (offered - rejected)
+    }
+
+    fn dec_item_count(&mut self, item_count: u16) -> u16 {
+        self.buffer.dec_item_count(item_count)
+    }
+
+    fn max_acceptable_item_count(&self) -> u16 {
+        self.buffer
+            .max_acceptable_item_count()
+            .min(self.in_remaining)
+    }
+
+    fn max_acceptable_stacks(&self, stack: &Stack) -> u32 {
+        let fits = self.buffer.max_acceptable_stacks(stack);
+        match stack.item_count {
+            0 => fits,
+            count => fits.min(self.in_remaining as u32 / count as u32),
+        }
+    }
+
+    fn max_buffer(&self) -> Option<u16> {
+        self.buffer.max_buffer()
+    }
+
+    fn set_max_buffer(&mut self, cap: Option<u16>) {
+        self.buffer.set_max_buffer(cap);
+    }
+
+    fn clear(&mut self) -> Vec<Stack> {
+        self.buffer.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORE: ItemType = ItemType::new(3);
+
+    #[test]
+    fn accumulates_while_downstream_is_blocked() {
+        let mut buffer = BufferConnection::new(20, 2, 4, 2);
+        let mut downstream = BeltOutputConnection::new(2, 2, None);
+        assert!(downstream.accept_stack(&Stack::new(ORE, 2)));
+
+        for _ in 0..3 {
+            buffer.tick();
+            assert_eq!(buffer.inc_item_count(ORE, 10), 6);
+            assert!(!buffer.can_take_item_count(1));
+            assert_eq!(buffer.transfer_out(&mut downstream), 0);
+        }
+        assert_eq!(buffer.buffered_item_count(), 12);
+
+        // once downstream frees up, items leave at the out rate
+        for drained in 1..=3 {
+            downstream.clear();
+            buffer.tick();
+            assert_eq!(buffer.transfer_out(&mut downstream), 2);
+            assert_eq!(buffer.transfer_out(&mut downstream), 0);
+            assert_eq!(buffer.buffered_item_count(), 12 - 2 * drained);
+        }
+    }
+
+    #[test]
+    fn output_batches_respect_the_out_rate() {
+        let mut buffer = BufferConnection::new(20, 4, 20, 6);
+        assert!(buffer.accept_stack(&Stack::new(ORE, 12)));
+        assert!(!buffer.accept_stack(&Stack::new(ORE, 9)));
+
+        let batch = buffer.take_output_batch(u32::MAX).unwrap();
+        assert_eq!(batch.total_items(), 4);
+        let batch = buffer.take_output_batch(u32::MAX).unwrap();
+        assert_eq!(batch.total_items(), 2);
+        assert!(buffer.take_output_batch(u32::MAX).is_none());
+
+        buffer.tick();
+        let batch = buffer.take_output_batch(u32::MAX).unwrap();
+        assert_eq!(batch.total_items(), 4);
+        assert_eq!(buffer.buffered_item_count(), 2);
+
+        // multiplied stacks are cut down to what the in allowance still covers
+        let tripled = Stack {
+            multiplicity: 3,
+            ..Stack::new(ORE, 8)
+        };
+        assert_eq!(buffer.accept_stacks(&tripled), 2);
+        assert_eq!(buffer.buffered_item_count(), 18);
+    }
+}
@@ -1,5 +1,6 @@
 use std::slice;
 
+use crate::logistics::belt_connection::transfer;
 use crate::logistics::{BeltInputConnection, BeltOutputConnection, Connection};
 use crate::types::ItemType;
 
@@ -16,18 +17,35 @@ pub struct BufferedSplitter {
     input_rr_index: usize,
     priority_outputs: Vec<BeltOutputConnection>,
     rr_outputs: Vec<BeltOutputConnection>,
+    // Relative share of round-robin items each rr output receives, one weight per rr output.
+    rr_weights: Vec<u16>,
+    // Smooth weighted round robin credit per rr output, carried across ticks so uneven weights hold up even
+    // when items trickle in one at a time.
+    rr_credits: Vec<i32>,
     output_rr_index: usize,
+    // Filled in order, only once no priority or rr output can take any more.
+    overflow_outputs: Vec<BeltOutputConnection>,
+    // Set by the last run when buffered inputs could not move because no output's filter admits their types.
+    filter_deadlocked: bool,
+    // Caps how many items the priority inputs may move per tick, so rr inputs are not starved.
+    max_priority_share: Option<u32>,
 }
 
 /// Drains items from the given input connections and distributes them to the output connections based on priority
-/// and round-robin strategy. Assumes input connections are all equal priority.
+/// and round-robin strategy. Assumes input connections are all equal priority. At most `budget` items are moved,
+/// and `budget` is reduced by the number actually moved.
+#[allow(clippy::too_many_arguments)]
 fn drain_connections(
     item_type: ItemType,
     rr_inputs: &mut [BeltInputConnection],
     input_rr_index: &mut usize,
     priority_outputs: &mut [BeltOutputConnection],
     rr_outputs: &mut [BeltOutputConnection],
+    rr_weights: &[u16],
+    rr_credits: &mut [i32],
     output_rr_index: &mut usize,
+    overflow_outputs: &mut [BeltOutputConnection],
+    budget: &mut u32,
 ) -> Option<()> {
     if rr_inputs.is_empty() {
         return None;
@@ -38,13 +56,17 @@ fn drain_connections(
         .filter(|c| c.current_item_type() == Some(item_type))
         .map(|c| c.buffered_item_count())
         .sum();
+    let item_count = item_count.min((*budget).min(u16::MAX as u32) as u16);
     // distribute items. This does not consume from the inputs, which will be done next.
     let remaining_item_count = distribute_items(
         item_count,
         item_type,
         priority_outputs,
         rr_outputs,
+        rr_weights,
+        rr_credits,
         output_rr_index,
+        overflow_outputs,
     );
 
     /*
@@ -52,9 +74,11 @@ fn drain_connections(
      * to maintain correctness.
      */
     let mut consumed_item_count = item_count - remaining_item_count;
+    *budget -= consumed_item_count as u32;
     while consumed_item_count > 0 {
         let non_empty_inputs = rr_inputs
             .iter()
+            .filter(|c| c.current_item_type() == Some(item_type))
             .map(|c| c.buffered_item_count())
             .filter(|&count| count > 0);
         let num_non_empty = non_empty_inputs.clone().count() as u16;
@@ -68,19 +92,24 @@ fn drain_connections(
         let amount_per_belt = amount_to_take / num_non_empty;
         let leftover = amount_to_take % num_non_empty;
 
+        // The first `leftover` matching inputs from the cursor give one extra item.
+        let start = *input_rr_index;
+        let mut extra_given = 0;
         for i in 0..rr_inputs.len() {
-            let index = (*input_rr_index + i) % rr_inputs.len();
+            let index = (start + i) % rr_inputs.len();
             if rr_inputs[index].current_item_type() != Some(item_type) {
                 continue;
             }
 
-            let to_take = if i < leftover as usize {
+            let to_take = if extra_given < leftover {
+                extra_given += 1;
                 *input_rr_index = (index + 1) % rr_inputs.len();
                 amount_per_belt + 1
             } else {
                 amount_per_belt
             };
-            debug_assert_eq!(rr_inputs[index].dec_item_count(to_take), 0);
+            let missing = rr_inputs[index].dec_item_count(to_take);
+            debug_assert_eq!(missing, 0);
         }
 
         consumed_item_count -= amount_to_take;
@@ -90,12 +119,48 @@ fn drain_connections(
 }
 
 /// Distributes the given number of items of the specified type to the output connections based on priority
-/// and round-robin strategy. Returns the number of items that could not be distributed.
+/// and round-robin strategy, spilling into the overflow outputs once neither can take more. Returns the number
+/// of items that could not be distributed.
+#[allow(clippy::too_many_arguments)]
 fn distribute_items(
+    item_count: u16,
+    item_type: ItemType,
+    priority_outputs: &mut [BeltOutputConnection],
+    rr_outputs: &mut [BeltOutputConnection],
+    rr_weights: &[u16],
+    rr_credits: &mut [i32],
+    rr_index: &mut usize,
+    overflow_outputs: &mut [BeltOutputConnection],
+) -> u16 {
+    let mut remaining_item_count = distribute_regular(
+        item_count,
+        item_type,
+        priority_outputs,
+        rr_outputs,
+        rr_weights,
+        rr_credits,
+        rr_index,
+    );
+
+    for output in overflow_outputs.iter_mut() {
+        if remaining_item_count == 0 {
+            break;
+        }
+        remaining_item_count = output.inc_item_count(item_type, remaining_item_count);
+    }
+
+    remaining_item_count
+}
+
+/// Distributes items to the priority outputs in order, then to the round robin outputs. Returns the number of
+/// items that could not be distributed.
+fn distribute_regular(
     mut remaining_item_count: u16,
     item_type: ItemType,
     priority_outputs: &mut [BeltOutputConnection],
     rr_outputs: &mut [BeltOutputConnection],
+    rr_weights: &[u16],
+    rr_credits: &mut [i32],
     rr_index: &mut usize,
 ) -> u16 {
     // first attempt to fill priority outputs in order
@@ -110,6 +175,17 @@ fn distribute_items(
         return remaining_item_count;
     }
 
+    if is_weighted(rr_weights) {
+        return distribute_weighted(
+            remaining_item_count,
+            item_type,
+            rr_outputs,
+            rr_weights,
+            rr_credits,
+            rr_index,
+        );
+    }
+
     /*
      * Round robin distribution can be "fast forwarded" with the following reasoning:
      * 1. Round robin distribution will first evenly fill all output belts that can accept the item type
@@ -137,19 +213,24 @@ fn distribute_items(
         let amount_per_belt = amount_to_distribute / num_rr_outputs;
         let leftover = amount_to_distribute % num_rr_outputs;
 
+        // The first `leftover` accepting outputs from the cursor receive one extra item.
+        let start = *rr_index;
+        let mut extra_given = 0;
         for i in 0..rr_outputs.len() {
-            let index = (*rr_index + i) % rr_outputs.len();
+            let index = (start + i) % rr_outputs.len();
             if !rr_outputs[index].can_take_item_type(item_type) {
                 continue;
             }
 
-            let to_give = if i < leftover as usize {
+            let to_give = if extra_given < leftover {
+                extra_given += 1;
                 *rr_index = (index + 1) % rr_outputs.len();
                 amount_per_belt + 1
             } else {
                 amount_per_belt
             };
-            debug_assert_eq!(rr_outputs[index].inc_item_count(item_type, to_give), 0);
+            let rejected = rr_outputs[index].inc_item_count(item_type, to_give);
+            debug_assert_eq!(rejected, 0);
         }
 
         remaining_item_count -= amount_to_distribute;
@@ -158,12 +239,88 @@ fn distribute_items(
     remaining_item_count
 }
 
-/// Runs the round robin loop once.
+/// Returns `true` if the rr outputs do not all share the same weight.
+fn is_weighted(rr_weights: &[u16]) -> bool {
+    rr_weights.windows(2).any(|pair| pair[0] != pair[1])
+}
+
+/// Picks the rr output the next item of `item_type` goes to under smooth weighted round robin, or `None` if no
+/// output can take it. Every output that can take the item earns its weight in credit, and the one with the most
+/// credit wins and pays back the total weight of the outputs that took part. Ties go to the first output from
+/// `rr_index`, which then moves past the winner.
+fn next_weighted_output(
+    item_type: ItemType,
+    rr_outputs: &[BeltOutputConnection],
+    rr_weights: &[u16],
+    rr_credits: &mut [i32],
+    rr_index: &mut usize,
+) -> Option<usize> {
+    debug_assert_eq!(rr_outputs.len(), rr_weights.len());
+    debug_assert_eq!(rr_outputs.len(), rr_credits.len());
+
+    let mut total_weight = 0;
+    let mut best: Option<usize> = None;
+    for i in 0..rr_outputs.len() {
+        let index = (*rr_index + i) % rr_outputs.len();
+        if rr_weights[index] == 0
+            || !rr_outputs[index].can_take_item_type(item_type)
+            || rr_outputs[index].max_acceptable_item_count() == 0
+        {
+            continue;
+        }
+
+        total_weight += rr_weights[index] as i32;
+        rr_credits[index] += rr_weights[index] as i32;
+        if best.is_none_or(|best| rr_credits[index] > rr_credits[best]) {
+            best = Some(index);
+        }
+    }
+
+    let best = best?;
+    rr_credits[best] -= total_weight;
+    *rr_index = (best + 1) % rr_outputs.len();
+    Some(best)
+}
+
+/// Distributes items to the round robin outputs in proportion to their weights. Returns the number of items
+/// that could not be distributed.
+fn distribute_weighted(
+    mut remaining_item_count: u16,
+    item_type: ItemType,
+    rr_outputs: &mut [BeltOutputConnection],
+    rr_weights: &[u16],
+    rr_credits: &mut [i32],
+    rr_index: &mut usize,
+) -> u16 {
+    /*
+     * Items go out one at a time so the credits carry the exact share each output is owed into the next tick.
+     * Splitting each tick's items by weight on its own would round every small batch the same way and skew the
+     * long run ratio whenever items arrive a few at a time.
+     */
+    while remaining_item_count > 0 {
+        let Some(index) =
+            next_weighted_output(item_type, rr_outputs, rr_weights, rr_credits, rr_index)
+        else {
+            break;
+        };
+
+        let rejected = rr_outputs[index].inc_item_count(item_type, 1);
+        debug_assert_eq!(rejected, 0);
+        remaining_item_count -= 1;
+    }
+
+    remaining_item_count
+}
+
+/// Runs the round robin loop once, moving at most `budget` items and reducing it by the number moved.
 fn rr_loop_once(
     rr_inputs: &mut [BeltInputConnection],
     rr_outputs: &mut [BeltOutputConnection],
+    rr_weights: &[u16],
+    rr_credits: &mut [i32],
     input_rr_index: &mut usize,
     output_rr_index: &mut usize,
+    budget: &mut u32,
 ) {
     if rr_inputs.is_empty() || rr_outputs.is_empty() {
         return;
@@ -171,6 +328,10 @@ fn rr_loop_once(
 
     // simulate 1-item at a time round robin assignment until everything is assigned or we looped through all inputs
     for i in 0..rr_inputs.len() {
+        if *budget == 0 {
+            return;
+        }
+
         let input_index = (*input_rr_index + i) % rr_inputs.len();
         let input_connection = &mut rr_inputs[input_index];
         let item_type = if let Some(item_type) = input_connection.current_item_type() {
@@ -179,24 +340,38 @@ fn rr_loop_once(
             continue;
         };
 
+        // weighted outputs take their turn by credit, so items primed here count towards their share
+        if is_weighted(rr_weights) {
+            if let Some(output_index) = next_weighted_output(
+                item_type,
+                rr_outputs,
+                rr_weights,
+                rr_credits,
+                output_rr_index,
+            ) {
+                let moved = transfer(input_connection, &mut rr_outputs[output_index], 1);
+                debug_assert_eq!(moved, 1);
+                *budget -= 1;
+            }
+            continue;
+        }
+
         // find the next output that can accept this item type, starting from output_rr_index
         for j in 0..rr_outputs.len() {
             let output_index = (*output_rr_index + j) % rr_outputs.len();
             let output_connection = &mut rr_outputs[output_index];
             if output_connection.can_take_item_type(item_type) {
                 // assign item type
-                output_connection.inc_item_count(item_type, 1);
-                input_connection.dec_item_count(1);
+                let moved = transfer(input_connection, output_connection, 1);
+                debug_assert_eq!(moved, 1);
+                *budget -= 1;
                 *output_rr_index = (output_index + 1) % rr_outputs.len();
                 break;
             }
         }
     }
 
-    // at this point every slot MUST have a slot assigned if the input belts are not empty
-    if rr_inputs.iter().any(|c| !c.is_empty()) {
-        debug_assert!(rr_outputs.iter().all(|c| !c.is_empty()))
-    }
+    // Each input primes at most one output, so with fewer inputs than outputs some outputs stay unassigned.
     // dont need to update input_rr_index here as we ran through each input once
 }
 
@@ -207,16 +382,273 @@ impl BufferedSplitter {
         priority_outputs: Vec<BeltOutputConnection>,
         rr_outputs: Vec<BeltOutputConnection>,
     ) -> Self {
+        let rr_weights = vec![1; rr_outputs.len()];
+        Self::with_weights(
+            priority_inputs,
+            rr_inputs,
+            priority_outputs,
+            rr_outputs,
+            rr_weights,
+        )
+    }
+
+    /// Creates a splitter like `new` whose round robin rotation starts at the given rr input and rr output,
+    /// e.g. picked from a seed so many splitters do not all favour their first outputs after a restart. The
+    /// indices are wrapped to the number of connections when the splitter runs, so any value is valid.
+    pub fn new_with_start(
+        priority_inputs: Vec<BeltInputConnection>,
+        rr_inputs: Vec<BeltInputConnection>,
+        priority_outputs: Vec<BeltOutputConnection>,
+        rr_outputs: Vec<BeltOutputConnection>,
+        input_start: usize,
+        output_start: usize,
+    ) -> Self {
+        let mut splitter = Self::new(priority_inputs, rr_inputs, priority_outputs, rr_outputs);
+        splitter.input_rr_index = input_start;
+        splitter.output_rr_index = output_start;
+        splitter
+    }
+
+    /// Creates a splitter whose round robin outputs receive items in proportion to `weights`, one non-zero
+    /// weight per rr output. Equal weights behave exactly like `new`. Panics if the weights do not match the rr
+    /// outputs one to one or any weight is zero.
+    pub fn with_weights(
+        priority_inputs: Vec<BeltInputConnection>,
+        rr_inputs: Vec<BeltInputConnection>,
+        priority_outputs: Vec<BeltOutputConnection>,
+        rr_outputs: Vec<BeltOutputConnection>,
+        weights: Vec<u16>,
+    ) -> Self {
+        assert_eq!(
+            weights.len(),
+            rr_outputs.len(),
+            "expected one weight per rr output"
+        );
+        assert!(
+            weights.iter().all(|&weight| weight > 0),
+            "rr output weights must be non-zero"
+        );
+
         Self {
             priority_inputs,
             rr_inputs,
             input_rr_index: 0,
             priority_outputs,
             rr_outputs,
+            rr_credits: vec![0; weights.len()],
+            rr_weights: weights,
             output_rr_index: 0,
+            overflow_outputs: Vec::new(),
+            filter_deadlocked: false,
+            max_priority_share: None,
+        }
+    }
+
+    /// Sets the overflow outputs. They are filled in order, and only with items that no priority or round
+    /// robin output can accept.
+    pub fn set_overflow_outputs(&mut self, overflow_outputs: Vec<BeltOutputConnection>) {
+        self.overflow_outputs = overflow_outputs;
+    }
+
+    pub fn overflow_outputs(&self) -> &[BeltOutputConnection] {
+        &self.overflow_outputs
+    }
+
+    pub fn priority_inputs(&self) -> &[BeltInputConnection] {
+        &self.priority_inputs
+    }
+
+    pub fn rr_inputs(&self) -> &[BeltInputConnection] {
+        &self.rr_inputs
+    }
+
+    pub fn priority_outputs(&self) -> &[BeltOutputConnection] {
+        &self.priority_outputs
+    }
+
+    pub fn rr_outputs(&self) -> &[BeltOutputConnection] {
+        &self.rr_outputs
+    }
+
+    /*
+     * The mutable accessors hand out slices rather than the vectors themselves: callers may fill or drain
+     * buffers between runs, but cannot add or remove connections, which would invalidate the round robin
+     * indices and the per-output weights.
+     */
+
+    pub fn priority_inputs_mut(&mut self) -> &mut [BeltInputConnection] {
+        &mut self.priority_inputs
+    }
+
+    pub fn rr_inputs_mut(&mut self) -> &mut [BeltInputConnection] {
+        &mut self.rr_inputs
+    }
+
+    pub fn priority_outputs_mut(&mut self) -> &mut [BeltOutputConnection] {
+        &mut self.priority_outputs
+    }
+
+    pub fn rr_outputs_mut(&mut self) -> &mut [BeltOutputConnection] {
+        &mut self.rr_outputs
+    }
+
+    /// Returns the round robin cursors as `(input_index, output_index)`, so they can be saved
+    /// alongside the connections and restored with `set_rr_state`.
+    pub fn rr_state(&self) -> (usize, usize) {
+        (self.input_rr_index, self.output_rr_index)
+    }
+
+    /// Restores the round robin cursors saved by `rr_state`. Indices past the current rr input or
+    /// output count are clamped to the last connection.
+    pub fn set_rr_state(&mut self, input_idx: usize, output_idx: usize) {
+        self.input_rr_index = input_idx.min(self.rr_inputs.len().saturating_sub(1));
+        self.output_rr_index = output_idx.min(self.rr_outputs.len().saturating_sub(1));
+    }
+
+    /// Returns the rr output the next round robin item goes to first, or `None` without rr outputs.
+    pub fn next_rr_output(&self) -> Option<usize> {
+        (!self.rr_outputs.is_empty()).then(|| self.output_rr_index % self.rr_outputs.len())
+    }
+
+    /// Returns the rr input the next round robin pass starts at, or `None` without rr inputs.
+    pub fn next_rr_input(&self) -> Option<usize> {
+        (!self.rr_inputs.is_empty()).then(|| self.input_rr_index % self.rr_inputs.len())
+    }
+
+    /// Caps how many items the priority inputs may move per tick. Once the cap is reached the rr inputs get their
+    /// turn, so a priority input that is always full cannot starve them. `None` removes the cap.
+    pub fn set_max_priority_share(&mut self, share: Option<u32>) {
+        self.max_priority_share = share;
+    }
+
+    pub fn max_priority_share(&self) -> Option<u32> {
+        self.max_priority_share
+    }
+
+    /// Returns `true` if the last run found items buffered on the inputs that no output's filter admits, so the
+    /// splitter cannot make progress until the filters or inputs change.
+    pub fn is_filter_deadlocked(&self) -> bool {
+        self.filter_deadlocked
+    }
+
+    /// Returns the item types waiting at the front of the priority and rr inputs, sorted and without duplicates.
+    pub fn pending_input_types(&self) -> Vec<ItemType> {
+        let mut types: Vec<_> = self
+            .priority_inputs
+            .iter()
+            .chain(self.rr_inputs.iter())
+            .filter_map(|c| c.current_item_type())
+            .collect();
+        types.sort_unstable();
+        types.dedup();
+        types
+    }
+
+    /// Returns how many items are buffered across every input and output, overflow outputs included. Running
+    /// the splitter only moves items between its connections, so this stays the same across `run`.
+    pub fn total_buffered_items(&self) -> u32 {
+        self.priority_inputs
+            .iter()
+            .chain(self.rr_inputs.iter())
+            .map(|input| input.buffered_item_count() as u32)
+            .chain(
+                self.all_outputs()
+                    .map(|output| output.buffered_item_count() as u32),
+            )
+            .sum()
+    }
+
+    /// Returns `true` if no output can take another item, so the feeding belts should stop. Overflow outputs
+    /// count too, since they still drain the inputs while they have room. A splitter without outputs is
+    /// always backpressured.
+    pub fn is_backpressured(&self) -> bool {
+        self.all_outputs()
+            .all(|output| output.max_acceptable_item_count() == 0)
+    }
+
+    /// Returns the indices of the outputs that cannot take another item. Priority outputs are numbered first,
+    /// followed by the rr outputs and then the overflow outputs.
+    pub fn saturated_outputs(&self) -> Vec<usize> {
+        self.all_outputs()
+            .enumerate()
+            .filter(|(_, output)| output.max_acceptable_item_count() == 0)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Moves already buffered items between rr outputs so outputs holding the same item type end up as evenly
+    /// filled as their capacities allow. Only outputs buffering a single run of that type take part, plus empty
+    /// outputs that can take it; outputs holding several types are left alone so their order is kept.
+    pub fn rebalance_outputs(&mut self) {
+        let mut types: Vec<_> = self
+            .rr_outputs
+            .iter()
+            .filter_map(|c| c.current_item_type())
+            .collect();
+        types.sort_unstable();
+        types.dedup();
+
+        for item_type in types {
+            let participants: Vec<_> = self
+                .rr_outputs
+                .iter()
+                .enumerate()
+                .filter(|(_, output)| match output.peek_buffer() {
+                    Some(front) => {
+                        front.item_type == item_type
+                            && front.item_count == output.buffered_item_count()
+                    }
+                    None => output.can_take_item_type(item_type),
+                })
+                .map(|(index, _)| index)
+                .collect();
+
+            loop {
+                let fill = |index: &usize| self.rr_outputs[*index].buffered_item_count();
+                let Some(donor) = participants.iter().copied().max_by_key(fill) else {
+                    break;
+                };
+                let Some(receiver) = participants
+                    .iter()
+                    .copied()
+                    .filter(|&index| self.rr_outputs[index].max_acceptable_item_count() > 0)
+                    .min_by_key(fill)
+                else {
+                    break;
+                };
+
+                let (most, least) = (fill(&donor), fill(&receiver));
+                if most <= least + 1 {
+                    break;
+                }
+
+                let amount =
+                    ((most - least) / 2).min(self.rr_outputs[receiver].max_acceptable_item_count());
+                let leftover = self.rr_outputs[receiver].inc_item_count(item_type, amount);
+                debug_assert_eq!(leftover, 0);
+                let leftover = self.rr_outputs[donor].dec_item_count(amount);
+                debug_assert_eq!(leftover, 0);
+            }
         }
     }
 
+    fn all_outputs(&self) -> impl Iterator<Item = &BeltOutputConnection> + Clone {
+        self.priority_outputs
+            .iter()
+            .chain(self.rr_outputs.iter())
+            .chain(self.overflow_outputs.iter())
+    }
+
+    fn inputs_blocked_by_filters(&self) -> bool {
+        let outputs = self.all_outputs();
+        let pending = self.pending_input_types();
+
+        !pending.is_empty()
+            && pending
+                .into_iter()
+                .all(|item_type| !outputs.clone().any(|c| c.accepts_item_type(item_type)))
+    }
+
     /// Runs a single "tick" of the buffered splitter, processing inputs and distributing items to outputs.
     /// The algorithm first drains from priority inputs to priority outputs, then to rr outputs,
     /// and finally drains from rr inputs to priority outputs and rr outputs.
@@ -227,7 +659,26 @@ impl BufferedSplitter {
     /// 3. Assign item types based on rr inputs and rr outputs
     /// 4. Drain from rr inputs to rr outputs
     pub fn run(&mut self) {
-        // First drain priority inputs
+        self.run_limited(u32::MAX);
+    }
+
+    /// Runs a single tick like `run`, but moves at most `max_items` items in total across all four steps and
+    /// returns how many were moved. The steps run in the same order, so a capped tick moves the items `run` would
+    /// have moved first, and the round robin indices stop where the tick did so the next call resumes fairly.
+    pub fn run_limited(&mut self, max_items: u32) -> u32 {
+        // Filters do not change during a tick, so if no output admits any buffered type nothing can move at all.
+        self.filter_deadlocked = self.inputs_blocked_by_filters();
+        if self.filter_deadlocked {
+            return 0;
+        }
+
+        let mut budget = max_items;
+
+        // First drain priority inputs, up to their share of the budget
+        let priority_limit = self
+            .max_priority_share
+            .map_or(budget, |share| share.min(budget));
+        let mut priority_budget = priority_limit;
         for input in self.priority_inputs.iter_mut() {
             // filter output connections by item type, skip if none
             let item_type = if let Some(item_type) = input.current_item_type() {
@@ -242,9 +693,14 @@ impl BufferedSplitter {
                 &mut self.input_rr_index,
                 self.priority_outputs.as_mut_slice(),
                 self.rr_outputs.as_mut_slice(),
+                &self.rr_weights,
+                &mut self.rr_credits,
                 &mut self.output_rr_index,
+                self.overflow_outputs.as_mut_slice(),
+                &mut priority_budget,
             );
         }
+        budget -= priority_limit - priority_budget;
 
         /*
          * Next drain rr inputs to priority outputs. As long as types match, this can proceed in any order.
@@ -266,7 +722,11 @@ impl BufferedSplitter {
                 &mut self.input_rr_index,
                 self.priority_outputs.as_mut_slice(),
                 &mut [],
+                &[],
+                &mut [],
                 &mut temp,
+                &mut [],
+                &mut budget,
             );
             debug_assert_eq!(temp, 0);
         }
@@ -278,8 +738,11 @@ impl BufferedSplitter {
         rr_loop_once(
             self.rr_inputs.as_mut_slice(),
             self.rr_outputs.as_mut_slice(),
+            &self.rr_weights,
+            &mut self.rr_credits,
             &mut self.input_rr_index,
             &mut self.output_rr_index,
+            &mut budget,
         );
 
         /*
@@ -301,9 +764,15 @@ impl BufferedSplitter {
                 &mut self.input_rr_index,
                 self.priority_outputs.as_mut_slice(),
                 self.rr_outputs.as_mut_slice(),
+                &self.rr_weights,
+                &mut self.rr_credits,
                 &mut self.output_rr_index,
+                self.overflow_outputs.as_mut_slice(),
+                &mut budget,
             );
         }
+
+        max_items - budget
     }
 }
 
@@ -441,7 +910,7 @@ mod tests {
         let output_1 = BeltOutputConnection::new(input_limits, 1, None);
         let output_2 = BeltOutputConnection::new(input_limits, 1, None);
 
-        let item_type = 1;
+        let item_type = ItemType::new(1);
         let item_count = 5;
         input_1.inc_item_count(item_type, item_count);
         input_2.inc_item_count(item_type, item_count);
@@ -464,7 +933,7 @@ mod tests {
 
     #[test]
     fn test_buffered_splitter_rr_simple_2() {
-        let item_type = 1;
+        let item_type = ItemType::new(1);
         let item_count: u16 = 6;
         let item_limit = item_count * 2;
         let mut input_1 = BeltInputConnection::new(item_limit, None);
@@ -494,7 +963,7 @@ mod tests {
 
     #[test]
     fn test_buffered_splitter_rr_simple_3() {
-        let item_type = 1;
+        let item_type = ItemType::new(1);
         let item_count: u16 = 6;
         let item_limit = item_count * 2;
         let mut input_1 = BeltInputConnection::new(item_limit, None);
@@ -527,7 +996,7 @@ mod tests {
 
     #[test]
     fn test_buffered_splitter_priority_inputs_fill_before_rr_distribution() {
-        const ITEM_TYPE: ItemType = 1;
+        const ITEM_TYPE: ItemType = ItemType::new(1);
         const PRIORITY_OUTPUT_LIMIT: u16 = 5;
         const RR_OUTPUT_LIMIT: u16 = 6;
         const PRIORITY_INPUT_COUNTS: [u16; 2] = [4, 3];
@@ -634,7 +1103,7 @@ mod tests {
 
     #[test]
     fn test_buffered_splitter_unbalanced_rr_output_capacity() {
-        const ITEM_TYPE: ItemType = 2;
+        const ITEM_TYPE: ItemType = ItemType::new(2);
         const PRIORITY_OUTPUT_LIMIT: u16 = 4;
         const RR_OUTPUT_STRONG_LIMIT: u16 = 8;
         const RR_OUTPUT_WEAK_LIMIT: u16 = 3;
@@ -722,8 +1191,8 @@ mod tests {
 
     #[test]
     fn test_buffered_splitter_mixed_item_types() {
-        const ITEM_A: ItemType = 1;
-        const ITEM_B: ItemType = 2;
+        const ITEM_A: ItemType = ItemType::new(1);
+        const ITEM_B: ItemType = ItemType::new(2);
         const PRIORITY_OUTPUT_LIMIT: u16 = 3;
         const RR_OUTPUT_LIMIT: u16 = 3;
         const PRIORITY_INPUTS: [(ItemType, u16); 2] = [(ITEM_A, 2), (ITEM_B, 1)];
@@ -828,7 +1297,7 @@ mod tests {
 
     #[test]
     fn test_buffered_splitter_high_volume_partial_drain() {
-        const ITEM_TYPE: ItemType = 3;
+        const ITEM_TYPE: ItemType = ItemType::new(3);
         const PRIORITY_INPUT_LIMIT: u16 = 220;
         const RR_INPUT_LIMIT: u16 = 260;
         const RR_INPUT_COUNTS: [u16; 2] = [220, 180];
@@ -929,4 +1398,642 @@ mod tests {
         assert_eq!(actual_rr_outputs, expected_rr_outputs);
         assert_eq!(rr_inputs_after, reference_rr_inputs);
     }
+
+    #[test]
+    fn test_buffered_splitter_weighted_rr_outputs() {
+        const ITEM_TYPE: ItemType = ItemType::new(4);
+        const ITEM_COUNT: u16 = 9;
+        const WEIGHTS: [u16; 2] = [2, 1];
+        const EXPECTED_RR_OUTPUT_COUNTS: [u16; 2] = [6, 3];
+
+        let mut rr_input = BeltInputConnection::new(ITEM_COUNT, None);
+        assert_eq!(rr_input.inc_item_count(ITEM_TYPE, ITEM_COUNT), 0);
+        let rr_outputs = vec![
+            BeltOutputConnection::new(ITEM_COUNT, 1, None),
+            BeltOutputConnection::new(ITEM_COUNT, 1, None),
+        ];
+
+        let mut splitter = BufferedSplitter::with_weights(
+            vec![],
+            vec![rr_input],
+            vec![],
+            rr_outputs,
+            WEIGHTS.to_vec(),
+        );
+        splitter.run();
+
+        let actual_rr_outputs = [
+            splitter.rr_outputs[0].buffered_item_count(),
+            splitter.rr_outputs[1].buffered_item_count(),
+        ];
+        assert_eq!(actual_rr_outputs, EXPECTED_RR_OUTPUT_COUNTS);
+        assert!(splitter.rr_inputs[0].is_empty());
+    }
+
+    #[test]
+    fn test_buffered_splitter_weighted_rr_outputs_respect_capacity() {
+        const ITEM_TYPE: ItemType = ItemType::new(4);
+        const ITEM_COUNT: u16 = 9;
+        const WEIGHTS: [u16; 2] = [2, 1];
+        const RR_OUTPUT_LIMITS: [u16; 2] = [4, 10];
+        const EXPECTED_RR_OUTPUT_COUNTS: [u16; 2] = [4, 5];
+
+        // The heavier output fills up early, so the lighter one absorbs the overflow.
+        let mut rr_input = BeltInputConnection::new(ITEM_COUNT, None);
+        assert_eq!(rr_input.inc_item_count(ITEM_TYPE, ITEM_COUNT), 0);
+        let rr_outputs = RR_OUTPUT_LIMITS
+            .iter()
+            .map(|&limit| BeltOutputConnection::new(limit, 1, None))
+            .collect();
+
+        let mut splitter = BufferedSplitter::with_weights(
+            vec![],
+            vec![rr_input],
+            vec![],
+            rr_outputs,
+            WEIGHTS.to_vec(),
+        );
+        splitter.run();
+
+        let actual_rr_outputs = [
+            splitter.rr_outputs[0].buffered_item_count(),
+            splitter.rr_outputs[1].buffered_item_count(),
+        ];
+        assert_eq!(actual_rr_outputs, EXPECTED_RR_OUTPUT_COUNTS);
+        assert!(splitter.rr_inputs[0].is_empty());
+    }
+
+    #[test]
+    fn test_buffered_splitter_weighted_rr_outputs_trickle_feed() {
+        const ITEM_TYPE: ItemType = ItemType::new(4);
+        const TICKS: u16 = 30;
+        const WEIGHTS: [u16; 2] = [2, 1];
+        const EXPECTED_RR_OUTPUT_COUNTS: [u16; 2] = [20, 10];
+
+        // One item arrives per tick, so every item goes through the priming step on its own.
+        let rr_outputs = vec![
+            BeltOutputConnection::new(TICKS, 1, None),
+            BeltOutputConnection::new(TICKS, 1, None),
+        ];
+        let mut splitter = BufferedSplitter::with_weights(
+            vec![],
+            vec![BeltInputConnection::new(1, None)],
+            vec![],
+            rr_outputs,
+            WEIGHTS.to_vec(),
+        );
+        for _ in 0..TICKS {
+            assert_eq!(splitter.rr_inputs_mut()[0].inc_item_count(ITEM_TYPE, 1), 0);
+            splitter.run();
+            assert!(splitter.rr_inputs[0].is_empty());
+        }
+
+        let actual_rr_outputs = [
+            splitter.rr_outputs[0].buffered_item_count(),
+            splitter.rr_outputs[1].buffered_item_count(),
+        ];
+        assert_eq!(actual_rr_outputs, EXPECTED_RR_OUTPUT_COUNTS);
+    }
+
+    #[test]
+    #[should_panic(expected = "rr output weights must be non-zero")]
+    fn test_buffered_splitter_rejects_zero_weight() {
+        let rr_outputs = vec![
+            BeltOutputConnection::new(1, 1, None),
+            BeltOutputConnection::new(1, 1, None),
+        ];
+        BufferedSplitter::with_weights(vec![], vec![], vec![], rr_outputs, vec![1, 0]);
+    }
+
+    #[test]
+    fn test_buffered_splitter_overflow_takes_remainder() {
+        const ITEM_TYPE: ItemType = ItemType::new(5);
+        const ITEM_COUNT: u16 = 10;
+        const RR_OUTPUT_LIMIT: u16 = 3;
+        const EXPECTED_RR_OUTPUT_COUNTS: [u16; 2] = [3, 3];
+        const EXPECTED_OVERFLOW_COUNT: u16 = 4;
+
+        let mut rr_input = BeltInputConnection::new(ITEM_COUNT, None);
+        assert_eq!(rr_input.inc_item_count(ITEM_TYPE, ITEM_COUNT), 0);
+        let rr_outputs = vec![
+            BeltOutputConnection::new(RR_OUTPUT_LIMIT, 1, None),
+            BeltOutputConnection::new(RR_OUTPUT_LIMIT, 1, None),
+        ];
+
+        let mut splitter = BufferedSplitter::new(vec![], vec![rr_input], vec![], rr_outputs);
+        splitter.set_overflow_outputs(vec![BeltOutputConnection::new(ITEM_COUNT, 1, None)]);
+        splitter.run();
+
+        let actual_rr_outputs = [
+            splitter.rr_outputs[0].buffered_item_count(),
+            splitter.rr_outputs[1].buffered_item_count(),
+        ];
+        assert_eq!(actual_rr_outputs, EXPECTED_RR_OUTPUT_COUNTS);
+        assert_eq!(
+            splitter.overflow_outputs()[0].buffered_item_count(),
+            EXPECTED_OVERFLOW_COUNT
+        );
+        assert!(splitter.rr_inputs[0].is_empty());
+    }
+
+    #[test]
+    fn test_buffered_splitter_overflow_unused_with_spare_capacity() {
+        const ITEM_TYPE: ItemType = ItemType::new(5);
+        const ITEM_COUNT: u16 = 6;
+        const PRIORITY_OUTPUT_LIMIT: u16 = 2;
+        const RR_OUTPUT_LIMIT: u16 = 4;
+        const EXPECTED_RR_OUTPUT_COUNTS: [u16; 2] = [2, 2];
+
+        let mut priority_input = BeltInputConnection::new(ITEM_COUNT, None);
+        assert_eq!(priority_input.inc_item_count(ITEM_TYPE, ITEM_COUNT), 0);
+        let rr_outputs = vec![
+            BeltOutputConnection::new(RR_OUTPUT_LIMIT, 1, None),
+            BeltOutputConnection::new(RR_OUTPUT_LIMIT, 1, None),
+        ];
+
+        let mut splitter = BufferedSplitter::new(
+            vec![priority_input],
+            vec![],
+            vec![BeltOutputConnection::new(PRIORITY_OUTPUT_LIMIT, 1, None)],
+            rr_outputs,
+        );
+        splitter.set_overflow_outputs(vec![BeltOutputConnection::new(ITEM_COUNT, 1, None)]);
+        splitter.run();
+
+        assert_eq!(
+            splitter.priority_outputs[0].buffered_item_count(),
+            PRIORITY_OUTPUT_LIMIT
+        );
+        let actual_rr_outputs = [
+            splitter.rr_outputs[0].buffered_item_count(),
+            splitter.rr_outputs[1].buffered_item_count(),
+        ];
+        assert_eq!(actual_rr_outputs, EXPECTED_RR_OUTPUT_COUNTS);
+        assert!(splitter.overflow_outputs()[0].is_empty());
+        assert!(splitter.priority_inputs[0].is_empty());
+    }
+
+    #[test]
+    fn test_buffered_splitter_restored_rr_state_matches_uninterrupted() {
+        const ITEM_TYPE: ItemType = ItemType::new(6);
+
+        let refill = |splitter: &mut BufferedSplitter| {
+            assert_eq!(splitter.rr_inputs[0].inc_item_count(ITEM_TYPE, 3), 0);
+        };
+
+        let mut uninterrupted = BufferedSplitter::new(
+            vec![],
+            vec![BeltInputConnection::new(3, None)],
+            vec![],
+            vec![
+                BeltOutputConnection::new(10, 1, None),
+                BeltOutputConnection::new(10, 1, None),
+            ],
+        );
+        refill(&mut uninterrupted);
+        uninterrupted.run();
+        assert_eq!(uninterrupted.rr_state(), (0, 1));
+
+        // Rebuild from the saved connections, as a save/load round trip would.
+        let (input_idx, output_idx) = uninterrupted.rr_state();
+        let mut restored = BufferedSplitter::new(
+            vec![],
+            uninterrupted.rr_inputs.clone(),
+            vec![],
+            uninterrupted.rr_outputs.clone(),
+        );
+        restored.set_rr_state(input_idx, output_idx);
+
+        refill(&mut uninterrupted);
+        uninterrupted.run();
+        refill(&mut restored);
+        restored.run();
+
+        for (restored, expected) in restored.rr_outputs.iter().zip(&uninterrupted.rr_outputs) {
+            assert_eq!(
+                restored.buffered_item_count(),
+                expected.buffered_item_count()
+            );
+        }
+        assert_eq!(uninterrupted.rr_outputs[0].buffered_item_count(), 3);
+        assert_eq!(restored.rr_state(), uninterrupted.rr_state());
+    }
+
+    #[test]
+    fn test_buffered_splitter_set_rr_state_clamps() {
+        let mut splitter = BufferedSplitter::new(
+            vec![],
+            vec![BeltInputConnection::new(1, None)],
+            vec![],
+            vec![
+                BeltOutputConnection::new(1, 1, None),
+                BeltOutputConnection::new(1, 1, None),
+            ],
+        );
+
+        splitter.set_rr_state(4, 9);
+        assert_eq!(splitter.rr_state(), (0, 1));
+    }
+
+    #[test]
+    fn test_buffered_splitter_accessors_feed_and_drain() {
+        const ITEM_TYPE: ItemType = ItemType::new(7);
+
+        let mut splitter = BufferedSplitter::new(
+            vec![BeltInputConnection::new(4, None)],
+            vec![BeltInputConnection::new(4, None)],
+            vec![BeltOutputConnection::new(2, 1, None)],
+            vec![BeltOutputConnection::new(4, 1, None)],
+        );
+
+        assert_eq!(
+            splitter.priority_inputs_mut()[0].inc_item_count(ITEM_TYPE, 3),
+            0
+        );
+        assert_eq!(splitter.rr_inputs_mut()[0].inc_item_count(ITEM_TYPE, 1), 0);
+        splitter.run();
+
+        assert!(splitter.priority_inputs()[0].is_empty());
+        assert!(splitter.rr_inputs()[0].is_empty());
+        assert_eq!(splitter.priority_outputs()[0].buffered_item_count(), 2);
+        assert_eq!(splitter.rr_outputs()[0].buffered_item_count(), 2);
+
+        let drained = splitter.rr_outputs_mut()[0]
+            .take_all()
+            .expect("items buffered");
+        assert_eq!(drained.num_stacks(), 2);
+        assert!(splitter.rr_outputs()[0].is_empty());
+        assert_eq!(splitter.priority_outputs_mut()[0].dec_item_count(2), 0);
+    }
+
+    #[test]
+    fn test_buffered_splitter_run_limited_caps_items_moved() {
+        const ITEM_TYPE: ItemType = ItemType::new(8);
+
+        let mut splitter = BufferedSplitter::new(
+            vec![BeltInputConnection::new(2, None)],
+            vec![BeltInputConnection::new(4, None)],
+            vec![BeltOutputConnection::new(10, 1, None)],
+            vec![],
+        );
+        assert_eq!(splitter.priority_inputs[0].inc_item_count(ITEM_TYPE, 2), 0);
+        assert_eq!(splitter.rr_inputs[0].inc_item_count(ITEM_TYPE, 4), 0);
+
+        assert_eq!(splitter.run_limited(3), 3);
+        // priority inputs drain first
+        assert!(splitter.priority_inputs[0].is_empty());
+        assert_eq!(splitter.rr_inputs[0].buffered_item_count(), 3);
+        assert_eq!(splitter.priority_outputs[0].buffered_item_count(), 3);
+
+        assert_eq!(splitter.run_limited(3), 3);
+        assert_eq!(splitter.run_limited(3), 0);
+        assert_eq!(splitter.priority_outputs[0].buffered_item_count(), 6);
+    }
+
+    #[test]
+    fn test_buffered_splitter_run_limited_resumes_round_robin() {
+        const ITEM_TYPE: ItemType = ItemType::new(8);
+
+        let build = || {
+            let mut splitter = BufferedSplitter::new(
+                vec![],
+                vec![
+                    BeltInputConnection::new(6, None),
+                    BeltInputConnection::new(6, None),
+                ],
+                vec![],
+                vec![
+                    BeltOutputConnection::new(6, 1, None),
+                    BeltOutputConnection::new(6, 1, None),
+                    BeltOutputConnection::new(6, 1, None),
+                ],
+            );
+            for input in splitter.rr_inputs.iter_mut() {
+                assert_eq!(input.inc_item_count(ITEM_TYPE, 6), 0);
+            }
+            splitter
+        };
+
+        let mut limited = build();
+        let mut moved = 0;
+        while moved < 12 {
+            let step = limited.run_limited(5);
+            assert!(step <= 5);
+            moved += step;
+        }
+        assert_eq!(limited.run_limited(5), 0);
+
+        let mut unlimited = build();
+        unlimited.run();
+
+        for output in limited.rr_outputs.iter() {
+            assert_eq!(output.buffered_item_count(), 4);
+        }
+        for (input_limited, input_unlimited) in limited.rr_inputs.iter().zip(&unlimited.rr_inputs) {
+            assert_eq!(input_limited.buffered_item_count(), 0);
+            assert_eq!(input_unlimited.buffered_item_count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_buffered_splitter_priority_share_lets_rr_inputs_through() {
+        const ITEM_TYPE: ItemType = ItemType::new(3);
+
+        let build = || {
+            let mut splitter = BufferedSplitter::new(
+                vec![BeltInputConnection::new(100, None)],
+                vec![BeltInputConnection::new(5, None)],
+                vec![],
+                vec![BeltOutputConnection::new(20, 1, None)],
+            );
+            assert_eq!(
+                splitter.priority_inputs[0].inc_item_count(ITEM_TYPE, 100),
+                0
+            );
+            assert_eq!(splitter.rr_inputs[0].inc_item_count(ITEM_TYPE, 5), 0);
+            splitter
+        };
+
+        // without a cap the priority input fills the output and the rr input never moves
+        let mut uncapped = build();
+        assert_eq!(uncapped.run_limited(u32::MAX), 20);
+        assert_eq!(uncapped.rr_inputs[0].buffered_item_count(), 5);
+
+        let mut capped = build();
+        capped.set_max_priority_share(Some(10));
+        assert_eq!(capped.run_limited(u32::MAX), 15);
+        assert_eq!(capped.priority_inputs[0].buffered_item_count(), 90);
+        assert!(capped.rr_inputs[0].is_empty());
+        assert_eq!(capped.rr_outputs[0].buffered_item_count(), 15);
+    }
+
+    #[test]
+    fn test_buffered_splitter_next_rr_indices_advance_after_run() {
+        const ITEM_TYPE: ItemType = ItemType::new(6);
+
+        let build = |output_limit| {
+            let splitter = BufferedSplitter::new(
+                vec![],
+                vec![
+                    BeltInputConnection::new(4, None),
+                    BeltInputConnection::new(4, None),
+                ],
+                vec![],
+                (0..3)
+                    .map(|_| BeltOutputConnection::new(output_limit, 1, None))
+                    .collect(),
+            );
+            assert_eq!(splitter.next_rr_input(), Some(0));
+            assert_eq!(splitter.next_rr_output(), Some(0));
+            splitter
+        };
+
+        let mut splitter = build(4);
+        assert_eq!(splitter.rr_inputs[0].inc_item_count(ITEM_TYPE, 1), 0);
+        splitter.run();
+        assert_eq!(splitter.next_rr_output(), Some(1));
+
+        // three items leave two equally full inputs, so the first one gives the extra item
+        let mut splitter = build(1);
+        for input in splitter.rr_inputs_mut() {
+            assert_eq!(input.inc_item_count(ITEM_TYPE, 3), 0);
+        }
+        splitter.run();
+        assert_eq!(splitter.total_buffered_items(), 6);
+        assert_eq!(splitter.next_rr_input(), Some(1));
+
+        // cursors set past the connection count wrap like they do on the next run
+        let wrapped = BufferedSplitter::new_with_start(
+            vec![],
+            vec![BeltInputConnection::new(1, None); 2],
+            vec![],
+            vec![BeltOutputConnection::new(1, 1, None); 3],
+            5,
+            7,
+        );
+        assert_eq!(wrapped.next_rr_input(), Some(1));
+        assert_eq!(wrapped.next_rr_output(), Some(1));
+
+        let idle = BufferedSplitter::new(vec![], vec![], vec![], vec![]);
+        assert_eq!(idle.next_rr_input(), None);
+        assert_eq!(idle.next_rr_output(), None);
+    }
+
+    #[test]
+    fn test_buffered_splitter_start_index_picks_the_first_rr_output() {
+        const ITEM_TYPE: ItemType = ItemType::new(6);
+
+        let build = |output_start| {
+            let mut splitter = BufferedSplitter::new_with_start(
+                vec![],
+                vec![BeltInputConnection::new(4, None)],
+                vec![],
+                vec![
+                    BeltOutputConnection::new(4, 1, None),
+                    BeltOutputConnection::new(4, 1, None),
+                ],
+                0,
+                output_start,
+            );
+            assert_eq!(splitter.rr_inputs[0].inc_item_count(ITEM_TYPE, 1), 0);
+            splitter.run();
+            splitter
+        };
+
+        let first = build(0);
+        let second = build(3);
+        assert_eq!(first.rr_outputs[0].buffered_item_count(), 1);
+        assert!(first.rr_outputs[1].is_empty());
+        assert!(second.rr_outputs[0].is_empty());
+        assert_eq!(second.rr_outputs[1].buffered_item_count(), 1);
+    }
+
+    #[test]
+    fn test_buffered_splitter_filter_deadlock_terminates() {
+        let item_a = ItemType::new(1);
+        let item_b = ItemType::new(2);
+
+        let mut splitter = BufferedSplitter::new(
+            vec![BeltInputConnection::new(4, None)],
+            vec![BeltInputConnection::new(4, None)],
+            vec![BeltOutputConnection::new(4, 1, Some(vec![item_b]))],
+            vec![BeltOutputConnection::new(4, 1, Some(vec![item_b]))],
+        );
+        assert_eq!(splitter.priority_inputs[0].inc_item_count(item_a, 2), 0);
+        assert_eq!(splitter.rr_inputs[0].inc_item_count(item_a, 3), 0);
+
+        splitter.run();
+        assert!(splitter.is_filter_deadlocked());
+        assert_eq!(splitter.priority_inputs[0].buffered_item_count(), 2);
+        assert_eq!(splitter.rr_inputs[0].buffered_item_count(), 3);
+        assert!(splitter.priority_outputs[0].is_empty());
+        assert!(splitter.rr_outputs[0].is_empty());
+
+        // An output that admits the type clears the deadlock.
+        splitter.set_overflow_outputs(vec![BeltOutputConnection::new(10, 1, None)]);
+        splitter.run();
+        assert!(!splitter.is_filter_deadlocked());
+        assert_eq!(splitter.overflow_outputs()[0].buffered_item_count(), 5);
+    }
+
+    #[test]
+    fn test_buffered_splitter_pending_input_types() {
+        let item_a = ItemType::new(3);
+        let item_b = ItemType::new(1);
+
+        let mut splitter = BufferedSplitter::new(
+            vec![
+                BeltInputConnection::new(4, None),
+                BeltInputConnection::new(4, None),
+            ],
+            vec![
+                BeltInputConnection::new(4, None),
+                BeltInputConnection::new(4, None),
+                BeltInputConnection::new(4, None),
+            ],
+            vec![],
+            vec![],
+        );
+        assert!(splitter.pending_input_types().is_empty());
+
+        assert_eq!(splitter.priority_inputs[0].inc_item_count(item_a, 1), 0);
+        assert_eq!(splitter.rr_inputs[0].inc_item_count(item_b, 2), 0);
+        assert_eq!(splitter.rr_inputs[2].inc_item_count(item_a, 2), 0);
+
+        assert_eq!(splitter.pending_input_types(), vec![item_b, item_a]);
+    }
+
+    fn fill(output: &mut BeltOutputConnection, item_count: u16) {
+        let item_type = ItemType::new(1);
+        assert_eq!(output.inc_item_count(item_type, item_count), 0);
+    }
+
+    #[test]
+    fn test_buffered_splitter_backpressure_when_all_outputs_full() {
+        let mut splitter = BufferedSplitter::new(
+            vec![],
+            vec![BeltInputConnection::new(4, None)],
+            vec![BeltOutputConnection::new(2, 1, None)],
+            vec![
+                BeltOutputConnection::new(3, 1, None),
+                BeltOutputConnection::new(3, 1, None),
+            ],
+        );
+        fill(&mut splitter.priority_outputs[0], 2);
+        fill(&mut splitter.rr_outputs[0], 3);
+        fill(&mut splitter.rr_outputs[1], 3);
+
+        assert!(splitter.is_backpressured());
+        assert_eq!(splitter.saturated_outputs(), vec![0, 1, 2]);
+
+        // an overflow output with room keeps the inputs draining
+        splitter.set_overflow_outputs(vec![BeltOutputConnection::new(3, 1, None)]);
+        assert!(!splitter.is_backpressured());
+        assert_eq!(splitter.saturated_outputs(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_buffered_splitter_partial_saturation() {
+        let mut splitter = BufferedSplitter::new(
+            vec![],
+            vec![BeltInputConnection::new(4, None)],
+            vec![BeltOutputConnection::new(2, 1, None)],
+            vec![
+                BeltOutputConnection::new(3, 1, None),
+                BeltOutputConnection::new(3, 1, None),
+            ],
+        );
+        fill(&mut splitter.rr_outputs[1], 3);
+        fill(&mut splitter.priority_outputs[0], 1);
+
+        assert!(!splitter.is_backpressured());
+        assert_eq!(splitter.saturated_outputs(), vec![2]);
+    }
+
+    #[test]
+    fn test_buffered_splitter_rebalance_outputs_evens_fill() {
+        let mut splitter = BufferedSplitter::new(
+            vec![],
+            vec![BeltInputConnection::new(4, None)],
+            vec![],
+            vec![
+                BeltOutputConnection::new(6, 1, None),
+                BeltOutputConnection::new(6, 1, None),
+            ],
+        );
+        fill(&mut splitter.rr_outputs[0], 6);
+
+        splitter.rebalance_outputs();
+        assert_eq!(splitter.rr_outputs[0].buffered_item_count(), 3);
+        assert_eq!(splitter.rr_outputs[1].buffered_item_count(), 3);
+
+        // a smaller output is filled only up to its capacity
+        let mut splitter = BufferedSplitter::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![
+                BeltOutputConnection::new(9, 1, None),
+                BeltOutputConnection::new(2, 1, None),
+                BeltOutputConnection::new(9, 1, Some(vec![ItemType::new(2)])),
+            ],
+        );
+        fill(&mut splitter.rr_outputs[0], 9);
+
+        splitter.rebalance_outputs();
+        assert_eq!(splitter.rr_outputs[0].buffered_item_count(), 7);
+        assert_eq!(splitter.rr_outputs[1].buffered_item_count(), 2);
+        assert!(splitter.rr_outputs[2].is_empty());
+    }
+
+    #[test]
+    fn test_buffered_splitter_run_conserves_total_items() {
+        const ITEM_TYPE: ItemType = ItemType::new(5);
+
+        let mut priority_input = BeltInputConnection::new(7, None);
+        assert_eq!(priority_input.inc_item_count(ITEM_TYPE, 7), 0);
+        let mut rr_input = BeltInputConnection::new(10, None);
+        assert_eq!(rr_input.inc_item_count(ITEM_TYPE, 10), 0);
+
+        let mut splitter = BufferedSplitter::new(
+            vec![priority_input],
+            vec![rr_input],
+            vec![BeltOutputConnection::new(2, 1, None)],
+            vec![
+                BeltOutputConnection::new(3, 1, None),
+                BeltOutputConnection::new(4, 1, None),
+            ],
+        );
+        splitter.set_overflow_outputs(vec![BeltOutputConnection::new(2, 1, None)]);
+        assert_eq!(splitter.total_buffered_items(), 17);
+
+        splitter.run();
+        assert!(splitter.is_backpressured());
+        assert_eq!(splitter.total_buffered_items(), 17);
+
+        assert!(splitter.rr_outputs_mut()[1].take_all().is_some());
+        splitter.run();
+        assert_eq!(splitter.total_buffered_items(), 13);
+    }
+
+    #[test]
+    fn test_buffered_splitter_backpressure_without_outputs() {
+        let splitter = BufferedSplitter::new(
+            vec![BeltInputConnection::new(4, None)],
+            vec![],
+            vec![],
+            vec![],
+        );
+        assert!(splitter.is_backpressured());
+        assert!(splitter.saturated_outputs().is_empty());
+
+        let idle = BufferedSplitter::new(
+            vec![],
+            vec![BeltInputConnection::new(4, None)],
+            vec![],
+            vec![BeltOutputConnection::new(3, 1, None)],
+        );
+        assert!(!idle.is_backpressured());
+        assert!(idle.saturated_outputs().is_empty());
+    }
 }
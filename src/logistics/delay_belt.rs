@@ -0,0 +1,130 @@
+use crate::logistics::{Belt, Stack};
+use std::collections::VecDeque;
+
+/**
+ * A belt whose output is held back for a fixed number of ticks, modeling processing latency
+ * independently of the belt length. Every tick the inner belt runs for one tick and the stacks
+ * that reached its front are moved into a delay queue; a stack that left the belt at tick `T`
+ * can be taken with `remove_item` from tick `T + delay` on. Stacks come out one copy at a time,
+ * in the order they left the belt.
+ *
+ * Each tick takes up to `max_throughput_per_tick` identical copies off the front, and at least
+ * one. The inner belt's input connection keeps feeding it, but it should have no output
+ * connection, since that would take the stacks before they reach the delay queue.
+ */
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DelayBelt {
+    belt: Belt,
+    delay: u32,
+    // Ticks run so far.
+    tick: u64,
+    // Stacks that left the belt, oldest first, with the tick they become available.
+    delayed: VecDeque<(u64, Stack)>,
+}
+
+impl DelayBelt {
+    pub fn new(belt: Belt, delay: u32) -> Self {
+        Self {
+            belt,
+            delay,
+            tick: 0,
+            delayed: VecDeque::new(),
+        }
+    }
+
+    pub fn belt(&self) -> &Belt {
+        &self.belt
+    }
+
+    pub fn belt_mut(&mut self) -> &mut Belt {
+        &mut self.belt
+    }
+
+    pub fn delay(&self) -> u32 {
+        self.delay
+    }
+
+    /// Returns how many stacks have left the inner belt but were not taken yet, including the
+    /// ones still waiting out their delay.
+    pub fn delayed_count(&self) -> usize {
+        self.delayed.len()
+    }
+
+    /// Runs the inner belt `ticks` ticks, one at a time, queueing whatever reaches its front.
+    pub fn run(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.tick += 1;
+            self.belt.run(1);
+
+            let max_copies = self.belt.max_throughput_per_tick().max(1);
+            if let Some(stack) = self.belt.remove_front_run(max_copies) {
+                let ready_at = self.tick + self.delay as u64;
+                for copy in stack.explode() {
+                    self.delayed.push_back((ready_at, copy));
+                }
+            }
+        }
+    }
+
+    /// Removes and returns the oldest stack whose delay has passed, or `None` if there is none.
+    pub fn remove_item(&mut self) -> Option<Stack> {
+        let (ready_at, _) = self.delayed.front()?;
+        if *ready_at > self.tick {
+            return None;
+        }
+
+        self.delayed.pop_front().map(|(_, stack)| stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ITEM_WIDTH;
+
+    #[test]
+    fn items_surface_after_the_delay() {
+        const DELAY: u32 = 5;
+
+        // The stack has to travel three slots at half a slot per tick.
+        const REMOVED_AT: u32 = 6;
+
+        let mut belt = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH / 2);
+        assert!(belt.add_item(Stack::new(1, 3)));
+        let mut delayed = DelayBelt::new(belt, DELAY);
+
+        delayed.run(REMOVED_AT - 1);
+        assert_eq!(delayed.belt().item_count(), 1);
+        assert_eq!(delayed.delayed_count(), 0);
+        delayed.run(1);
+        assert!(delayed.belt().is_empty());
+        assert_eq!(delayed.delayed_count(), 1);
+
+        for _ in 0..DELAY {
+            assert_eq!(delayed.remove_item(), None);
+            delayed.run(1);
+        }
+        assert_eq!(delayed.remove_item(), Some(Stack::new(1, 3)));
+        assert_eq!(delayed.remove_item(), None);
+        assert_eq!(delayed.delayed_count(), 0);
+    }
+
+    #[test]
+    fn zero_delay_surfaces_in_the_same_tick() {
+        let layout = [(
+            Stack {
+                multiplicity: 2,
+                ..Stack::new(4, 1)
+            },
+            0,
+        )];
+        let belt = Belt::from_layout(ITEM_WIDTH * 2, ITEM_WIDTH * 2, &layout).unwrap();
+        let mut delayed = DelayBelt::new(belt, 0);
+
+        delayed.run(1);
+        assert_eq!(delayed.remove_item(), Some(Stack::new(4, 1)));
+        assert_eq!(delayed.remove_item(), Some(Stack::new(4, 1)));
+        assert_eq!(delayed.remove_item(), None);
+    }
+}
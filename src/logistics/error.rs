@@ -0,0 +1,31 @@
+use std::fmt;
+
+/// Why a logistics operation could not place or accept a stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogisticsError {
+    /// There is not enough free space or buffer capacity for the stack.
+    NoSpace,
+    /// The destination already holds a different item type and cannot take another one.
+    TypeMismatch,
+    /// The destination's item filter rejects the stack's item type.
+    FilterRejected,
+    /// The stack, or a configured stack size, holds no items.
+    InvalidStackSize,
+    /// The operation only handles single stacks, but the stack has a multiplicity above 1.
+    MultiplicityUnsupported,
+}
+
+impl fmt::Display for LogisticsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            LogisticsError::NoSpace => "not enough space for the stack",
+            LogisticsError::TypeMismatch => "a different item type is already held",
+            LogisticsError::FilterRejected => "the item filter rejects this item type",
+            LogisticsError::InvalidStackSize => "stack size must be non-zero",
+            LogisticsError::MultiplicityUnsupported => "only single stacks are supported",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for LogisticsError {}
@@ -0,0 +1,286 @@
+use crate::logistics::{Connection, FilterMode, LogisticsError, Stack};
+use crate::types::ItemType;
+
+/**
+ * A connection that buffers a continuous volume of a single fluid instead of discrete stacks.
+ * The volume is tracked as a `u32`, so it can exceed what the stack-oriented `Connection` methods
+ * can express; those report the volume and limit clamped to `u16::MAX`. Use `add_volume` and
+ * `drain_volume` to move larger amounts at once. Stack multiplicity only scales the volume
+ * offered, and there is no output stack size: whatever is buffered is handed out as one run.
+ * The item filter works exactly like it does on belt connections.
+ */
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FluidConnection {
+    volume_limit: u32,
+    item_filter: Option<Vec<ItemType>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    item_filter_mode: FilterMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_buffer: Option<u16>,
+    volume: u32,
+    // The buffered fluid and its clamped volume, kept in sync so `peek_buffer` can lend it out.
+    // `None` while the buffer is empty.
+    front: Option<Stack>,
+}
+
+fn clamp_to_u16(volume: u32) -> u16 {
+    volume.min(u16::MAX as u32) as u16
+}
+
+impl FluidConnection {
+    pub fn new(volume_limit: u32, item_filter: Option<Vec<ItemType>>) -> Self {
+        Self {
+            volume_limit,
+            item_filter,
+            item_filter_mode: FilterMode::Allow,
+            max_buffer: None,
+            volume: 0,
+            front: None,
+        }
+    }
+
+    pub fn volume_limit(&self) -> u32 {
+        self.volume_limit
+    }
+
+    /// Returns the buffered volume without clamping.
+    pub fn volume(&self) -> u32 {
+        self.volume
+    }
+
+    // The volume the buffer may actually hold: `volume_limit`, lowered by `max_buffer`.
+    fn effective_limit(&self) -> u32 {
+        self.max_buffer
+            .map_or(self.volume_limit, |cap| self.volume_limit.min(cap as u32))
+    }
+
+    fn free_volume(&self) -> u32 {
+        self.effective_limit().saturating_sub(self.volume)
+    }
+
+    // Returns true if `fluid` passes the filter and does not mix with a different buffered fluid.
+    fn can_hold(&self, fluid: ItemType) -> bool {
+        self.accepts_item_type(fluid)
+            && self
+                .front
+                .as_ref()
+                .is_none_or(|front| front.item_type == fluid)
+    }
+
+    /// Adds up to `volume` of `fluid` and returns the volume that did not fit. Nothing is added
+    /// if the filter rejects `fluid` or a different fluid is buffered.
+    pub fn add_volume(&mut self, fluid: ItemType, volume: u32) -> u32 {
+        if !self.can_hold(fluid) {
+            return volume;
+        }
+
+        let added = volume.min(self.free_volume());
+        if added > 0 {
+            self.volume += added;
+            self.sync_front(fluid);
+        }
+        volume - added
+    }
+
+    /// Removes up to `volume` from the buffer and returns the volume that could not be removed.
+    pub fn drain_volume(&mut self, volume: u32) -> u32 {
+        let Some(fluid) = self.front.as_ref().map(|front| front.item_type) else {
+            return volume;
+        };
+
+        let removed = volume.min(self.volume);
+        self.volume -= removed;
+        self.sync_front(fluid);
+        volume - removed
+    }
+
+    fn check_accept_stack(&self, stack: &Stack) -> Result<(), LogisticsError> {
+        let volume = stack.item_count as u32 * stack.multiplicity;
+        if volume == 0 {
+            return Ok(());
+        }
+
+        if !self.accepts_item_type(stack.item_type) {
+            Err(LogisticsError::FilterRejected)
+        } else if !self.can_hold(stack.item_type) {
+            Err(LogisticsError::TypeMismatch)
+        } else if volume > self.free_volume() {
+            Err(LogisticsError::NoSpace)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn sync_front(&mut self, fluid: ItemType) {
+        self.front = Stack::try_new(fluid, clamp_to_u16(self.volume));
+    }
+}
+
+impl Connection for FluidConnection {
+    fn item_limit(&self) -> u16 {
+        clamp_to_u16(self.volume_limit)
+    }
+
+    fn item_filter(&self) -> Option<&[ItemType]> {
+        self.item_filter.as_deref()
+    }
+
+    fn set_item_filter(&mut self, filter: Option<Vec<ItemType>>) {
+        self.item_filter = filter;
+    }
+
+    fn item_filter_mode(&self) -> FilterMode {
+        self.item_filter_mode
+    }
+
+    fn set_item_filter_mode(&mut self, mode: FilterMode) {
+        self.item_filter_mode = mode;
+    }
+
+    fn accepts_item_type(&self, item_type: ItemType) -> bool {
+        self.item_filter_mode.allows(self.item_filter(), item_type)
+    }
+
+    fn buffered_item_count(&self) -> u16 {
+        clamp_to_u16(self.volume)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.volume == 0
+    }
+
+    fn current_item_type(&self) -> Option<ItemType> {
+        self.front.as_ref().map(|front| front.item_type)
+    }
+
+    fn peek_buffer(&self) -> Option<&Stack> {
+        self.front.as_ref()
+    }
+
+    fn can_take_item_type(&self, item_type: ItemType) -> bool {
+        self.can_hold(item_type) && self.free_volume() > 0
+    }
+
+    fn can_take_item_count(&self, item_count: u16) -> bool {
+        item_count as u32 <= self.free_volume()
+    }
+
+    fn can_accept_stack(&self, stack: &Stack) -> bool {
+        self.check_accept_stack(stack).is_ok()
+    }
+
+    fn try_accept_stack(&mut self, stack: &Stack) -> Result<(), LogisticsError> {
+        self.check_accept_stack(stack)?;
+
+        let leftover = self.add_volume(
+            stack.item_type,
+            stack.item_count as u32 * stack.multiplicity,
+        );
+        debug_assert_eq!(leftover, 0);
+        Ok(())
+    }
+
+    fn accept_stacks(&mut self, stack: &Stack) -> u32 {
+        let single = Stack::new(stack.item_type, stack.item_count);
+        let accepted = stack.multiplicity.min(self.max_acceptable_stacks(&single));
+        if stack.item_count > 0 && accepted > 0 {
+            let leftover = self.add_volume(stack.item_type, accepted * stack.item_count as u32);
+            debug_assert_eq!(leftover, 0);
+        }
+        accepted
+    }
+
+    fn inc_item_count(&mut self, item_type: ItemType, item_count: u16) -> u16 {
+        self.add_volume(item_type, item_count as u32) as u16
+    }
+
+    fn dec_item_count(&mut self, item_count: u16) -> u16 {
+        self.drain_volume(item_count as u32) as u16
+    }
+
+    fn max_acceptable_item_count(&self) -> u16 {
+        clamp_to_u16(self.free_volume())
+    }
+
+    fn max_acceptable_stacks(&self, stack: &Stack) -> u32 {
+        if stack.multiplicity != 1 || !self.accepts_item_type(stack.item_type) {
+            return 0;
+        }
+        if stack.item_count == 0 {
+            return u32::MAX;
+        }
+        if !self.can_hold(stack.item_type) {
+            return 0;
+        }
+
+        self.free_volume() / stack.item_count as u32
+    }
+
+    fn max_buffer(&self) -> Option<u16> {
+        self.max_buffer
+    }
+
+    fn set_max_buffer(&mut self, cap: Option<u16>) {
+        self.max_buffer = cap;
+    }
+
+    fn clear(&mut self) -> Vec<Stack> {
+        let Some(fluid) = self.current_item_type() else {
+            return Vec::new();
+        };
+
+        let mut drained = Vec::new();
+        while self.volume > 0 {
+            let part = clamp_to_u16(self.volume);
+            drained.push(Stack::new(fluid, part));
+            self.volume -= part as u32;
+        }
+        self.front = None;
+        drained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WATER: ItemType = ItemType::new(1);
+    const OIL: ItemType = ItemType::new(2);
+
+    #[test]
+    fn over_fill_is_clamped_to_the_limit() {
+        let mut pipe = FluidConnection::new(100_000, None);
+        assert_eq!(pipe.add_volume(WATER, 120_000), 20_000);
+        assert_eq!(pipe.volume(), 100_000);
+        assert_eq!(pipe.buffered_item_count(), u16::MAX);
+        assert_eq!(pipe.peek_buffer(), Some(&Stack::new(WATER, u16::MAX)));
+        assert_eq!(pipe.max_acceptable_item_count(), 0);
+        assert!(!pipe.can_take_item_type(WATER));
+
+        // another fluid never mixes in, even with room to spare
+        assert_eq!(pipe.drain_volume(50_000), 0);
+        assert_eq!(pipe.add_volume(OIL, 10), 10);
+        assert_eq!(pipe.inc_item_count(WATER, 10), 0);
+        assert_eq!(pipe.volume(), 50_010);
+    }
+
+    #[test]
+    fn partial_drain_leaves_the_rest() {
+        let mut pipe = FluidConnection::new(1_000, Some(vec![OIL]));
+        assert_eq!(pipe.inc_item_count(WATER, 10), 10);
+        assert_eq!(pipe.add_volume(OIL, 300), 0);
+
+        assert_eq!(pipe.dec_item_count(120), 0);
+        assert_eq!(pipe.buffered_item_count(), 180);
+        assert_eq!(pipe.drain_volume(500), 320);
+        assert!(pipe.is_empty());
+        assert_eq!(pipe.current_item_type(), None);
+
+        // once empty the pipe takes whatever its filter allows
+        pipe.set_item_filter(None);
+        assert!(pipe.accept_stack(&Stack::new(WATER, 40)));
+        assert_eq!(pipe.clear(), vec![Stack::new(WATER, 40)]);
+        assert!(pipe.is_empty());
+    }
+}
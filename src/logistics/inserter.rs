@@ -0,0 +1,226 @@
+use crate::logistics::{Belt, Stack, Storage};
+use crate::types::ItemType;
+
+/// One end of an inserter: it takes from the head of a belt or from a storage, and puts onto the
+/// tail of a belt or into a storage.
+#[derive(Debug)]
+pub enum InserterEnd<'a> {
+    Belt(&'a mut Belt),
+    Storage(&'a mut Storage),
+}
+
+/**
+ * An inserter moves items from a source to a destination, up to `items_per_tick` items per tick.
+ * Belt stacks are moved whole, so a belt stack is only picked up if it fits in what is left of
+ * this tick's allowance; the first stack of a tick is always allowed so oversized stacks still move.
+ * Items taken from a storage are grouped into one stack per move. An optional item filter limits
+ * which types are moved; a belt whose head item is filtered out blocks the inserter.
+ */
+#[derive(Debug, Clone)]
+pub struct Inserter {
+    items_per_tick: u16,
+    item_filter: Option<Vec<ItemType>>,
+}
+
+impl Inserter {
+    pub fn new(items_per_tick: u16) -> Self {
+        Self {
+            items_per_tick,
+            item_filter: None,
+        }
+    }
+
+    pub fn items_per_tick(&self) -> u16 {
+        self.items_per_tick
+    }
+
+    /// Restricts the inserter to the listed item types. `None` moves every type.
+    pub fn set_item_filter(&mut self, item_filter: Option<Vec<ItemType>>) {
+        self.item_filter = item_filter;
+    }
+
+    pub fn item_filter(&self) -> Option<&[ItemType]> {
+        self.item_filter.as_deref()
+    }
+
+    fn allows(&self, item_type: ItemType) -> bool {
+        self.item_filter
+            .as_ref()
+            .is_none_or(|filter| filter.contains(&item_type))
+    }
+
+    /// Runs a single tick, moving items from `source` to `dest`. Returns how many items were moved.
+    pub fn run(&mut self, mut source: InserterEnd, mut dest: InserterEnd) -> u32 {
+        let mut moved: u32 = 0;
+        loop {
+            let allowance = (self.items_per_tick as u32).saturating_sub(moved);
+            if allowance == 0 {
+                break;
+            }
+
+            let Some(stack) = self.next_stack(&source, &dest, moved == 0, allowance as u16) else {
+                break;
+            };
+
+            let taken = match &mut source {
+                InserterEnd::Belt(belt) => belt.remove_item(),
+                InserterEnd::Storage(storage) => {
+                    Some(storage.extract(stack.item_type, stack.item_count))
+                }
+            };
+            debug_assert_eq!(taken.as_ref(), Some(&stack));
+
+            match &mut dest {
+                InserterEnd::Belt(belt) => {
+                    let added = belt.add_item(stack.clone());
+                    debug_assert!(added);
+                }
+                InserterEnd::Storage(storage) => {
+                    let leftover = storage.insert(stack.clone());
+                    debug_assert!(leftover.is_none());
+                }
+            }
+            moved += stack.item_count as u32;
+        }
+
+        moved
+    }
+
+    // Picks the next stack to move, sized to fit both the allowance and the destination.
+    fn next_stack(
+        &self,
+        source: &InserterEnd,
+        dest: &InserterEnd,
+        first_move: bool,
+        allowance: u16,
+    ) -> Option<Stack> {
+        match source {
+            InserterEnd::Belt(belt) => {
+                let (stack, _) = belt.peek_front_stack()?;
+                let fits_allowance = first_move || stack.item_count <= allowance;
+                (fits_allowance && self.allows(stack.item_type) && Self::dest_takes(dest, &stack))
+                    .then_some(stack)
+            }
+            InserterEnd::Storage(storage) => {
+                let mut item_types: Vec<_> = storage
+                    .contents()
+                    .iter()
+                    .flatten()
+                    .map(|stack| stack.item_type)
+                    .filter(|&item_type| self.allows(item_type))
+                    .collect();
+                item_types.dedup();
+
+                item_types.into_iter().find_map(|item_type| {
+                    let available = storage.count_of(item_type).min(allowance as u32);
+                    let count = match dest {
+                        InserterEnd::Belt(_) => available,
+                        InserterEnd::Storage(target) => {
+                            available.min(target.free_capacity_for(item_type))
+                        }
+                    };
+                    let stack = Stack::try_new(item_type, count as u16)?;
+                    Self::dest_takes(dest, &stack).then_some(stack)
+                })
+            }
+        }
+    }
+
+    fn dest_takes(dest: &InserterEnd, stack: &Stack) -> bool {
+        match dest {
+            InserterEnd::Belt(belt) => belt.can_add(stack),
+            InserterEnd::Storage(storage) => {
+                storage.free_capacity_for(stack.item_type) >= stack.item_count as u32
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ITEM_WIDTH;
+
+    fn loaded_belt(stacks: &[Stack]) -> Belt {
+        let mut belt = Belt::new(ITEM_WIDTH * stacks.len() as u32, ITEM_WIDTH);
+        for stack in stacks {
+            belt.run(1);
+            assert!(belt.add_item(stack.clone()));
+        }
+        belt.run(ITEM_WIDTH);
+        belt
+    }
+
+    #[test]
+    fn belt_to_storage_takes_stacks_as_they_arrive() {
+        let mut belt = loaded_belt(&[Stack::new(1, 2), Stack::new(1, 2), Stack::new(1, 2)]);
+        let mut storage = Storage::new(2, 10);
+        let mut inserter = Inserter::new(4);
+
+        // Removing the head stack leaves a gap, so only one stack is reachable per tick.
+        for _ in 0..2 {
+            let moved = inserter.run(
+                InserterEnd::Belt(&mut belt),
+                InserterEnd::Storage(&mut storage),
+            );
+            assert_eq!(moved, 2);
+            belt.run(1);
+        }
+        assert_eq!(belt.item_count(), 1);
+        assert_eq!(storage.count_of(ItemType::new(1)), 4);
+    }
+
+    #[test]
+    fn belt_stack_larger_than_rate_still_moves() {
+        let mut belt = loaded_belt(&[Stack::new(1, 5)]);
+        let mut storage = Storage::new(1, 10);
+        let mut inserter = Inserter::new(3);
+
+        let moved = inserter.run(
+            InserterEnd::Belt(&mut belt),
+            InserterEnd::Storage(&mut storage),
+        );
+        assert_eq!(moved, 5);
+        assert!(belt.is_empty());
+    }
+
+    #[test]
+    fn storage_to_belt_forms_one_stack() {
+        let mut storage = Storage::new(2, 5);
+        assert_eq!(storage.insert(Stack::new(2, 8)), None);
+        let mut belt = Belt::new(ITEM_WIDTH * 2, ITEM_WIDTH);
+        let mut inserter = Inserter::new(6);
+
+        let moved = inserter.run(
+            InserterEnd::Storage(&mut storage),
+            InserterEnd::Belt(&mut belt),
+        );
+        assert_eq!(moved, 6);
+        assert_eq!(belt.peek_back_stack(), Some(&Stack::new(2, 6)));
+        assert_eq!(storage.count_of(ItemType::new(2)), 2);
+    }
+
+    #[test]
+    fn filter_blocks_available_item() {
+        let mut belt = loaded_belt(&[Stack::new(3, 1)]);
+        let mut storage = Storage::new(1, 10);
+        let mut inserter = Inserter::new(5);
+        inserter.set_item_filter(Some(vec![ItemType::new(4)]));
+
+        let moved = inserter.run(
+            InserterEnd::Belt(&mut belt),
+            InserterEnd::Storage(&mut storage),
+        );
+        assert_eq!(moved, 0);
+        assert_eq!(belt.item_count(), 1);
+
+        assert_eq!(storage.insert(Stack::new(3, 2)), None);
+        let mut out = Belt::new(ITEM_WIDTH, ITEM_WIDTH);
+        let moved = inserter.run(
+            InserterEnd::Storage(&mut storage),
+            InserterEnd::Belt(&mut out),
+        );
+        assert_eq!(moved, 0);
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::logistics::{Belt, LogisticsError};
+
+/**
+ * A merger that combines several input belts onto a single output belt. Inputs are visited in
+ * round-robin order, and the index of the next input is kept between ticks so no input is
+ * favored when the output is the bottleneck.
+ */
+#[derive(Debug, Default)]
+pub struct Merger {
+    input_rr_index: usize,
+}
+
+impl Merger {
+    pub fn new() -> Self {
+        Self { input_rr_index: 0 }
+    }
+
+    /// Runs a single tick of the merger. Stacks are taken from the head of each input belt in
+    /// turn and appended to the tail of `output` until the output has no room left or no input
+    /// has a stack ready at its head. An input whose head stack the output refuses for any other
+    /// reason, such as its item filter, is skipped so the remaining inputs keep flowing.
+    pub fn run(&mut self, inputs: &mut [&mut Belt], output: &mut Belt) {
+        let input_len = inputs.len();
+        if input_len == 0 {
+            self.input_rr_index = 0;
+            return;
+        }
+
+        if self.input_rr_index >= input_len {
+            self.input_rr_index %= input_len;
+        }
+
+        let mut progress = true;
+        while progress {
+            progress = false;
+
+            for offset in 0..input_len {
+                let idx = (self.input_rr_index + offset) % input_len;
+                let belt = &mut *inputs[idx];
+
+                let Some((stack, _)) = belt.peek_front_stack() else {
+                    continue;
+                };
+
+                match output.try_add_item(stack) {
+                    Ok(()) => {}
+                    // The output is full; every other input would be refused as well.
+                    Err(LogisticsError::NoSpace) => return,
+                    Err(_) => continue,
+                }
+
+                let removed = belt.remove_item();
+                debug_assert!(removed.is_some());
+                self.input_rr_index = (idx + 1) % input_len;
+                progress = true;
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logistics::Stack;
+    use crate::types::{ITEM_WIDTH, ItemType};
+
+    fn loaded_input(item_type: u16) -> Belt {
+        let mut belt = Belt::new(ITEM_WIDTH * 2, ITEM_WIDTH);
+        assert!(belt.add_item(Stack::new(item_type, 1)));
+        belt.run(1);
+        assert!(belt.add_item(Stack::new(item_type, 1)));
+        belt
+    }
+
+    #[test]
+    fn simple_rr_merge_test() {
+        let mut merger = Merger::new();
+        let mut input_a = loaded_input(1);
+        let mut input_b = loaded_input(2);
+        let mut output = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH);
+
+        for _ in 0..4 {
+            let mut inputs = vec![&mut input_a, &mut input_b];
+            merger.run(inputs.as_mut_slice(), &mut output);
+            drop(inputs);
+
+            output.run(1);
+            input_a.run(1);
+            input_b.run(1);
+        }
+
+        assert!(input_a.is_empty());
+        assert!(input_b.is_empty());
+        let merged: Vec<_> = output
+            .remove_while_run(ITEM_WIDTH * 4, None, None)
+            .iter()
+            .map(|stack| stack.item_type)
+            .collect();
+        assert_eq!(merged, [1, 2, 1, 2].map(ItemType::new));
+    }
+
+    #[test]
+    fn merge_skips_empty_inputs() {
+        let mut merger = Merger::new();
+        let mut empty = Belt::new(ITEM_WIDTH, 1);
+        let mut input = loaded_input(3);
+        let mut output = Belt::new(ITEM_WIDTH * 2, 1);
+
+        let mut inputs = vec![&mut empty, &mut input];
+        merger.run(inputs.as_mut_slice(), &mut output);
+        drop(inputs);
+
+        assert_eq!(input.item_count(), 1);
+        assert_eq!(output.item_count(), 1);
+    }
+
+    #[test]
+    fn merge_skips_inputs_the_output_filters_out() {
+        let mut merger = Merger::new();
+        let mut rejected = loaded_input(5);
+        let mut accepted = loaded_input(6);
+        let mut output = Belt::new(ITEM_WIDTH * 2, 1);
+        output.set_item_filter(Some(vec![ItemType::new(6)]));
+
+        let mut inputs = vec![&mut rejected, &mut accepted];
+        merger.run(inputs.as_mut_slice(), &mut output);
+        drop(inputs);
+
+        assert_eq!(rejected.item_count(), 2);
+        assert_eq!(accepted.item_count(), 1);
+        assert_eq!(output.count_of(ItemType::new(6)), 1);
+    }
+
+    #[test]
+    fn merge_stalls_when_output_full() {
+        let mut merger = Merger::new();
+        let mut input = loaded_input(4);
+        let mut output = Belt::new(ITEM_WIDTH, 1);
+        assert!(output.add_item(Stack::new(4, 1)));
+
+        let mut inputs = vec![&mut input];
+        merger.run(inputs.as_mut_slice(), &mut output);
+        drop(inputs);
+
+        assert_eq!(input.item_count(), 2);
+        assert_eq!(output.item_count(), 1);
+    }
+}
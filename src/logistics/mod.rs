@@ -1,14 +1,35 @@
 //! Core logistics primitives and data structures for moving item stacks.
 
+pub mod balancer;
 pub mod belt;
 pub mod belt_connection;
+pub mod buffer_connection;
 pub mod buffered_splitter;
+pub mod delay_belt;
+pub mod error;
+pub mod fluid_connection;
+pub mod inserter;
+pub mod merger;
+pub mod pass_through;
 pub mod splitter;
 pub mod stack;
+pub mod storage;
 
 // Re-export the main types for easier access
-pub use belt::Belt;
-pub use belt_connection::{BeltInputConnection, BeltOutputConnection, Connection, OutputBatch};
+pub use balancer::Balancer;
+pub use belt::{Belt, BeltMetrics, DrainWhileRun, GroupInfo, RunOutcome, run_chain};
+pub use belt_connection::{
+    BeltInputConnection, BeltInputConnectionBuilder, BeltOutputConnection,
+    BeltOutputConnectionBuilder, Connection, ConnectionCallback, FilterMode, OutputBatch, transfer,
+};
+pub use buffer_connection::BufferConnection;
 pub use buffered_splitter::BufferedSplitter;
-pub use splitter::Splitter;
+pub use delay_belt::DelayBelt;
+pub use error::LogisticsError;
+pub use fluid_connection::FluidConnection;
+pub use inserter::{Inserter, InserterEnd};
+pub use merger::Merger;
+pub use pass_through::PassThroughConnection;
+pub use splitter::{SplitPlan, SplitSource, Splitter, SplitterMode, SplitterStats};
 pub use stack::Stack;
+pub use storage::Storage;
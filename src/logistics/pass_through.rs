@@ -0,0 +1,176 @@
+use crate::logistics::{Belt, Connection, FilterMode, LogisticsError, Stack};
+use crate::types::ItemType;
+
+/**
+ * A connection without a buffer of its own that forwards everything to a target connection.
+ * Capacity and acceptance checks report the target's real state, and accepted items land in the
+ * target's buffer straight away, so wiring one belt into another through a pass-through behaves
+ * like a single continuous belt with no extra buffering latency. Filter and buffer settings are
+ * forwarded to the target as well. Since nothing is ever held here, the pass-through always reads
+ * as empty and has nothing to hand out or clear.
+ */
+pub struct PassThroughConnection<'a> {
+    target: &'a mut dyn Connection,
+}
+
+impl<'a> PassThroughConnection<'a> {
+    pub fn new(target: &'a mut dyn Connection) -> Self {
+        Self { target }
+    }
+
+    /// Forwards into `belt`'s input connection. Returns `None` if the belt has none.
+    pub fn for_belt(belt: &'a mut Belt) -> Option<Self> {
+        let target = belt.input_connection_mut()?;
+        Some(Self::new(target))
+    }
+
+    pub fn target(&self) -> &dyn Connection {
+        self.target
+    }
+}
+
+impl Connection for PassThroughConnection<'_> {
+    fn item_limit(&self) -> u16 {
+        self.target.item_limit()
+    }
+
+    fn item_filter(&self) -> Option<&[ItemType]> {
+        self.target.item_filter()
+    }
+
+    fn set_item_filter(&mut self, filter: Option<Vec<ItemType>>) {
+        self.target.set_item_filter(filter);
+    }
+
+    fn item_filter_mode(&self) -> FilterMode {
+        self.target.item_filter_mode()
+    }
+
+    fn set_item_filter_mode(&mut self, mode: FilterMode) {
+        self.target.set_item_filter_mode(mode);
+    }
+
+    fn accepts_item_type(&self, item_type: ItemType) -> bool {
+        self.target.accepts_item_type(item_type)
+    }
+
+    fn buffered_item_count(&self) -> u16 {
+        0
+    }
+
+    fn is_empty(&self) -> bool {
+        true
+    }
+
+    fn current_item_type(&self) -> Option<ItemType> {
+        None
+    }
+
+    fn peek_buffer(&self) -> Option<&Stack> {
+        None
+    }
+
+    fn can_take_item_type(&self, item_type: ItemType) -> bool {
+        self.target.can_take_item_type(item_type)
+    }
+
+    fn can_take_item_count(&self, item_count: u16) -> bool {
+        self.target.can_take_item_count(item_count)
+    }
+
+    fn can_accept_stack(&self, stack: &Stack) -> bool {
+        self.target.can_accept_stack(stack)
+    }
+
+    fn can_accept_as_full_stack(&self, stack: &Stack) -> bool {
+        self.target.can_accept_as_full_stack(stack)
+    }
+
+    fn try_accept_stack(&mut self, stack: &Stack) -> Result<(), LogisticsError> {
+        self.target.try_accept_stack(stack)
+    }
+
+    fn accept_stacks(&mut self, stack: &Stack) -> u32 {
+        self.target.accept_stacks(stack)
+    }
+
+    fn inc_item_count(&mut self, item_type: ItemType, item_count: u16) -> u16 {
+        self.target.inc_item_count(item_type, item_count)
+    }
+
+    fn dec_item_count(&mut self, item_count: u16) -> u16 {
+        item_count
+    }
+
+    fn max_acceptable_item_count(&self) -> u16 {
+        self.target.max_acceptable_item_count()
+    }
+
+    fn max_acceptable_stacks(&self, stack: &Stack) -> u32 {
+        self.target.max_acceptable_stacks(stack)
+    }
+
+    fn max_buffer(&self) -> Option<u16> {
+        self.target.max_buffer()
+    }
+
+    fn set_max_buffer(&mut self, cap: Option<u16>) {
+        self.target.set_max_buffer(cap);
+    }
+
+    fn clear(&mut self) -> Vec<Stack> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logistics::{BeltInputConnection, BeltOutputConnection, transfer};
+    use crate::types::ITEM_WIDTH;
+
+    #[test]
+    fn items_cross_into_the_next_belt_in_one_tick() {
+        let mut upstream = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH);
+        upstream.set_output_connection(Some(BeltInputConnection::new(3, None)));
+        let mut downstream = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH);
+        downstream.set_input_connection(Some(BeltOutputConnection::new(3, 1, None)));
+
+        assert!(upstream.add_item(Stack::new(5, 1)));
+        upstream.run(4);
+        assert_eq!(
+            upstream.output_connection().unwrap().buffered_item_count(),
+            1
+        );
+
+        let mut pass_through = PassThroughConnection::for_belt(&mut downstream).unwrap();
+        let moved = transfer(
+            upstream.output_connection_mut().unwrap(),
+            &mut pass_through,
+            u32::MAX,
+        );
+        assert_eq!(moved, 1);
+        assert!(pass_through.is_empty());
+
+        downstream.run(1);
+        assert!(upstream.output_connection().unwrap().is_empty());
+        assert_eq!(downstream.item_count(), 1);
+        assert_eq!(downstream.peek_back_stack(), Some(&Stack::new(5, 1)));
+    }
+
+    #[test]
+    fn reports_target_capacity_without_inflation() {
+        let item_type = ItemType::new(2);
+        let mut target = BeltInputConnection::new(4, Some(vec![item_type]));
+        assert_eq!(target.inc_item_count(item_type, 3), 0);
+
+        let mut pass_through = PassThroughConnection::new(&mut target);
+        assert_eq!(pass_through.item_limit(), 4);
+        assert_eq!(pass_through.max_acceptable_item_count(), 1);
+        assert!(!pass_through.can_take_item_type(ItemType::new(9)));
+        assert_eq!(pass_through.inc_item_count(item_type, 5), 4);
+        assert_eq!(pass_through.max_acceptable_item_count(), 0);
+        assert_eq!(pass_through.buffered_item_count(), 0);
+        assert_eq!(pass_through.target().buffered_item_count(), 4);
+    }
+}
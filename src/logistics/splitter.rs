@@ -1,15 +1,79 @@
 use crate::logistics::{Belt, Stack};
 
+/// Counts of the stacks a `Splitter` moved during one `run`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SplitterStats {
+    /// Total stacks moved from inputs to outputs.
+    pub moved: u32,
+    /// Stacks placed on priority outputs.
+    pub priority_moved: u32,
+    /// Stacks placed on round-robin outputs.
+    pub rr_moved: u32,
+}
+
+/// Identifies the input belt a planned move takes its stacks from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitSource {
+    /// Index into the priority inputs.
+    Priority(usize),
+    /// Index into the round-robin inputs.
+    RoundRobin(usize),
+}
+
+/// What a `Splitter::run` would do, as worked out by `Splitter::plan`. Each output lists the stacks
+/// it would receive in placement order, paired with the input they come from. A stack's
+/// multiplicity is the number of identical copies placed together.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplitPlan {
+    pub priority_outputs: Vec<Vec<(SplitSource, Stack)>>,
+    pub rr_outputs: Vec<Vec<(SplitSource, Stack)>>,
+}
+
+/// How a `Splitter` picks the round robin output for each stack.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SplitterMode {
+    /// Outputs take turns, following the round robin index.
+    #[default]
+    RoundRobin,
+    /// Each stack goes to the output with the most free slots. Ties go to the output the round
+    /// robin index would pick first.
+    LeastFull,
+}
+
+// Collects the stats of a run and, when planning, every move it makes.
+#[derive(Default)]
+struct Recorder {
+    stats: SplitterStats,
+    plan: Option<SplitPlan>,
+}
+
+impl Recorder {
+    fn priority(&mut self, source: SplitSource, output: usize, stack: Stack) {
+        self.stats.priority_moved += stack.multiplicity;
+        if let Some(plan) = self.plan.as_mut() {
+            plan.priority_outputs[output].push((source, stack));
+        }
+    }
+
+    fn rr(&mut self, source: SplitSource, output: usize, stack: Stack) {
+        self.stats.rr_moved += stack.multiplicity;
+        if let Some(plan) = self.plan.as_mut() {
+            plan.rr_outputs[output].push((source, stack));
+        }
+    }
+}
+
 /**
  * A splitter that interacts directly with belts instead of intermediate buffers.
  * Belts are grouped into priority and round-robin (RR) sets for both inputs and outputs.
  * Priority groups are processed greedily in order, while RR groups advance internal indices
  * to maintain round-robin fairness between ticks.
  */
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Splitter {
     input_rr_index: usize,
     output_rr_index: usize,
+    mode: SplitterMode,
 }
 
 impl Splitter {
@@ -17,19 +81,108 @@ impl Splitter {
         Self {
             input_rr_index: 0,
             output_rr_index: 0,
+            mode: SplitterMode::RoundRobin,
+        }
+    }
+
+    /// Creates a splitter whose round robin rotation starts at the given input and output, e.g. picked from a
+    /// seed so many splitters do not all favour their first outputs after a restart. The indices are wrapped
+    /// to the number of belts on each run, so any value is valid.
+    pub fn new_with_start(input_start: usize, output_start: usize) -> Self {
+        Self {
+            input_rr_index: input_start,
+            output_rr_index: output_start,
+            mode: SplitterMode::RoundRobin,
         }
     }
 
+    pub fn mode(&self) -> SplitterMode {
+        self.mode
+    }
+
+    /// Chooses how round robin outputs are picked. Defaults to `SplitterMode::RoundRobin`.
+    pub fn set_mode(&mut self, mode: SplitterMode) {
+        self.mode = mode;
+    }
+
+    /// Returns the rr input the next tick starts its round robin pass at.
+    pub fn input_rr_index(&self) -> usize {
+        self.input_rr_index
+    }
+
+    /// Returns the rr output that receives the next round robin item.
+    pub fn output_rr_index(&self) -> usize {
+        self.output_rr_index
+    }
+
     /// Runs a single tick of the splitter. Only the belt ends are touched: items are read from
     /// the head of input belts and appended to the tail of output belts.
     /// Priority inputs feed priority outputs first, then RR outputs. Round-robin inputs fill
     /// any remaining priority outputs before participating in RR distribution.
+    /// Returns how many stacks were moved, so callers can skip splitters that are idle or jammed.
     pub fn run(
         &mut self,
         priority_inputs: &mut [&mut Belt],
         rr_inputs: &mut [&mut Belt],
         priority_outputs: &mut [&mut Belt],
         rr_outputs: &mut [&mut Belt],
+    ) -> SplitterStats {
+        let mut record = Recorder::default();
+        self.run_recorded(
+            priority_inputs,
+            rr_inputs,
+            priority_outputs,
+            rr_outputs,
+            &mut record,
+        );
+        record.stats
+    }
+
+    /// Works out how `run` would distribute the current belt contents without changing any belt
+    /// or the round robin state. The plan matches what a `run` with the same belts does next.
+    pub fn plan(
+        &self,
+        priority_inputs: &[&Belt],
+        rr_inputs: &[&Belt],
+        priority_outputs: &[&Belt],
+        rr_outputs: &[&Belt],
+    ) -> SplitPlan {
+        fn copies(belts: &[&Belt]) -> Vec<Belt> {
+            belts.iter().map(|belt| (*belt).clone()).collect()
+        }
+        fn refs(belts: &mut [Belt]) -> Vec<&mut Belt> {
+            belts.iter_mut().collect()
+        }
+
+        let mut priority_inputs = copies(priority_inputs);
+        let mut rr_inputs = copies(rr_inputs);
+        let mut priority_outputs = copies(priority_outputs);
+        let mut rr_outputs = copies(rr_outputs);
+
+        let mut record = Recorder {
+            stats: SplitterStats::default(),
+            plan: Some(SplitPlan {
+                priority_outputs: vec![Vec::new(); priority_outputs.len()],
+                rr_outputs: vec![Vec::new(); rr_outputs.len()],
+            }),
+        };
+        self.clone().run_recorded(
+            &mut refs(&mut priority_inputs),
+            &mut refs(&mut rr_inputs),
+            &mut refs(&mut priority_outputs),
+            &mut refs(&mut rr_outputs),
+            &mut record,
+        );
+        record.plan.unwrap_or_default()
+    }
+
+    fn run_recorded(
+        &mut self,
+        priority_inputs: &mut [&mut Belt],
+        rr_inputs: &mut [&mut Belt],
+        priority_outputs: &mut [&mut Belt],
+        rr_outputs: &mut [&mut Belt],
+        record: &mut Recorder,
     ) {
         if rr_inputs.is_empty() {
             self.input_rr_index = 0;
@@ -43,9 +196,10 @@ impl Splitter {
             self.output_rr_index %= rr_outputs.len();
         }
 
-        self.drain_priority_inputs(priority_inputs, priority_outputs, rr_outputs);
-        self.drain_rr_inputs_to_priority(rr_inputs, priority_outputs);
-        self.drain_rr_inputs_to_rr(rr_inputs, rr_outputs);
+        self.drain_priority_inputs(priority_inputs, priority_outputs, rr_outputs, record);
+        self.drain_rr_inputs_to_priority(rr_inputs, priority_outputs, record);
+        self.drain_rr_inputs_to_rr(rr_inputs, rr_outputs, record);
+        record.stats.moved = record.stats.priority_moved + record.stats.rr_moved;
     }
 
     fn drain_priority_inputs(
@@ -53,20 +207,20 @@ impl Splitter {
         priority_inputs: &mut [&mut Belt],
         priority_outputs: &mut [&mut Belt],
         rr_outputs: &mut [&mut Belt],
+        record: &mut Recorder,
     ) {
-        for input in priority_inputs.iter_mut() {
+        for (idx, input) in priority_inputs.iter_mut().enumerate() {
             let belt = &mut **input;
-            loop {
-                let Some((stack, _)) = belt.peek_front_stack() else {
-                    break;
-                };
-
-                if !self.try_assign_full(&stack, priority_outputs, rr_outputs) {
+            let source = SplitSource::Priority(idx);
+            while let Some(run) = Self::peek_head_run(belt) {
+                let placed =
+                    self.try_assign_full(source, &run, priority_outputs, rr_outputs, record);
+                if placed == 0 {
                     break;
                 }
 
-                let removed = belt.remove_item();
-                debug_assert!(removed.is_some());
+                let removed = belt.remove_front_run(placed);
+                debug_assert_eq!(removed.map(|stack| stack.multiplicity), Some(placed));
             }
         }
     }
@@ -75,6 +229,7 @@ impl Splitter {
         &mut self,
         rr_inputs: &mut [&mut Belt],
         priority_outputs: &mut [&mut Belt],
+        record: &mut Recorder,
     ) {
         if priority_outputs.is_empty() {
             return;
@@ -83,26 +238,29 @@ impl Splitter {
         let mut progress = true;
         while progress {
             progress = false;
-            for input in rr_inputs.iter_mut() {
+            for (idx, input) in rr_inputs.iter_mut().enumerate() {
                 let belt = &mut **input;
-                loop {
-                    let Some((stack, _)) = belt.peek_front_stack() else {
-                        break;
-                    };
-
-                    if !Self::try_assign_priority(&stack, priority_outputs) {
+                let source = SplitSource::RoundRobin(idx);
+                while let Some(run) = Self::peek_head_run(belt) {
+                    let placed = Self::try_assign_priority(source, &run, priority_outputs, record);
+                    if placed == 0 {
                         break;
                     }
 
-                    let removed = belt.remove_item();
-                    debug_assert!(removed.is_some());
+                    let removed = belt.remove_front_run(placed);
+                    debug_assert_eq!(removed.map(|stack| stack.multiplicity), Some(placed));
                     progress = true;
                 }
             }
         }
     }
 
-    fn drain_rr_inputs_to_rr(&mut self, rr_inputs: &mut [&mut Belt], rr_outputs: &mut [&mut Belt]) {
+    fn drain_rr_inputs_to_rr(
+        &mut self,
+        rr_inputs: &mut [&mut Belt],
+        rr_outputs: &mut [&mut Belt],
+        record: &mut Recorder,
+    ) {
         let input_len = rr_inputs.len();
         if input_len == 0 || rr_outputs.is_empty() {
             return;
@@ -123,12 +281,14 @@ impl Splitter {
                     .expect("index must be within rr_inputs bounds");
                 let belt = &mut **belt_slot;
 
-                if let Some((stack, _)) = belt.peek_front_stack()
-                    && self.try_assign_rr(&stack, rr_outputs)
-                {
-                    let removed = belt.remove_item();
-                    debug_assert!(removed.is_some());
-                    progress = true;
+                if let Some(run) = Self::peek_head_run(belt) {
+                    let source = SplitSource::RoundRobin(idx);
+                    let placed = self.try_assign_rr(source, &run, rr_outputs, record);
+                    if placed > 0 {
+                        let removed = belt.remove_front_run(placed);
+                        debug_assert_eq!(removed.map(|stack| stack.multiplicity), Some(placed));
+                        progress = true;
+                    }
                 }
 
                 self.input_rr_index = (self.input_rr_index + 1) % input_len;
@@ -136,61 +296,144 @@ impl Splitter {
         }
     }
 
+    // Returns the stack at the head of `belt` with the number of identical copies there as its
+    // multiplicity, so the whole run can be handed out in one pass.
+    fn peek_head_run(belt: &Belt) -> Option<Stack> {
+        let (mut stack, copies) = belt.peek_front_stack()?;
+        stack.multiplicity = copies;
+        Some(stack)
+    }
+
+    /// Places as many copies of `run` as possible, on priority outputs first and then round robin.
+    /// Returns how many copies were placed.
     fn try_assign_full(
         &mut self,
-        stack: &Stack,
+        source: SplitSource,
+        run: &Stack,
         priority_outputs: &mut [&mut Belt],
         rr_outputs: &mut [&mut Belt],
-    ) -> bool {
-        if Self::try_assign_priority(stack, priority_outputs) {
-            return true;
+        record: &mut Recorder,
+    ) -> u32 {
+        let placed = Self::try_assign_priority(source, run, priority_outputs, record);
+        if placed == run.multiplicity {
+            return placed;
         }
 
-        self.try_assign_rr(stack, rr_outputs)
+        let mut remaining = run.clone();
+        remaining.multiplicity -= placed;
+        placed + self.try_assign_rr(source, &remaining, rr_outputs, record)
     }
 
-    fn try_assign_priority(stack: &Stack, priority_outputs: &mut [&mut Belt]) -> bool {
-        let item_type = stack.item_type;
-        let item_count = stack.item_count;
-
-        for output in priority_outputs.iter_mut() {
-            if output.add_item(Stack::new(item_type, item_count)) {
-                return true;
+    /// Fills the priority outputs in order with copies of `run`. Returns how many copies were placed.
+    fn try_assign_priority(
+        source: SplitSource,
+        run: &Stack,
+        priority_outputs: &mut [&mut Belt],
+        record: &mut Recorder,
+    ) -> u32 {
+        let mut remaining = run.clone();
+        for (idx, output) in priority_outputs.iter_mut().enumerate() {
+            let before = remaining.multiplicity;
+            let left = output
+                .add_item_saturating(remaining.clone())
+                .map_or(0, |stack| stack.multiplicity);
+            if left < before {
+                let mut placed = remaining.clone();
+                placed.multiplicity = before - left;
+                record.priority(source, idx, placed);
+            }
+            remaining.multiplicity = left;
+            if left == 0 {
+                break;
             }
         }
 
-        false
+        run.multiplicity - remaining.multiplicity
     }
 
-    fn try_assign_rr(&mut self, stack: &Stack, rr_outputs: &mut [&mut Belt]) -> bool {
+    /// Deals copies of `run` to the round robin outputs one at a time, advancing the round robin
+    /// index per copy, and places each output's share as one contiguous run. In `LeastFull` mode
+    /// each copy goes to the output with the most room left instead. Returns how many copies
+    /// were placed.
+    fn try_assign_rr(
+        &mut self,
+        source: SplitSource,
+        run: &Stack,
+        rr_outputs: &mut [&mut Belt],
+        record: &mut Recorder,
+    ) -> u32 {
         let len = rr_outputs.len();
         if len == 0 {
-            return false;
+            return 0;
         }
 
         if self.output_rr_index >= len {
             self.output_rr_index %= len;
         }
 
-        let item_type = stack.item_type;
-        let item_count = stack.item_count;
+        let item_type = run.item_type;
+        let single = Stack::new(item_type, run.item_count);
+
+        // Item types named by an output's filter are routed only to those outputs; every other
+        // type is shared between the outputs whose filters let it through.
+        let filter_names_type = |belt: &Belt| {
+            belt.item_filter()
+                .is_some_and(|filter| filter.contains(&item_type))
+        };
+        let routed = rr_outputs.iter().any(|belt| filter_names_type(belt));
+
+        let mut room: Vec<u32> = rr_outputs
+            .iter()
+            .map(|belt| {
+                let eligible = !routed || filter_names_type(belt);
+                if eligible && belt.can_add(&single) {
+                    belt.free_slots()
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let mut shares = vec![0u32; len];
+
+        let mut placed = 0;
+        while placed < run.multiplicity {
+            let rotation = (0..len).map(|offset| (self.output_rr_index + offset) % len);
+            let pick = match self.mode {
+                SplitterMode::RoundRobin => rotation.clone().find(|&idx| room[idx] > 0),
+                // `max_by_key` keeps the last maximum, so walk the rotation backwards.
+                SplitterMode::LeastFull => rotation
+                    .rev()
+                    .filter(|&idx| room[idx] > 0)
+                    .max_by_key(|&idx| room[idx]),
+            };
+            let Some(idx) = pick else {
+                break;
+            };
+
+            room[idx] -= 1;
+            shares[idx] += 1;
+            placed += 1;
+            self.output_rr_index = (idx + 1) % len;
+        }
 
-        for offset in 0..len {
-            let idx = (self.output_rr_index + offset) % len;
-            if rr_outputs[idx].add_item(Stack::new(item_type, item_count)) {
-                self.output_rr_index = (idx + 1) % len;
-                return true;
+        for (idx, (output, share)) in rr_outputs.iter_mut().zip(shares).enumerate() {
+            if share > 0 {
+                let mut stack = single.clone();
+                stack.multiplicity = share;
+                let added = output.add_stack_run(stack.clone());
+                debug_assert_eq!(added, share);
+                record.rr(source, idx, stack);
             }
         }
 
-        false
+        placed
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::ITEM_WIDTH;
+    use crate::types::{ITEM_WIDTH, ItemType};
 
     fn stack(item_type: u16, item_count: u16) -> Stack {
         Stack::new(item_type, item_count)
@@ -326,6 +569,80 @@ mod tests {
         assert_eq!(rr_output_b.item_count(), 1);
     }
 
+    #[test]
+    fn output_rr_index_wraps_after_full_rotation() {
+        let mut splitter = Splitter::new();
+        let mut input = Belt::new(ITEM_WIDTH, 1);
+        let mut outputs: Vec<Belt> = (0..3).map(|_| Belt::new(ITEM_WIDTH, 1)).collect();
+
+        for expected in [1, 2, 0] {
+            assert!(input.add_item(stack(4, 1)));
+            let mut rr_inputs = vec![&mut input];
+            let mut rr_outputs: Vec<&mut Belt> = outputs.iter_mut().collect();
+            let stats = splitter.run(
+                &mut [],
+                rr_inputs.as_mut_slice(),
+                &mut [],
+                rr_outputs.as_mut_slice(),
+            );
+            assert_eq!(stats.rr_moved, 1);
+            assert_eq!(splitter.output_rr_index(), expected);
+            assert_eq!(splitter.input_rr_index(), 0);
+        }
+        assert!(outputs.iter().all(|output| output.item_count() == 1));
+    }
+
+    #[test]
+    fn least_full_mode_routes_to_the_emptier_output() {
+        let run = |mode: SplitterMode| {
+            let mut splitter = Splitter::new();
+            splitter.set_mode(mode);
+            let copies = |multiplicity: u32| Stack {
+                multiplicity,
+                ..stack(9, 1)
+            };
+            let mut input = Belt::from_layout(ITEM_WIDTH * 2, 1, &[(copies(2), 0)]).unwrap();
+            let mut fuller = Belt::from_layout(ITEM_WIDTH * 6, 1, &[(copies(3), 0)]).unwrap();
+            let mut emptier = Belt::from_layout(ITEM_WIDTH * 6, 1, &[(copies(1), 0)]).unwrap();
+
+            let mut rr_inputs = vec![&mut input];
+            let mut rr_outputs = vec![&mut fuller, &mut emptier];
+            let stats = splitter.run(
+                &mut [],
+                rr_inputs.as_mut_slice(),
+                &mut [],
+                rr_outputs.as_mut_slice(),
+            );
+            assert_eq!(stats.rr_moved, 2);
+            (fuller.item_count(), emptier.item_count())
+        };
+
+        assert_eq!(run(SplitterMode::RoundRobin), (4, 2));
+        assert_eq!(run(SplitterMode::LeastFull), (3, 3));
+    }
+
+    #[test]
+    fn start_index_picks_the_first_rr_output() {
+        for (output_start, receiving) in [(0, 0), (1, 1), (5, 1)] {
+            let mut splitter = Splitter::new_with_start(0, output_start);
+            let mut input = Belt::new(ITEM_WIDTH, 1);
+            let mut outputs: Vec<Belt> = (0..2).map(|_| Belt::new(ITEM_WIDTH, 1)).collect();
+            assert!(input.add_item(stack(4, 1)));
+
+            let mut rr_inputs = vec![&mut input];
+            let mut rr_outputs: Vec<&mut Belt> = outputs.iter_mut().collect();
+            splitter.run(
+                &mut [],
+                rr_inputs.as_mut_slice(),
+                &mut [],
+                rr_outputs.as_mut_slice(),
+            );
+
+            assert_eq!(outputs[receiving].item_count(), 1);
+            assert_eq!(outputs[1 - receiving].item_count(), 0);
+        }
+    }
+
     #[test]
     fn input_stalls_when_outputs_full() {
         let mut splitter = Splitter::new();
@@ -355,4 +672,224 @@ mod tests {
         assert_eq!(input.item_count(), 1);
         assert_eq!(output.item_count(), 1);
     }
+
+    fn run_rr(
+        splitter: &mut Splitter,
+        input: &mut Belt,
+        outputs: &mut [&mut Belt],
+    ) -> SplitterStats {
+        let mut priority_inputs: Vec<&mut Belt> = Vec::new();
+        let mut rr_inputs = vec![input];
+        let mut priority_outputs: Vec<&mut Belt> = Vec::new();
+
+        splitter.run(
+            priority_inputs.as_mut_slice(),
+            rr_inputs.as_mut_slice(),
+            priority_outputs.as_mut_slice(),
+            outputs,
+        )
+    }
+
+    #[test]
+    fn filtered_output_receives_only_its_type() {
+        let mut splitter = Splitter::new();
+        let mut input = Belt::new(ITEM_WIDTH, ITEM_WIDTH);
+        let mut filtered = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH);
+        let mut open_a = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH);
+        let mut open_b = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH);
+        filtered.set_item_filter(Some(vec![ItemType::new(5)]));
+
+        for item_type in [1, 5, 2, 5, 3] {
+            assert!(input.add_item(stack(item_type, 1)));
+
+            let mut rr_outputs = vec![&mut filtered, &mut open_a, &mut open_b];
+            run_rr(&mut splitter, &mut input, rr_outputs.as_mut_slice());
+            drop(rr_outputs);
+
+            assert!(input.is_empty());
+            filtered.run(1);
+            open_a.run(1);
+            open_b.run(1);
+        }
+
+        assert_eq!(filtered.count_of(ItemType::new(5)), 2);
+        assert_eq!(filtered.item_count(), 2);
+        assert_eq!(
+            open_a.count_of(ItemType::new(5)) + open_b.count_of(ItemType::new(5)),
+            0
+        );
+        assert_eq!(open_a.item_count() + open_b.item_count(), 3);
+    }
+
+    #[test]
+    fn filtered_type_waits_for_its_output() {
+        let mut splitter = Splitter::new();
+        let mut input = Belt::new(ITEM_WIDTH, 1);
+        let mut filtered = Belt::new(ITEM_WIDTH, 1);
+        let mut open = Belt::new(ITEM_WIDTH * 2, 1);
+        filtered.set_item_filter(Some(vec![ItemType::new(5)]));
+        assert!(filtered.add_item(stack(5, 1)));
+        assert!(input.add_item(stack(5, 1)));
+
+        let mut rr_outputs = vec![&mut filtered, &mut open];
+        run_rr(&mut splitter, &mut input, rr_outputs.as_mut_slice());
+        drop(rr_outputs);
+
+        assert_eq!(input.item_count(), 1);
+        assert!(open.is_empty());
+    }
+
+    #[test]
+    fn run_reports_moved_stacks() {
+        let mut splitter = Splitter::new();
+        let mut priority_input = Belt::new(ITEM_WIDTH, 1);
+        let mut rr_input = Belt::new(ITEM_WIDTH, 1);
+        let mut priority_output = Belt::new(ITEM_WIDTH, 1);
+        let mut rr_output = Belt::new(ITEM_WIDTH, 1);
+        let mut stalled_input = Belt::new(ITEM_WIDTH, 1);
+
+        assert!(priority_input.add_item(stack(1, 1)));
+        assert!(rr_input.add_item(stack(2, 1)));
+        assert!(stalled_input.add_item(stack(3, 1)));
+
+        let mut priority_inputs = vec![&mut priority_input];
+        let mut rr_inputs = vec![&mut rr_input, &mut stalled_input];
+        let mut priority_outputs = vec![&mut priority_output];
+        let mut rr_outputs = vec![&mut rr_output];
+
+        let stats = splitter.run(
+            priority_inputs.as_mut_slice(),
+            rr_inputs.as_mut_slice(),
+            priority_outputs.as_mut_slice(),
+            rr_outputs.as_mut_slice(),
+        );
+
+        drop(priority_inputs);
+        drop(rr_inputs);
+        drop(priority_outputs);
+        drop(rr_outputs);
+
+        assert_eq!(
+            stats,
+            SplitterStats {
+                moved: 2,
+                priority_moved: 1,
+                rr_moved: 1,
+            }
+        );
+        let drained = 3
+            - (priority_input.item_count() + rr_input.item_count() + stalled_input.item_count())
+                as u32;
+        assert_eq!(stats.moved, drained);
+
+        let mut priority_inputs: Vec<&mut Belt> = Vec::new();
+        let mut rr_inputs = vec![&mut stalled_input];
+        let mut priority_outputs = vec![&mut priority_output];
+        let mut rr_outputs = vec![&mut rr_output];
+        let stats = splitter.run(
+            priority_inputs.as_mut_slice(),
+            rr_inputs.as_mut_slice(),
+            priority_outputs.as_mut_slice(),
+            rr_outputs.as_mut_slice(),
+        );
+        assert_eq!(stats, SplitterStats::default());
+    }
+
+    #[test]
+    fn multiplied_head_splits_across_rr_outputs_in_one_tick() {
+        let mut splitter = Splitter::new();
+        let mut input = Belt::new(ITEM_WIDTH * 3, ITEM_WIDTH);
+        let mut run = stack(4, 2);
+        run.multiplicity = 3;
+        assert_eq!(input.add_stack_run(run), 3);
+        input.run(3);
+        assert_eq!(input.peek_front_stack(), Some((stack(4, 2), 3)));
+
+        let mut output_a = Belt::new(ITEM_WIDTH * 3, ITEM_WIDTH);
+        let mut output_b = Belt::new(ITEM_WIDTH * 3, ITEM_WIDTH);
+        let stats = run_rr(
+            &mut splitter,
+            &mut input,
+            &mut [&mut output_a, &mut output_b],
+        );
+
+        assert_eq!(stats.rr_moved, 3);
+        assert!(input.is_empty());
+        assert_eq!(output_a.count_of(ItemType::new(4)), 2);
+        assert_eq!(output_b.count_of(ItemType::new(4)), 1);
+    }
+
+    #[test]
+    fn plan_matches_run() {
+        fn loaded(stacks: &[Stack]) -> Belt {
+            let mut belt = Belt::new(ITEM_WIDTH * 4, ITEM_WIDTH);
+            for stack in stacks {
+                assert!(belt.add_item_saturating(stack.clone()).is_none());
+            }
+            belt.run(4);
+            belt
+        }
+
+        let mut splitter = Splitter::new();
+        let mut priority_input = loaded(&[stack(1, 1)]);
+        let mut rr_a = loaded(&[Stack {
+            multiplicity: 2,
+            ..stack(2, 1)
+        }]);
+        let mut rr_b = loaded(&[stack(3, 1)]);
+        let mut priority_output = Belt::new(ITEM_WIDTH, ITEM_WIDTH);
+        let mut out_a = Belt::new(ITEM_WIDTH * 2, ITEM_WIDTH);
+        let mut out_b = Belt::new(ITEM_WIDTH * 2, ITEM_WIDTH);
+        let input_layout = rr_a.layout_snapshot();
+
+        let plan = splitter.plan(
+            &[&priority_input],
+            &[&rr_a, &rr_b],
+            &[&priority_output],
+            &[&out_a, &out_b],
+        );
+        assert_eq!(rr_a.layout_snapshot(), input_layout);
+        assert!(out_a.is_empty() && priority_output.is_empty());
+        assert_eq!(
+            plan.priority_outputs,
+            vec![vec![(SplitSource::Priority(0), stack(1, 1))]]
+        );
+
+        let mut priority_inputs = vec![&mut priority_input];
+        let mut rr_inputs = vec![&mut rr_a, &mut rr_b];
+        let mut priority_outputs = vec![&mut priority_output];
+        let mut rr_outputs = vec![&mut out_a, &mut out_b];
+        let stats = splitter.run(
+            priority_inputs.as_mut_slice(),
+            rr_inputs.as_mut_slice(),
+            priority_outputs.as_mut_slice(),
+            rr_outputs.as_mut_slice(),
+        );
+        drop(priority_inputs);
+        drop(rr_inputs);
+        drop(priority_outputs);
+        drop(rr_outputs);
+
+        let planned: u32 = plan
+            .priority_outputs
+            .iter()
+            .chain(plan.rr_outputs.iter())
+            .flatten()
+            .map(|(_, stack)| stack.multiplicity)
+            .sum();
+        assert_eq!(stats.moved, planned);
+
+        let outputs = [&priority_output, &out_a, &out_b];
+        let plans = plan.priority_outputs.iter().chain(plan.rr_outputs.iter());
+        for (output, received) in outputs.into_iter().zip(plans) {
+            for (_, stack) in received {
+                assert_eq!(
+                    output.count_of(stack.item_type),
+                    stack.multiplicity as usize
+                );
+            }
+            let total: u32 = received.iter().map(|(_, stack)| stack.multiplicity).sum();
+            assert_eq!(output.item_count(), total as usize);
+        }
+    }
 }
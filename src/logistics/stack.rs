@@ -1,7 +1,11 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
 use crate::types::ItemType;
 
 /// Represents a stack of homogeneous items traveling through factory logistics.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stack {
     /// Item identifier representing the type in this stack.
     pub item_type: ItemType,
@@ -13,20 +17,91 @@ pub struct Stack {
 
 impl Stack {
     /// Creates a new stack for the given item type with the provided quantity.
-    pub fn new(item_type: ItemType, item_count: u16) -> Self {
+    /// A zero `item_count` is accepted and yields an empty stack; prefer [`Stack::try_new`]
+    /// when the count comes from untrusted arithmetic.
+    pub fn new(item_type: impl Into<ItemType>, item_count: u16) -> Self {
         Self {
-            item_type,
+            item_type: item_type.into(),
             item_count,
             multiplicity: 1,
         }
     }
 
+    /// Creates a new stack, or returns `None` if `item_count` is zero.
+    pub fn try_new(item_type: impl Into<ItemType>, item_count: u16) -> Option<Self> {
+        (item_count > 0).then(|| Self::new(item_type, item_count))
+    }
+
     /// Returns `true` if the stack holds no items.
     pub fn is_empty(&self) -> bool {
         self.item_count == 0
     }
+
+    /// Returns `true` if both stacks hold the same item type, regardless of count.
+    pub fn same_type(&self, other: &Stack) -> bool {
+        self.item_type == other.item_type
+    }
+
+    /// Returns `true` if both stacks match in item type, item count, and multiplicity.
+    pub fn identical(&self, other: &Stack) -> bool {
+        self == other && self.multiplicity == other.multiplicity
+    }
+
+    /// Splits `count` items off into a new stack, leaving the rest in `self`.
+    /// Returns `None` unless `0 < count < self.item_count`, so both halves are non-empty.
+    pub fn split(&mut self, count: u16) -> Option<Stack> {
+        if count == 0 || count >= self.item_count {
+            return None;
+        }
+
+        Some(self.split_off(count))
+    }
+
+    /// Splits up to `count` items off into a new stack, clamping to the items available.
+    /// Splitting off the whole stack leaves `self` empty.
+    pub fn split_off(&mut self, count: u16) -> Stack {
+        let taken = count.min(self.item_count);
+        self.item_count -= taken;
+        Stack::new(self.item_type, taken)
+    }
+
+    /// Merges the items of `other` into this stack if both hold the same item type.
+    /// The count saturates at `u16::MAX`; any items that do not fit are returned as a leftover
+    /// stack. Stacks of a different type are handed back unchanged.
+    pub fn merge(&mut self, other: Stack) -> Option<Stack> {
+        if !self.same_type(&other) {
+            return Some(other);
+        }
+
+        let total = self.item_count as u32 + other.item_count as u32;
+        self.item_count = total.min(u16::MAX as u32) as u16;
+        let overflow = total - self.item_count as u32;
+        if overflow == 0 {
+            None
+        } else {
+            Some(Stack::new(other.item_type, overflow as u16))
+        }
+    }
+
+    /// Expands this entry into `multiplicity` separate stacks of multiplicity 1.
+    pub fn explode(self) -> Vec<Stack> {
+        let mut out = Vec::with_capacity(self.multiplicity as usize);
+        self.explode_into(&mut out);
+        out
+    }
+
+    /// Like [`Stack::explode`], but appends the unit stacks to `out` so its allocation can be
+    /// reused.
+    pub fn explode_into(&self, out: &mut Vec<Stack>) {
+        let unit = Stack::new(self.item_type, self.item_count);
+        out.extend(std::iter::repeat_n(unit, self.multiplicity as usize));
+    }
 }
 
+/// Two stacks are equal when they hold the same item type and item count; `multiplicity` is
+/// ignored. Belts rely on this to fold adjacent equal stacks into a single multiplied entry, so
+/// stacks of the same type but different counts must stay distinct. Use [`Stack::same_type`] for
+/// a type-only comparison and [`Stack::identical`] to include multiplicity.
 impl PartialEq for Stack {
     fn eq(&self, other: &Self) -> bool {
         self.item_type == other.item_type && self.item_count == other.item_count
@@ -34,3 +109,149 @@ impl PartialEq for Stack {
 }
 
 impl Eq for Stack {}
+
+/// Hashes the same fields `==` compares, so equal stacks always hash alike.
+impl Hash for Stack {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.item_type.hash(state);
+        self.item_count.hash(state);
+    }
+}
+
+/// Orders stacks by `(item_type, item_count)`, consistent with `==`.
+impl Ord for Stack {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.item_type, self.item_count).cmp(&(other.item_type, other.item_count))
+    }
+}
+
+impl PartialOrd for Stack {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_new_rejects_zero_count() {
+        assert_eq!(Stack::try_new(1, 0), None);
+        assert_eq!(Stack::try_new(1, 5), Some(Stack::new(1, 5)));
+    }
+
+    #[test]
+    fn equality_variants() {
+        let mut tripled = Stack::new(1, 5);
+        tripled.multiplicity = 3;
+        let single = Stack::new(1, 5);
+        let other_count = Stack::new(1, 6);
+
+        assert_eq!(tripled, single);
+        assert!(!tripled.identical(&single));
+        assert!(single.identical(&Stack::new(1, 5)));
+        assert_ne!(single, other_count);
+        assert!(single.same_type(&other_count));
+        assert!(!single.same_type(&Stack::new(2, 5)));
+    }
+
+    #[test]
+    fn hash_set_dedups_equal_stacks() {
+        use std::collections::HashSet;
+
+        let mut tripled = Stack::new(1, 5);
+        tripled.multiplicity = 3;
+        let set: HashSet<Stack> = [Stack::new(1, 5), tripled, Stack::new(1, 6)]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn ordering_by_type_then_count() {
+        let mut stacks = vec![Stack::new(2, 1), Stack::new(1, 9), Stack::new(1, 3)];
+        stacks.sort();
+        assert_eq!(
+            stacks,
+            vec![Stack::new(1, 3), Stack::new(1, 9), Stack::new(2, 1)]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_keeps_multiplicity() {
+        let mut tripled = Stack::new(4, 7);
+        tripled.multiplicity = 3;
+
+        let json = serde_json::to_string(&tripled).expect("stack serializes");
+        let restored: Stack = serde_json::from_str(&json).expect("stack deserializes");
+        assert!(restored.identical(&tripled));
+    }
+
+    #[test]
+    fn split_keeps_both_halves_non_empty() {
+        let mut stack = Stack::new(1, 10);
+        assert_eq!(stack.split(4), Some(Stack::new(1, 4)));
+        assert_eq!(stack.item_count, 6);
+        assert_eq!(stack.split(6), None);
+        assert_eq!(stack.split(0), None);
+        assert_eq!(stack.item_count, 6);
+    }
+
+    #[test]
+    fn split_off_takes_whole_stack() {
+        let mut stack = Stack::new(1, 10);
+        assert_eq!(stack.split_off(10), Stack::new(1, 10));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn split_off_clamps_to_available() {
+        let mut stack = Stack::new(2, 3);
+        assert_eq!(stack.split_off(8), Stack::new(2, 3));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn merge_same_type_adds_counts() {
+        let mut stack = Stack::new(1, 10);
+        assert_eq!(stack.merge(Stack::new(1, 5)), None);
+        assert_eq!(stack.item_count, 15);
+    }
+
+    #[test]
+    fn merge_returns_overflow() {
+        let mut stack = Stack::new(1, u16::MAX - 3);
+        let leftover = stack.merge(Stack::new(1, 10)).expect("overflow expected");
+        assert_eq!(stack.item_count, u16::MAX);
+        assert_eq!(leftover, Stack::new(1, 7));
+    }
+
+    #[test]
+    fn explode_yields_unit_stacks() {
+        let mut tripled = Stack::new(1, 5);
+        tripled.multiplicity = 3;
+
+        let mut out = vec![Stack::new(2, 1)];
+        tripled.explode_into(&mut out);
+        assert_eq!(out.len(), 4);
+        assert!(
+            out[1..]
+                .iter()
+                .all(|stack| stack.identical(&Stack::new(1, 5)))
+        );
+
+        let exploded = tripled.explode();
+        assert_eq!(exploded, vec![Stack::new(1, 5); 3]);
+        assert!(exploded.iter().all(|stack| stack.multiplicity == 1));
+    }
+
+    #[test]
+    fn merge_rejects_other_type() {
+        let mut stack = Stack::new(1, 4);
+        let other = Stack::new(2, 6);
+        assert_eq!(stack.merge(other.clone()), Some(other));
+        assert_eq!(stack.item_count, 4);
+    }
+}
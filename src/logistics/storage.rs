@@ -0,0 +1,262 @@
+use crate::logistics::{BeltInputConnection, BeltOutputConnection, Connection, Stack};
+use crate::types::ItemType;
+
+/**
+ * A storage container with a fixed number of slots. Each slot holds one stack of up to
+ * `slot_capacity` items and may be restricted to a single item type. Items are inserted into
+ * partially filled slots of the same type first, then into empty slots in order, and extracted
+ * from the last matching slot backwards so the front slots stay full.
+ */
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Storage {
+    slots: Vec<Option<Stack>>,
+    // Per-slot item type restriction; `None` accepts any type.
+    slot_filters: Vec<Option<ItemType>>,
+    slot_capacity: u16,
+}
+
+impl Storage {
+    pub fn new(slot_count: usize, slot_capacity: u16) -> Self {
+        Self {
+            slots: vec![None; slot_count],
+            slot_filters: vec![None; slot_count],
+            slot_capacity,
+        }
+    }
+
+    pub fn slot_capacity(&self) -> u16 {
+        self.slot_capacity
+    }
+
+    /// Returns the slots in order; empty slots are `None`.
+    pub fn contents(&self) -> &[Option<Stack>] {
+        &self.slots
+    }
+
+    /// Restricts the slot at `index` to `item_type`, or lifts the restriction with `None`.
+    /// Items already in the slot stay there.
+    pub fn set_slot_filter(&mut self, index: usize, item_type: Option<ItemType>) {
+        self.slot_filters[index] = item_type;
+    }
+
+    pub fn slot_filter(&self, index: usize) -> Option<ItemType> {
+        self.slot_filters[index]
+    }
+
+    /// Returns how many items of `item_type` are stored across all slots.
+    pub fn count_of(&self, item_type: ItemType) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|stack| stack.item_type == item_type)
+            .map(|stack| stack.item_count as u32)
+            .sum()
+    }
+
+    /// Returns how many more items of `item_type` would fit.
+    pub fn free_capacity_for(&self, item_type: ItemType) -> u32 {
+        (0..self.slots.len())
+            .filter(|&index| self.slot_accepts(index, item_type))
+            .map(|index| {
+                let used = self.slots[index]
+                    .as_ref()
+                    .map_or(0, |stack| stack.item_count);
+                (self.slot_capacity - used) as u32
+            })
+            .sum()
+    }
+
+    /// Inserts `stack` and returns whatever did not fit, or `None` if everything was stored.
+    /// The stack must have a multiplicity of 1.
+    pub fn insert(&mut self, stack: Stack) -> Option<Stack> {
+        debug_assert_eq!(stack.multiplicity, 1);
+
+        let item_type = stack.item_type;
+        let mut remaining = stack.item_count;
+
+        // Top up slots already holding this type before opening empty ones.
+        for index in 0..self.slots.len() {
+            if remaining == 0 {
+                break;
+            }
+            if !self.slot_accepts(index, item_type) {
+                continue;
+            }
+            if let Some(slot) = self.slots[index].as_mut() {
+                let added = remaining.min(self.slot_capacity - slot.item_count);
+                slot.item_count += added;
+                remaining -= added;
+            }
+        }
+
+        for index in 0..self.slots.len() {
+            if remaining == 0 {
+                break;
+            }
+            if self.slots[index].is_none() && self.slot_accepts(index, item_type) {
+                let added = remaining.min(self.slot_capacity);
+                self.slots[index] = Stack::try_new(item_type, added);
+                remaining -= added;
+            }
+        }
+
+        Stack::try_new(item_type, remaining)
+    }
+
+    /// Removes up to `count` items of `item_type`, taking from the last matching slot first.
+    /// The returned stack holds fewer items, possibly none, if not enough are stored.
+    pub fn extract(&mut self, item_type: ItemType, count: u16) -> Stack {
+        let mut taken = Stack::new(item_type, 0);
+
+        for slot in self.slots.iter_mut().rev() {
+            if taken.item_count == count {
+                break;
+            }
+            let Some(stack) = slot.as_mut() else {
+                continue;
+            };
+            if stack.item_type != item_type {
+                continue;
+            }
+
+            let part = stack.split_off(count - taken.item_count);
+            taken.item_count += part.item_count;
+            if stack.is_empty() {
+                *slot = None;
+            }
+        }
+
+        taken
+    }
+
+    /// Moves items buffered in `connection`, oldest first, into storage until the buffer is empty
+    /// or storage is full. Returns how many items were moved.
+    pub fn pull_from(&mut self, connection: &mut BeltInputConnection) -> u32 {
+        let mut moved = 0;
+        while let Some(run) = connection.peek_buffer() {
+            let run = Stack::new(run.item_type, run.item_count);
+            let offered = run.item_count;
+            let leftover = self.insert(run).map_or(0, |stack| stack.item_count);
+
+            let stored = offered - leftover;
+            if stored > 0 {
+                let missing = connection.dec_item_count(stored);
+                debug_assert_eq!(missing, 0);
+                moved += stored as u32;
+            }
+            if leftover > 0 {
+                break;
+            }
+        }
+
+        moved
+    }
+
+    /// Fills `connection` from storage with the item types it can take, in slot order. Returns how many
+    /// items were moved.
+    pub fn push_to(&mut self, connection: &mut BeltOutputConnection) -> u32 {
+        let mut moved = 0;
+        for index in 0..self.slots.len() {
+            let Some(item_type) = self.slots[index].as_ref().map(|stack| stack.item_type) else {
+                continue;
+            };
+            if !connection.can_take_item_type(item_type) {
+                continue;
+            }
+
+            let room = connection.max_acceptable_item_count();
+            let taken = self.extract(item_type, room);
+            if taken.is_empty() {
+                continue;
+            }
+
+            let rejected = connection.inc_item_count(item_type, taken.item_count);
+            debug_assert_eq!(rejected, 0);
+            moved += taken.item_count as u32;
+        }
+
+        moved
+    }
+
+    fn slot_accepts(&self, index: usize, item_type: ItemType) -> bool {
+        self.slot_filters[index].is_none_or(|filter| filter == item_type)
+            && self.slots[index]
+                .as_ref()
+                .is_none_or(|stack| stack.item_type == item_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_fills_partial_slots_then_empty_ones() {
+        let mut storage = Storage::new(3, 10);
+        assert_eq!(storage.insert(Stack::new(1, 4)), None);
+        assert_eq!(storage.insert(Stack::new(2, 3)), None);
+        assert_eq!(storage.insert(Stack::new(1, 9)), None);
+
+        assert_eq!(
+            storage.contents(),
+            &[
+                Some(Stack::new(1, 10)),
+                Some(Stack::new(2, 3)),
+                Some(Stack::new(1, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_returns_leftover_when_full() {
+        let mut storage = Storage::new(2, 5);
+        assert_eq!(storage.insert(Stack::new(1, 12)), Some(Stack::new(1, 2)));
+        assert_eq!(storage.count_of(ItemType::new(1)), 10);
+        assert_eq!(storage.insert(Stack::new(2, 1)), Some(Stack::new(2, 1)));
+    }
+
+    #[test]
+    fn slot_filters_restrict_types() {
+        let mut storage = Storage::new(2, 5);
+        storage.set_slot_filter(0, Some(ItemType::new(7)));
+
+        assert_eq!(storage.insert(Stack::new(3, 8)), Some(Stack::new(3, 3)));
+        assert_eq!(storage.contents()[0], None);
+        assert_eq!(storage.free_capacity_for(ItemType::new(7)), 5);
+        assert_eq!(storage.insert(Stack::new(7, 2)), None);
+        assert_eq!(storage.contents()[0], Some(Stack::new(7, 2)));
+    }
+
+    #[test]
+    fn extract_takes_from_last_slot_first() {
+        let mut storage = Storage::new(3, 5);
+        assert_eq!(storage.insert(Stack::new(1, 12)), None);
+
+        assert_eq!(storage.extract(ItemType::new(1), 4), Stack::new(1, 4));
+        assert_eq!(
+            storage.contents(),
+            &[Some(Stack::new(1, 5)), Some(Stack::new(1, 3)), None]
+        );
+
+        assert_eq!(storage.extract(ItemType::new(1), 20), Stack::new(1, 8));
+        assert!(storage.contents().iter().all(Option::is_none));
+        assert!(storage.extract(ItemType::new(1), 1).is_empty());
+    }
+
+    #[test]
+    fn connections_load_and_unload_storage() {
+        let item_type = ItemType::new(4);
+        let mut storage = Storage::new(2, 5);
+        let mut from_belt = BeltInputConnection::new(20, None);
+        let mut to_belt = BeltOutputConnection::new(3, 1, None);
+
+        assert_eq!(from_belt.inc_item_count(item_type, 12), 0);
+        assert_eq!(storage.pull_from(&mut from_belt), 10);
+        assert_eq!(from_belt.buffered_item_count(), 2);
+
+        assert_eq!(storage.push_to(&mut to_belt), 3);
+        assert_eq!(to_belt.buffered_item_count(), 3);
+        assert_eq!(storage.count_of(item_type), 7);
+    }
+}
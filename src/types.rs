@@ -1,7 +1,42 @@
 //! Shared primitive types used across the factory library.
 
-// An item type is represented by a simple integer identifier.
-pub type ItemType = u16;
+/// The raw integer identifier behind an [`ItemType`].
+pub type ItemTypeId = u16;
+
+/// Identifies a kind of item. Wrapping the raw id keeps item types from being mixed up with
+/// item counts and other plain integers.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct ItemType(ItemTypeId);
+
+impl ItemType {
+    /// Creates an item type from its raw identifier.
+    pub const fn new(id: ItemTypeId) -> Self {
+        Self(id)
+    }
+
+    /// Returns the raw identifier of this item type.
+    pub const fn id(self) -> ItemTypeId {
+        self.0
+    }
+}
+
+impl From<ItemTypeId> for ItemType {
+    fn from(id: ItemTypeId) -> Self {
+        Self(id)
+    }
+}
+
+impl From<ItemType> for ItemTypeId {
+    fn from(item_type: ItemType) -> Self {
+        item_type.0
+    }
+}
 
 /*
  * The width of a single item in world units.